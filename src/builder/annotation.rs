@@ -0,0 +1,584 @@
+use super::{add_attribute, BuilderError};
+use crate::{
+    bytecode::{
+        Annotation, AttributeInfo, AttributeInfoKind, ElementValue, ElementValueKind,
+        ElementValuePair, ParameterAnnotation,
+    },
+    constant_pool::ConstantPool,
+    descriptor::Descriptor,
+    name::ClassName,
+};
+
+/// Whether an annotation can be read by reflection at run time, like
+/// `@Retention(RUNTIME)`, or is only kept in the class file, like
+/// `@Retention(CLASS)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retention {
+    Visible,
+    Invisible,
+}
+
+/// The value of an annotation element.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnnotationValue {
+    Byte(i8),
+    Char(u16),
+    Double(f64),
+    Float(f32),
+    Int(i32),
+    Long(i64),
+    Short(i16),
+    Boolean(bool),
+    String(String),
+    /// The constant `name` of the enum class `class`, such as
+    /// `java/lang/annotation/ElementType` and `METHOD`.
+    Enum {
+        class: String,
+        name: String,
+    },
+    /// A class literal such as `String.class` or `void.class`.
+    Class(Descriptor),
+    Annotation(AnnotationBuilder),
+    Array(Vec<AnnotationValue>),
+}
+
+impl From<i8> for AnnotationValue {
+    fn from(value: i8) -> Self {
+        AnnotationValue::Byte(value)
+    }
+}
+
+impl From<f64> for AnnotationValue {
+    fn from(value: f64) -> Self {
+        AnnotationValue::Double(value)
+    }
+}
+
+impl From<f32> for AnnotationValue {
+    fn from(value: f32) -> Self {
+        AnnotationValue::Float(value)
+    }
+}
+
+impl From<i32> for AnnotationValue {
+    fn from(value: i32) -> Self {
+        AnnotationValue::Int(value)
+    }
+}
+
+impl From<i64> for AnnotationValue {
+    fn from(value: i64) -> Self {
+        AnnotationValue::Long(value)
+    }
+}
+
+impl From<i16> for AnnotationValue {
+    fn from(value: i16) -> Self {
+        AnnotationValue::Short(value)
+    }
+}
+
+impl From<bool> for AnnotationValue {
+    fn from(value: bool) -> Self {
+        AnnotationValue::Boolean(value)
+    }
+}
+
+impl From<&str> for AnnotationValue {
+    fn from(value: &str) -> Self {
+        AnnotationValue::String(value.to_string())
+    }
+}
+
+impl From<String> for AnnotationValue {
+    fn from(value: String) -> Self {
+        AnnotationValue::String(value)
+    }
+}
+
+impl From<AnnotationBuilder> for AnnotationValue {
+    fn from(value: AnnotationBuilder) -> Self {
+        AnnotationValue::Annotation(value)
+    }
+}
+
+impl<T: Into<AnnotationValue>> From<Vec<T>> for AnnotationValue {
+    fn from(values: Vec<T>) -> Self {
+        AnnotationValue::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl AnnotationValue {
+    fn to_element_value(&self, pool: &mut ConstantPool) -> Result<ElementValue, BuilderError> {
+        let (tag, value) = match self {
+            AnnotationValue::Byte(value) => (b'B', const_value(pool.integer(*value as i32)?)),
+            AnnotationValue::Char(value) => (b'C', const_value(pool.integer(*value as i32)?)),
+            AnnotationValue::Double(value) => (b'D', const_value(pool.double(*value)?)),
+            AnnotationValue::Float(value) => (b'F', const_value(pool.float(*value)?)),
+            AnnotationValue::Int(value) => (b'I', const_value(pool.integer(*value)?)),
+            AnnotationValue::Long(value) => (b'J', const_value(pool.long(*value)?)),
+            AnnotationValue::Short(value) => (b'S', const_value(pool.integer(*value as i32)?)),
+            AnnotationValue::Boolean(value) => (b'Z', const_value(pool.integer(*value as i32)?)),
+            AnnotationValue::String(value) => (b's', const_value(pool.utf8(value)?)),
+            AnnotationValue::Enum { class, name } => (
+                b'e',
+                ElementValueKind::EnumConstValue {
+                    type_name_index: pool
+                        .utf8(&Descriptor::from(ClassName::new(class)?).serialize())?,
+                    const_name_index: pool.utf8(name)?,
+                },
+            ),
+            AnnotationValue::Class(descriptor) => {
+                if let Descriptor::Function(..) = descriptor {
+                    return Err(BuilderError::InvalidDescriptor(descriptor.serialize()));
+                }
+                (
+                    b'c',
+                    ElementValueKind::ClassInfoIndex(pool.utf8(&descriptor.serialize())?),
+                )
+            }
+            AnnotationValue::Annotation(annotation) => (
+                b'@',
+                ElementValueKind::AnnotationValue(annotation.to_annotation(pool)?),
+            ),
+            AnnotationValue::Array(values) => (
+                b'[',
+                ElementValueKind::ArrayValue {
+                    num_values: values.len() as u16,
+                    values: values
+                        .iter()
+                        .map(|value| value.to_element_value(pool))
+                        .collect::<Result<_, _>>()?,
+                },
+            ),
+        };
+        Ok(ElementValue { tag, value })
+    }
+}
+
+fn const_value(index: u16) -> ElementValueKind {
+    ElementValueKind::ConstValueIndex(index)
+}
+
+/// An annotation to attach to a class, field, method or parameter. Nested
+/// annotations are built the same way and passed as element values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnotationBuilder {
+    class: String,
+    elements: Vec<(String, AnnotationValue)>,
+}
+
+impl AnnotationBuilder {
+    /// Creates an annotation of the annotation interface `class`, such as
+    /// `org/junit/Test`.
+    pub fn new(class: &str) -> Self {
+        AnnotationBuilder {
+            class: class.to_string(),
+            elements: vec![],
+        }
+    }
+
+    /// Sets the element `name` to `value`. Elements left out take their
+    /// default value.
+    pub fn element(mut self, name: &str, value: impl Into<AnnotationValue>) -> Self {
+        let value = value.into();
+        match self.elements.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value,
+            None => self.elements.push((name.to_string(), value)),
+        }
+        self
+    }
+
+    fn to_annotation(&self, pool: &mut ConstantPool) -> Result<Annotation, BuilderError> {
+        let type_index = pool.utf8(&Descriptor::from(ClassName::new(&self.class)?).serialize())?;
+        let element_value_pairs = self
+            .elements
+            .iter()
+            .map(|(name, value)| {
+                Ok(ElementValuePair {
+                    element_name_index: pool.utf8(name)?,
+                    value: value.to_element_value(pool)?,
+                })
+            })
+            .collect::<Result<Vec<_>, BuilderError>>()?;
+        Ok(Annotation {
+            type_index,
+            num_element_value_pairs: element_value_pairs.len() as u16,
+            element_value_pairs,
+        })
+    }
+}
+
+pub(super) fn add_annotation(
+    attributes: &mut Vec<AttributeInfo>,
+    count: &mut u16,
+    pool: &mut ConstantPool,
+    annotation: &AnnotationBuilder,
+    retention: Retention,
+) -> Result<(), BuilderError> {
+    let annotation = annotation.to_annotation(pool)?;
+    let existing = attributes
+        .iter_mut()
+        .find_map(|a| match (&mut a.info, retention) {
+            (
+                AttributeInfoKind::RuntimeVisibleAnnotations {
+                    num_annotations,
+                    annotations,
+                },
+                Retention::Visible,
+            )
+            | (
+                AttributeInfoKind::RuntimeInvisibleAnnotations {
+                    num_annotations,
+                    annotations,
+                },
+                Retention::Invisible,
+            ) => Some((num_annotations, annotations)),
+            _ => None,
+        });
+    if let Some((num_annotations, annotations)) = existing {
+        annotations.push(annotation);
+        *num_annotations = annotations.len() as u16;
+        return Ok(());
+    }
+    let (name, info) = match retention {
+        Retention::Visible => (
+            "RuntimeVisibleAnnotations",
+            AttributeInfoKind::RuntimeVisibleAnnotations {
+                num_annotations: 1,
+                annotations: vec![annotation],
+            },
+        ),
+        Retention::Invisible => (
+            "RuntimeInvisibleAnnotations",
+            AttributeInfoKind::RuntimeInvisibleAnnotations {
+                num_annotations: 1,
+                annotations: vec![annotation],
+            },
+        ),
+    };
+    let attribute = AttributeInfo {
+        attribute_name_index: pool.utf8(name)?,
+        attribute_length: 0,
+        info,
+    };
+    add_attribute(attributes, count, attribute);
+    Ok(())
+}
+
+pub(super) fn add_parameter_annotation(
+    attributes: &mut Vec<AttributeInfo>,
+    count: &mut u16,
+    pool: &mut ConstantPool,
+    num_parameters: u8,
+    parameter: u8,
+    annotation: &AnnotationBuilder,
+    retention: Retention,
+) -> Result<(), BuilderError> {
+    if parameter >= num_parameters {
+        return Err(BuilderError::InvalidParameter(parameter));
+    }
+    let annotation = annotation.to_annotation(pool)?;
+    let existing = attributes
+        .iter_mut()
+        .find_map(|a| match (&mut a.info, retention) {
+            (
+                AttributeInfoKind::RuntimeVisibleParameterAnnotations {
+                    parameter_annotations,
+                    ..
+                },
+                Retention::Visible,
+            )
+            | (
+                AttributeInfoKind::RuntimeInvisibleParameterAnnotations {
+                    parameter_annotations,
+                    ..
+                },
+                Retention::Invisible,
+            ) => Some(parameter_annotations),
+            _ => None,
+        });
+    let parameter_annotations = match existing {
+        Some(parameter_annotations) => parameter_annotations,
+        None => {
+            let parameter_annotations = (0..num_parameters)
+                .map(|_| ParameterAnnotation {
+                    num_annotations: 0,
+                    annotations: vec![],
+                })
+                .collect();
+            let (name, info) = match retention {
+                Retention::Visible => (
+                    "RuntimeVisibleParameterAnnotations",
+                    AttributeInfoKind::RuntimeVisibleParameterAnnotations {
+                        num_parameters,
+                        parameter_annotations,
+                    },
+                ),
+                Retention::Invisible => (
+                    "RuntimeInvisibleParameterAnnotations",
+                    AttributeInfoKind::RuntimeInvisibleParameterAnnotations {
+                        num_parameters,
+                        parameter_annotations,
+                    },
+                ),
+            };
+            let attribute = AttributeInfo {
+                attribute_name_index: pool.utf8(name)?,
+                attribute_length: 0,
+                info,
+            };
+            add_attribute(attributes, count, attribute);
+            match &mut attributes.last_mut().unwrap().info {
+                AttributeInfoKind::RuntimeVisibleParameterAnnotations {
+                    parameter_annotations,
+                    ..
+                }
+                | AttributeInfoKind::RuntimeInvisibleParameterAnnotations {
+                    parameter_annotations,
+                    ..
+                } => parameter_annotations,
+                _ => unreachable!(),
+            }
+        }
+    };
+    let parameter = &mut parameter_annotations[parameter as usize];
+    parameter.annotations.push(annotation);
+    parameter.num_annotations = parameter.annotations.len() as u16;
+    Ok(())
+}
+
+pub(super) fn annotation_default(
+    pool: &mut ConstantPool,
+    value: &AnnotationValue,
+) -> Result<AttributeInfo, BuilderError> {
+    Ok(AttributeInfo {
+        attribute_name_index: pool.utf8("AnnotationDefault")?,
+        attribute_length: 0,
+        info: AttributeInfoKind::AnnotationDefault {
+            default_value: value.to_element_value(pool)?,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::ClassBuilder,
+        bytecode::{
+            ClassFile, ToJvmBytecode, FIELD_ACC_PRIVATE, METHOD_ACC_ABSTRACT, METHOD_ACC_PUBLIC,
+        },
+        parser::Parser,
+        reader::Reader,
+    };
+
+    fn reparse(class_file: &ClassFile) -> ClassFile {
+        Parser {
+            reader: Reader::new(class_file.to_jvm_bytecode()),
+        }
+        .parse()
+    }
+
+    fn annotated_class() -> ClassFile {
+        let mut builder = ClassBuilder::new();
+        builder.set_class_name("Annotated").unwrap();
+        let object = builder.load_class("java/lang/Object").unwrap();
+        builder.set_super_class(object);
+        let nested = AnnotationBuilder::new("Nested").element("names", vec!["a", "b"]);
+        let annotation = AnnotationBuilder::new("First")
+            .element("value", 1)
+            .element("nested", nested);
+        builder
+            .add_annotation(&annotation, Retention::Visible)
+            .unwrap();
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn parsed_annotations_are_written_back_unchanged() {
+        let class_file = annotated_class();
+        let parsed = reparse(&class_file);
+        assert!(parsed
+            .attributes
+            .iter()
+            .any(|a| matches!(a.info, AttributeInfoKind::RuntimeVisibleAnnotations { .. })));
+        assert_eq!(parsed.to_jvm_bytecode(), class_file.to_jvm_bytecode());
+    }
+
+    #[test]
+    fn add_annotation_to_parsed_class_extends_existing_attribute() {
+        let parsed = reparse(&annotated_class());
+        let mut builder = ClassBuilder::from_class_file(parsed).unwrap();
+        builder
+            .add_annotation(&AnnotationBuilder::new("Second"), Retention::Visible)
+            .unwrap();
+        let class_file = reparse(&builder.build().unwrap());
+
+        let attributes: Vec<&Vec<Annotation>> = class_file
+            .attributes
+            .iter()
+            .filter_map(|a| match &a.info {
+                AttributeInfoKind::RuntimeVisibleAnnotations { annotations, .. } => {
+                    Some(annotations)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(attributes.len(), 1);
+        let types: Vec<&str> = attributes[0]
+            .iter()
+            .map(|a| class_file.get_utf8(a.type_index).unwrap())
+            .collect();
+        assert_eq!(types, ["LFirst;", "LSecond;"]);
+    }
+
+    #[test]
+    fn element_values_use_the_tag_of_their_kind() {
+        let mut pool = ConstantPool::new();
+        let annotation = AnnotationBuilder::new("All")
+            .element("byte", 1i8)
+            .element("char", AnnotationValue::Char('x' as u16))
+            .element("double", 1.5)
+            .element("float", 1.5f32)
+            .element("int", 1)
+            .element("long", 1i64)
+            .element("short", 1i16)
+            .element("boolean", true)
+            .element("string", "text")
+            .element(
+                "enum",
+                AnnotationValue::Enum {
+                    class: "java/lang/annotation/ElementType".to_string(),
+                    name: "METHOD".to_string(),
+                },
+            )
+            .element("class", AnnotationValue::Class(Descriptor::Void))
+            .element("nested", AnnotationBuilder::new("Nested"))
+            .element("array", vec![1, 2]);
+        let annotation = annotation.to_annotation(&mut pool).unwrap();
+        let tags: Vec<u8> = annotation
+            .element_value_pairs
+            .iter()
+            .map(|pair| pair.value.tag)
+            .collect();
+        assert_eq!(tags, b"BCDFIJSZsec@[");
+        assert_eq!(pool.get_utf8(annotation.type_index), Some("LAll;"));
+
+        let value = |name: &str| {
+            let pair = annotation
+                .element_value_pairs
+                .iter()
+                .find(|pair| pool.get_utf8(pair.element_name_index) == Some(name))
+                .unwrap();
+            pair.value.value.clone()
+        };
+        assert_eq!(
+            value("boolean"),
+            ElementValueKind::ConstValueIndex(pool.clone().integer(1).unwrap())
+        );
+        assert_eq!(
+            value("string"),
+            ElementValueKind::ConstValueIndex(pool.clone().utf8("text").unwrap())
+        );
+        assert_eq!(
+            value("class"),
+            ElementValueKind::ClassInfoIndex(pool.clone().utf8("V").unwrap())
+        );
+        let ElementValueKind::EnumConstValue {
+            type_name_index,
+            const_name_index,
+        } = value("enum")
+        else {
+            panic!("not an enum value");
+        };
+        assert_eq!(
+            pool.get_utf8(type_name_index),
+            Some("Ljava/lang/annotation/ElementType;")
+        );
+        assert_eq!(pool.get_utf8(const_name_index), Some("METHOD"));
+        assert!(matches!(
+            value("array"),
+            ElementValueKind::ArrayValue { num_values: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn invalid_annotation_values_are_errors() {
+        let mut pool = ConstantPool::new();
+        let method = Descriptor::Function(vec![], Box::new(Descriptor::Void));
+        let annotation =
+            AnnotationBuilder::new("Bad").element("class", AnnotationValue::Class(method));
+        assert_eq!(
+            annotation.to_annotation(&mut pool),
+            Err(BuilderError::InvalidDescriptor("()V".to_string()))
+        );
+        let annotation = AnnotationBuilder::new("java.lang.Deprecated");
+        assert!(matches!(
+            annotation.to_annotation(&mut pool),
+            Err(BuilderError::InvalidName(_))
+        ));
+    }
+
+    #[test]
+    fn annotations_attach_to_members_and_parameters() {
+        let mut builder = ClassBuilder::new();
+        builder.set_class_name("Annotated").unwrap();
+        let inject = AnnotationBuilder::new("javax/inject/Inject");
+        let mut field = builder
+            .field("name", Descriptor::Int, FIELD_ACC_PRIVATE)
+            .unwrap();
+        field.add_annotation(&inject, Retention::Invisible).unwrap();
+        let descriptor = Descriptor::Function(
+            vec![Descriptor::Int, Descriptor::Long],
+            Box::new(Descriptor::Void),
+        );
+        let flags = METHOD_ACC_PUBLIC | METHOD_ACC_ABSTRACT;
+        let mut method = builder.method("set", descriptor, flags).unwrap();
+        method.add_annotation(&inject, Retention::Visible).unwrap();
+        method
+            .add_parameter_annotation(1, &inject, Retention::Visible)
+            .unwrap();
+        assert_eq!(
+            method.add_parameter_annotation(2, &inject, Retention::Visible),
+            Err(BuilderError::InvalidParameter(2))
+        );
+        method.set_annotation_default(3).unwrap();
+        let class_file = builder.build().unwrap();
+
+        assert!(matches!(
+            &class_file.fields[0].attributes[0].info,
+            AttributeInfoKind::RuntimeInvisibleAnnotations {
+                num_annotations: 1,
+                ..
+            }
+        ));
+        let attributes = &class_file.method_info[0].attributes;
+        assert!(matches!(
+            &attributes[0].info,
+            AttributeInfoKind::RuntimeVisibleAnnotations {
+                num_annotations: 1,
+                ..
+            }
+        ));
+        let AttributeInfoKind::RuntimeVisibleParameterAnnotations {
+            num_parameters,
+            parameter_annotations,
+        } = &attributes[1].info
+        else {
+            panic!("no parameter annotations");
+        };
+        assert_eq!(*num_parameters, 2);
+        let counts: Vec<u16> = parameter_annotations
+            .iter()
+            .map(|p| p.num_annotations)
+            .collect();
+        assert_eq!(counts, [0, 1]);
+        assert!(matches!(
+            &attributes[2].info,
+            AttributeInfoKind::AnnotationDefault {
+                default_value: ElementValue { tag: b'I', .. }
+            }
+        ));
+    }
+}
@@ -0,0 +1,479 @@
+use super::{BuilderError, Label, MethodBuilder};
+use crate::bytecode::instr::Cond;
+
+/// A test made by the structured control flow helpers of `MethodBuilder`, on
+/// the values its condition code leaves on the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    /// Compares an `int` with zero, like `if<cond>`. A boolean is true with
+    /// `Int(Cond::Ne)`.
+    Int(Cond),
+    /// Compares two `int`s, like `if_icmp<cond>`.
+    IntCompare(Cond),
+    SameReference,
+    DifferentReference,
+    Null,
+    NonNull,
+}
+
+impl Condition {
+    /// Returns the condition that holds exactly when `self` doesn't.
+    pub fn negate(self) -> Condition {
+        match self {
+            Condition::Int(cond) => Condition::Int(cond.negate()),
+            Condition::IntCompare(cond) => Condition::IntCompare(cond.negate()),
+            Condition::SameReference => Condition::DifferentReference,
+            Condition::DifferentReference => Condition::SameReference,
+            Condition::Null => Condition::NonNull,
+            Condition::NonNull => Condition::Null,
+        }
+    }
+}
+
+/// The labels of a loop created by `while_loop` or `for_range`, used by
+/// `build_break` and `build_continue`. Loops can be nested, and a `Loop`
+/// captured by an inner body breaks out of the outer loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Loop {
+    break_label: Label,
+    continue_label: Label,
+    /// The `build_try_finally` blocks enclosing the loop, which stay open.
+    finally_depth: usize,
+}
+
+impl Loop {
+    /// Returns the label following the loop.
+    pub fn break_label(&self) -> Label {
+        self.break_label
+    }
+
+    /// Returns the label starting the next iteration.
+    pub fn continue_label(&self) -> Label {
+        self.continue_label
+    }
+}
+
+impl MethodBuilder {
+    /// Jumps to `target` if `condition` holds.
+    pub fn build_if(&mut self, condition: Condition, target: Label) {
+        match condition {
+            Condition::Int(Cond::Eq) => self.build_ifeq(target),
+            Condition::Int(Cond::Ne) => self.build_ifne(target),
+            Condition::Int(Cond::Lt) => self.build_iflt(target),
+            Condition::Int(Cond::Ge) => self.build_ifge(target),
+            Condition::Int(Cond::Gt) => self.build_ifgt(target),
+            Condition::Int(Cond::Le) => self.build_ifle(target),
+            Condition::IntCompare(Cond::Eq) => self.build_if_icmpeq(target),
+            Condition::IntCompare(Cond::Ne) => self.build_if_icmpne(target),
+            Condition::IntCompare(Cond::Lt) => self.build_if_icmplt(target),
+            Condition::IntCompare(Cond::Ge) => self.build_if_icmpge(target),
+            Condition::IntCompare(Cond::Gt) => self.build_if_icmpgt(target),
+            Condition::IntCompare(Cond::Le) => self.build_if_icmple(target),
+            Condition::SameReference => self.build_if_acmpeq(target),
+            Condition::DifferentReference => self.build_if_acmpne(target),
+            Condition::Null => self.build_ifnull(target),
+            Condition::NonNull => self.build_ifnonnull(target),
+        }
+    }
+
+    /// Emits `condition`, then `then` if the condition it returns holds.
+    pub fn if_then<C, T>(&mut self, condition: C, then: T) -> Result<(), BuilderError>
+    where
+        C: FnOnce(&mut Self) -> Result<Condition, BuilderError>,
+        T: FnOnce(&mut Self) -> Result<(), BuilderError>,
+    {
+        let end = self.new_label();
+        let condition = condition(self)?;
+        self.build_if(condition.negate(), end);
+        then(self)?;
+        self.place_label(end)
+    }
+
+    /// Emits `condition`, then `then` if the condition it returns holds and
+    /// `otherwise` if it doesn't.
+    pub fn if_then_else<C, T, E>(
+        &mut self,
+        condition: C,
+        then: T,
+        otherwise: E,
+    ) -> Result<(), BuilderError>
+    where
+        C: FnOnce(&mut Self) -> Result<Condition, BuilderError>,
+        T: FnOnce(&mut Self) -> Result<(), BuilderError>,
+        E: FnOnce(&mut Self) -> Result<(), BuilderError>,
+    {
+        let (else_label, end) = (self.new_label(), self.new_label());
+        let condition = condition(self)?;
+        self.build_if(condition.negate(), else_label);
+        then(self)?;
+        // No jump is needed over `otherwise` if `then` returns or throws.
+        let falls_through = !self.ends_unconditionally(&[]);
+        if falls_through {
+            self.build_goto(end);
+        }
+        self.place_label(else_label)?;
+        otherwise(self)?;
+        self.place_label(end)
+    }
+
+    /// Emits a loop running `body` while the condition returned by
+    /// `condition` holds, which is tested before each iteration.
+    pub fn while_loop<C, B>(&mut self, condition: C, body: B) -> Result<(), BuilderError>
+    where
+        C: FnOnce(&mut Self) -> Result<Condition, BuilderError>,
+        B: FnOnce(&mut Self, Loop) -> Result<(), BuilderError>,
+    {
+        let target = Loop {
+            break_label: self.new_label(),
+            continue_label: self.new_label(),
+            finally_depth: self.finally_depth(),
+        };
+        self.place_label(target.continue_label)?;
+        let condition = condition(self)?;
+        self.build_if(condition.negate(), target.break_label);
+        body(self, target)?;
+        self.build_goto(target.continue_label);
+        self.place_label(target.break_label)
+    }
+
+    /// Emits a loop running `body` for each `int` in the local `counter` from
+    /// the value pushed by `start` up to, but excluding, the value pushed by
+    /// `end`. Like the condition of a Java `for`, `end` is evaluated before
+    /// each iteration.
+    pub fn for_range<S, E, B>(
+        &mut self,
+        counter: u16,
+        start: S,
+        end: E,
+        body: B,
+    ) -> Result<(), BuilderError>
+    where
+        S: FnOnce(&mut Self) -> Result<(), BuilderError>,
+        E: FnOnce(&mut Self) -> Result<(), BuilderError>,
+        B: FnOnce(&mut Self, Loop) -> Result<(), BuilderError>,
+    {
+        let target = Loop {
+            break_label: self.new_label(),
+            continue_label: self.new_label(),
+            finally_depth: self.finally_depth(),
+        };
+        let head = self.new_label();
+        start(self)?;
+        self.build_istore(counter);
+        self.place_label(head)?;
+        self.build_iload(counter);
+        end(self)?;
+        self.build_if_icmpge(target.break_label);
+        body(self, target)?;
+        self.place_label(target.continue_label)?;
+        self.build_iinc(counter, 1);
+        self.build_goto(head);
+        self.place_label(target.break_label)
+    }
+
+    /// Jumps out of `target`, first running the finally blocks of the
+    /// `build_try_finally` calls inside the loop, like a Java `break`.
+    pub fn build_break(&mut self, target: Loop) -> Result<(), BuilderError> {
+        self.exit_finally_blocks(target.finally_depth, |m| m.build_goto(target.break_label))
+    }
+
+    /// Jumps to the next iteration of `target`, first running the finally
+    /// blocks of the `build_try_finally` calls inside the loop, like a Java
+    /// `continue`.
+    pub fn build_continue(&mut self, target: Loop) -> Result<(), BuilderError> {
+        self.exit_finally_blocks(target.finally_depth, |m| {
+            m.build_goto(target.continue_label)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::ClassBuilder,
+        bytecode::{instr::Opcode, AttributeInfoKind, METHOD_ACC_STATIC, PUBLIC, SUPER},
+        descriptor::Descriptor,
+    };
+
+    /// Builds the class with the single method `static void run(int)` whose
+    /// code is emitted by `emit`, and returns that code.
+    fn build_code<F>(emit: F) -> Vec<Opcode>
+    where
+        F: FnOnce(&mut MethodBuilder) -> Result<(), BuilderError>,
+    {
+        let mut builder = ClassBuilder::new();
+        builder.set_access_flags(PUBLIC | SUPER);
+        builder.set_class_name("Loops").unwrap();
+        let object = builder.load_class("java/lang/Object").unwrap();
+        builder.set_super_class(object);
+        let descriptor = Descriptor::Function(vec![Descriptor::Int], Box::new(Descriptor::Void));
+        let mut method = builder
+            .method("run", descriptor, METHOD_ACC_STATIC)
+            .unwrap();
+        emit(&mut method).unwrap();
+        method.build_return().unwrap();
+        let class_file = builder.build().unwrap();
+        class_file.method_info[0]
+            .attributes
+            .iter()
+            .find_map(|a| match &a.info {
+                AttributeInfoKind::Code { code, .. } => Some(code.clone()),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    fn finally(m: &mut MethodBuilder) -> Result<(), BuilderError> {
+        m.build_iinc(0, 1);
+        Ok(())
+    }
+
+    /// Emits a loop whose body is a try block ending with `exit`.
+    fn loop_with_finally<E>(exit: E) -> Vec<Opcode>
+    where
+        E: FnOnce(&mut MethodBuilder, Loop) -> Result<(), BuilderError>,
+    {
+        build_code(|m| {
+            m.while_loop(
+                |m| {
+                    m.build_iload_n(0)?;
+                    Ok(Condition::Int(Cond::Ne))
+                },
+                |m, lp| {
+                    let body = |m: &mut MethodBuilder| {
+                        m.build_iinc(0, 2);
+                        exit(m, lp)
+                    };
+                    m.build_try_finally(body, finally)
+                },
+            )
+        })
+    }
+
+    fn first_goto(code: &[Opcode]) -> usize {
+        code.iter()
+            .position(|op| matches!(op, Opcode::Goto(..)))
+            .unwrap()
+    }
+
+    #[test]
+    fn break_runs_finally_before_leaving_the_loop() {
+        let code = loop_with_finally(|m, lp| m.build_break(lp));
+        assert_eq!(code[first_goto(&code) - 1], Opcode::Iinc(0, 1));
+        // No fall-through copy, as the body ends with the break.
+        let finally_runs = code.iter().filter(|op| **op == Opcode::Iinc(0, 1)).count();
+        assert_eq!(finally_runs, 2);
+    }
+
+    #[test]
+    fn continue_runs_finally_before_the_next_iteration() {
+        let code = loop_with_finally(|m, lp| m.build_continue(lp));
+        assert_eq!(code[first_goto(&code) - 1], Opcode::Iinc(0, 1));
+    }
+
+    #[test]
+    fn break_keeps_finally_blocks_enclosing_the_loop() {
+        let code = build_code(|m| {
+            m.build_try_finally(
+                |m| {
+                    m.while_loop(
+                        |m| {
+                            m.build_iload_n(0)?;
+                            Ok(Condition::Int(Cond::Ne))
+                        },
+                        |m, lp| m.build_break(lp),
+                    )
+                },
+                finally,
+            )
+        });
+        // The break only jumps, then the finally code runs on fall-through
+        // and in the handler.
+        assert_ne!(code[first_goto(&code) - 1], Opcode::Iinc(0, 1));
+        let finally_runs = code.iter().filter(|op| **op == Opcode::Iinc(0, 1)).count();
+        assert_eq!(finally_runs, 2);
+    }
+
+    fn positive(m: &mut MethodBuilder) -> Result<Condition, BuilderError> {
+        m.build_iload_n(0)?;
+        Ok(Condition::Int(Cond::Gt))
+    }
+
+    #[test]
+    fn conditions_negate_to_their_opposite() {
+        for condition in [
+            Condition::Int(Cond::Lt),
+            Condition::IntCompare(Cond::Ge),
+            Condition::SameReference,
+            Condition::Null,
+        ] {
+            assert_ne!(condition.negate(), condition);
+            assert_eq!(condition.negate().negate(), condition);
+        }
+        assert_eq!(Condition::Int(Cond::Gt).negate(), Condition::Int(Cond::Le));
+        assert_eq!(Condition::Null.negate(), Condition::NonNull);
+    }
+
+    #[test]
+    fn if_then_skips_the_branch_when_the_condition_fails() {
+        let code = build_code(|m| {
+            m.if_then(positive, |m| {
+                m.build_iinc(0, 1);
+                Ok(())
+            })
+        });
+        assert_eq!(
+            code,
+            [
+                Opcode::IloadN(0),
+                Opcode::ICOND(Cond::Le, 0, 6),
+                Opcode::Iinc(0, 1),
+                Opcode::Return_,
+            ]
+        );
+    }
+
+    #[test]
+    fn if_then_else_jumps_over_the_else_branch() {
+        let code = build_code(|m| {
+            m.if_then_else(
+                positive,
+                |m| {
+                    m.build_iinc(0, 1);
+                    Ok(())
+                },
+                |m| {
+                    m.build_iinc(0, -1);
+                    Ok(())
+                },
+            )
+        });
+        assert_eq!(
+            code,
+            [
+                Opcode::IloadN(0),
+                Opcode::ICOND(Cond::Le, 0, 9),
+                Opcode::Iinc(0, 1),
+                Opcode::Goto(0, 6),
+                Opcode::Iinc(0, 0xff),
+                Opcode::Return_,
+            ]
+        );
+    }
+
+    #[test]
+    fn if_then_else_omits_the_jump_after_a_return() {
+        let code = build_code(|m| {
+            m.if_then_else(positive, MethodBuilder::build_return, |m| {
+                m.build_iinc(0, 1);
+                Ok(())
+            })
+        });
+        assert_eq!(
+            code,
+            [
+                Opcode::IloadN(0),
+                Opcode::ICOND(Cond::Le, 0, 4),
+                Opcode::Return_,
+                Opcode::Iinc(0, 1),
+                Opcode::Return_,
+            ]
+        );
+    }
+
+    #[test]
+    fn while_loop_tests_the_condition_before_each_iteration() {
+        let code = build_code(|m| {
+            m.while_loop(positive, |m, _| {
+                m.build_iinc(0, -1);
+                Ok(())
+            })
+        });
+        assert_eq!(
+            code,
+            [
+                Opcode::IloadN(0),
+                Opcode::ICOND(Cond::Le, 0, 9),
+                Opcode::Iinc(0, 0xff),
+                Opcode::Goto(0xff, 0xf9),
+                Opcode::Return_,
+            ]
+        );
+    }
+
+    #[test]
+    fn for_range_counts_up_to_the_end() {
+        let code = build_code(|m| {
+            m.for_range(
+                1,
+                |m| m.build_iconst_i(0),
+                |m| m.build_iload_n(0),
+                |m, _| {
+                    m.build_iinc(0, 2);
+                    Ok(())
+                },
+            )
+        });
+        assert_eq!(
+            code,
+            [
+                Opcode::IconstI(0),
+                Opcode::Istore(1),
+                Opcode::Iload(1),
+                Opcode::IloadN(0),
+                Opcode::IfIcmCond(Cond::Ge, 0, 12),
+                Opcode::Iinc(0, 2),
+                Opcode::Iinc(1, 1),
+                Opcode::Goto(0xff, 0xf4),
+                Opcode::Return_,
+            ]
+        );
+    }
+
+    #[test]
+    fn continue_in_for_range_increments_the_counter() {
+        let code = build_code(|m| {
+            m.for_range(
+                1,
+                |m| m.build_iconst_i(0),
+                |m| m.build_iload_n(0),
+                |m, lp| {
+                    m.if_then(positive, |m| m.build_continue(lp))?;
+                    m.build_iinc(0, 2);
+                    Ok(())
+                },
+            )
+        });
+        // The continue jumps to the `iinc` of the counter at pc 19.
+        assert_eq!(code[7], Opcode::Goto(0, 6));
+        assert_eq!(code[9], Opcode::Iinc(1, 1));
+        assert_eq!(code[4], Opcode::IfIcmCond(Cond::Ge, 0, 19));
+    }
+
+    #[test]
+    fn break_leaves_an_enclosing_loop() {
+        let code = build_code(|m| {
+            m.while_loop(positive, |m, outer| {
+                m.while_loop(positive, |m, _| m.build_break(outer))
+            })
+        });
+        // The inner loop's jump back is unreachable after the break, so it is
+        // replaced like other dead code.
+        assert_eq!(
+            code,
+            [
+                Opcode::IloadN(0),
+                Opcode::ICOND(Cond::Le, 0, 16),
+                Opcode::IloadN(0),
+                Opcode::ICOND(Cond::Le, 0, 9),
+                Opcode::Goto(0, 9),
+                Opcode::Nop,
+                Opcode::Nop,
+                Opcode::Athrow,
+                Opcode::Goto(0xff, 0xf2),
+                Opcode::Return_,
+            ]
+        );
+    }
+}
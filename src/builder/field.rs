@@ -0,0 +1,294 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::{annotation, set_attribute, AnnotationBuilder, BuilderError, ClassState, Retention};
+use crate::{
+    bytecode::{AttributeInfo, AttributeInfoKind, FieldInfo, FIELD_ACC_STATIC},
+    constant_pool::ConstantPoolError,
+    descriptor::Descriptor,
+    flags::AccessError,
+};
+
+/// The compile-time value of a `static final` field, stored in its
+/// `ConstantValue` attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstantValue {
+    /// The value of an `int`, `short`, `char`, `byte` or `boolean` field.
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+}
+
+impl From<i32> for ConstantValue {
+    fn from(value: i32) -> Self {
+        ConstantValue::Int(value)
+    }
+}
+
+impl From<i64> for ConstantValue {
+    fn from(value: i64) -> Self {
+        ConstantValue::Long(value)
+    }
+}
+
+impl From<f32> for ConstantValue {
+    fn from(value: f32) -> Self {
+        ConstantValue::Float(value)
+    }
+}
+
+impl From<f64> for ConstantValue {
+    fn from(value: f64) -> Self {
+        ConstantValue::Double(value)
+    }
+}
+
+impl From<&str> for ConstantValue {
+    fn from(value: &str) -> Self {
+        ConstantValue::String(value.to_string())
+    }
+}
+
+impl From<String> for ConstantValue {
+    fn from(value: String) -> Self {
+        ConstantValue::String(value)
+    }
+}
+
+impl ConstantValue {
+    /// Returns `true` if the value can initialize a field of type `descriptor`.
+    fn matches(&self, descriptor: &Descriptor) -> bool {
+        match self {
+            ConstantValue::Int(_) => matches!(
+                descriptor,
+                Descriptor::Int
+                    | Descriptor::Short
+                    | Descriptor::Char
+                    | Descriptor::Byte
+                    | Descriptor::Boolean
+            ),
+            ConstantValue::Long(_) => *descriptor == Descriptor::Long,
+            ConstantValue::Float(_) => *descriptor == Descriptor::Float,
+            ConstantValue::Double(_) => *descriptor == Descriptor::Double,
+            ConstantValue::String(_) => {
+                matches!(descriptor, Descriptor::Object(class) if class == "java/lang/String")
+            }
+        }
+    }
+}
+
+/// Sets the attributes of a field created by `ClassBuilder::field`.
+#[derive(Debug)]
+pub struct FieldBuilder {
+    class: Rc<RefCell<ClassState>>,
+    index: u16,
+    descriptor: Descriptor,
+}
+
+impl FieldBuilder {
+    pub(super) fn new(class: Rc<RefCell<ClassState>>, index: u16, descriptor: Descriptor) -> Self {
+        FieldBuilder {
+            class,
+            index,
+            descriptor,
+        }
+    }
+
+    /// Returns the `Fieldref` constant referring to this field, which is
+    /// created on the first call. It refers to the class by the name set at
+    /// that time, so the class must not be renamed afterwards.
+    pub fn field_ref(&self) -> Result<u16, BuilderError> {
+        self.class.borrow_mut().field_ref(self.index)
+    }
+
+    /// Returns the `field_info` structure of the field, as passed to
+    /// `ClassBuilder::add_getter` and the other accessor generators.
+    pub fn field_info(&self) -> FieldInfo {
+        self.class.borrow().class_file.fields[self.index as usize].clone()
+    }
+
+    /// Sets the value the JVM initializes the field with, which must match its
+    /// type. Only `static` fields are initialized this way, so the JVM would
+    /// ignore the value of another field, and this fails instead.
+    pub fn set_constant_value(
+        &mut self,
+        value: impl Into<ConstantValue>,
+    ) -> Result<(), BuilderError> {
+        let value = value.into();
+        if !value.matches(&self.descriptor) {
+            return Err(BuilderError::InvalidConstantValue {
+                descriptor: self.descriptor.serialize(),
+                value,
+            });
+        }
+        let mut class = self.class.borrow_mut();
+        let field = &class.class_file.fields[self.index as usize];
+        if !field.access_flags.is_set(FIELD_ACC_STATIC) {
+            let pool = &class.constant_pool;
+            let class_name = pool.get_class_name(class.class_file.this_class);
+            let name = pool.get_utf8(field.name_index);
+            return Err(BuilderError::InvalidAccess {
+                name: format!(
+                    "{}.{}",
+                    class_name.unwrap_or_default(),
+                    name.unwrap_or_default()
+                ),
+                error: AccessError::Missing {
+                    flag: "static",
+                    on: "field with a constant value",
+                },
+            });
+        }
+        let pool = &mut class.constant_pool;
+        let constantvalue_index = match value {
+            ConstantValue::Int(value) => pool.integer(value)?,
+            ConstantValue::Long(value) => pool.long(value)?,
+            ConstantValue::Float(value) => pool.float(value)?,
+            ConstantValue::Double(value) => pool.double(value)?,
+            ConstantValue::String(value) => pool.string(&value)?,
+        };
+        let attribute = AttributeInfo {
+            attribute_name_index: pool.utf8("ConstantValue")?,
+            attribute_length: 0,
+            info: AttributeInfoKind::ConstantValue {
+                constantvalue_index,
+            },
+        };
+        let field = &mut class.class_file.fields[self.index as usize];
+        set_attribute(
+            &mut field.attributes,
+            &mut field.attributes_count,
+            attribute,
+        );
+        Ok(())
+    }
+
+    /// Sets the generic signature of the field, such as
+    /// `Ljava/util/List<Ljava/lang/String;>;`.
+    pub fn set_signature(&mut self, signature: &str) -> Result<(), ConstantPoolError> {
+        let mut class = self.class.borrow_mut();
+        let attribute = class.signature_attribute(signature)?;
+        let field = &mut class.class_file.fields[self.index as usize];
+        set_attribute(
+            &mut field.attributes,
+            &mut field.attributes_count,
+            attribute,
+        );
+        Ok(())
+    }
+
+    pub fn set_deprecated(&mut self) -> Result<(), ConstantPoolError> {
+        let mut class = self.class.borrow_mut();
+        let attribute = class.deprecated_attribute()?;
+        let field = &mut class.class_file.fields[self.index as usize];
+        set_attribute(
+            &mut field.attributes,
+            &mut field.attributes_count,
+            attribute,
+        );
+        Ok(())
+    }
+
+    /// Annotates the field with `annotation`.
+    pub fn add_annotation(
+        &mut self,
+        annotation: &AnnotationBuilder,
+        retention: Retention,
+    ) -> Result<(), BuilderError> {
+        let mut class = self.class.borrow_mut();
+        let class = &mut *class;
+        let field = &mut class.class_file.fields[self.index as usize];
+        annotation::add_annotation(
+            &mut field.attributes,
+            &mut field.attributes_count,
+            &mut class.constant_pool,
+            annotation,
+            retention,
+        )
+    }
+
+    /// Adds an attribute named `name` with `info` as its contents, replacing
+    /// an attribute of the same name.
+    pub fn add_attribute(&mut self, name: &str, info: Vec<u8>) -> Result<(), ConstantPoolError> {
+        let mut class = self.class.borrow_mut();
+        let attribute = class.custom_attribute(name, info)?;
+        let field = &mut class.class_file.fields[self.index as usize];
+        set_attribute(
+            &mut field.attributes,
+            &mut field.attributes_count,
+            attribute,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::ClassBuilder,
+        bytecode::{CpInfoType, FIELD_ACC_FINAL, FIELD_ACC_PRIVATE},
+        name::ClassName,
+    };
+
+    #[test]
+    fn constant_values_require_static_fields() {
+        let mut builder = ClassBuilder::new();
+        builder.set_class_name("Constants").unwrap();
+        let flags = FIELD_ACC_PRIVATE | FIELD_ACC_FINAL;
+        let mut field = builder.field("size", Descriptor::Int, flags).unwrap();
+        assert_eq!(
+            field.set_constant_value(3),
+            Err(BuilderError::InvalidAccess {
+                name: "Constants.size".to_string(),
+                error: AccessError::Missing {
+                    flag: "static",
+                    on: "field with a constant value",
+                },
+            })
+        );
+        let mut field = builder
+            .field("SIZE", Descriptor::Int, flags | FIELD_ACC_STATIC)
+            .unwrap();
+        field.set_constant_value(3).unwrap();
+    }
+
+    #[test]
+    fn constant_values_must_match_the_field_type() {
+        let mut builder = ClassBuilder::new();
+        builder.set_class_name("Constants").unwrap();
+        let flags = FIELD_ACC_STATIC | FIELD_ACC_FINAL;
+        let mut field = builder.field("SIZE", Descriptor::Long, flags).unwrap();
+        assert_eq!(
+            field.set_constant_value(1),
+            Err(BuilderError::InvalidConstantValue {
+                descriptor: "J".to_string(),
+                value: ConstantValue::Int(1),
+            })
+        );
+        field.set_constant_value(1i64).unwrap();
+        let mut field = builder.field("ON", Descriptor::Boolean, flags).unwrap();
+        field.set_constant_value(1).unwrap();
+
+        let string = Descriptor::Object(ClassName::new("java/lang/String").unwrap());
+        let mut field = builder.field("NAME", string, flags).unwrap();
+        assert!(field.set_constant_value(1.5f32).is_err());
+        field.set_constant_value("name").unwrap();
+        let constant = field
+            .field_info()
+            .attributes
+            .iter()
+            .find_map(|a| match a.info {
+                AttributeInfoKind::ConstantValue {
+                    constantvalue_index,
+                } => Some(constantvalue_index),
+                _ => None,
+            });
+        let pool = builder.constant_pool();
+        assert!(matches!(
+            pool.get(constant.unwrap()).unwrap().info,
+            CpInfoType::String { string_index } if pool.get_utf8(string_index) == Some("name")
+        ));
+    }
+}
@@ -0,0 +1,1876 @@
+use std::{
+    cell::{RefCell, RefMut},
+    fmt,
+    rc::Rc,
+};
+
+use super::{
+    annotation, compute_byte, set_attribute, AnnotationBuilder, AnnotationValue, BuilderError,
+    ClassState, Fixup, Handler, Label, LocalVariable, MethodState, Retention,
+};
+use crate::{
+    bytecode::{instr::*, CpInfoType},
+    constant_pool::ConstantPoolError,
+    descriptor::Descriptor,
+    name::ClassName,
+};
+
+type Finally = dyn Fn(&mut MethodBuilder) -> Result<(), BuilderError>;
+
+/// A `try` whose finally block is still open, so returns emitted inside it
+/// must run the finally block first.
+#[derive(Clone)]
+struct FinallyBlock {
+    finally: Rc<Finally>,
+    /// The start of the protected range currently being emitted.
+    start: Label,
+    /// The protected ranges closed so far, which exclude inlined finally code.
+    ranges: Vec<(Label, Label)>,
+}
+
+impl fmt::Debug for FinallyBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FinallyBlock")
+            .field("start", &self.start)
+            .field("ranges", &self.ranges)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Emits the code of a method created by `ClassBuilder::method`.
+///
+/// Method builders share the constant pool of their class but are otherwise
+/// independent, so several methods can be written at the same time. The code
+/// is resolved when the class is built.
+#[derive(Debug)]
+pub struct MethodBuilder {
+    class: Rc<RefCell<ClassState>>,
+    /// The index of the builder's state in `ClassState::methods`.
+    index: u16,
+    finally_blocks: Vec<FinallyBlock>,
+    /// The instruction indices of the jumps that leave try blocks after
+    /// running their finally code.
+    finally_exits: Vec<usize>,
+}
+
+impl MethodBuilder {
+    pub(super) fn new(class: Rc<RefCell<ClassState>>, index: u16) -> Self {
+        MethodBuilder {
+            class,
+            index,
+            finally_blocks: vec![],
+            finally_exits: vec![],
+        }
+    }
+
+    /// Returns the `Methodref` constant referring to this method, or `None`
+    /// for the static initializer. The constant is created on the first call
+    /// and refers to the class by the name set at that time, so the class must
+    /// not be renamed afterwards.
+    pub fn method_ref(&self) -> Result<Option<u16>, BuilderError> {
+        let mut class = self.class.borrow_mut();
+        let method = class.methods[self.index as usize].method;
+        let name_index = class.class_file.method_info[method as usize].name_index;
+        // The static initializer can't be referred to.
+        if class.constant_pool.get_utf8(name_index) == Some("<clinit>") {
+            return Ok(None);
+        }
+        class.method_ref(method).map(Some)
+    }
+
+    /// Sets the generic signature of the method, such as
+    /// `<T:Ljava/lang/Object;>(TT;)Ljava/util/List<TT;>;`.
+    pub fn set_signature(&mut self, signature: &str) -> Result<(), ConstantPoolError> {
+        let mut class = self.class.borrow_mut();
+        let attribute = class.signature_attribute(signature)?;
+        let method = class.methods[self.index as usize].method;
+        let method = &mut class.class_file.method_info[method as usize];
+        set_attribute(
+            &mut method.attributes,
+            &mut method.attributes_count,
+            attribute,
+        );
+        Ok(())
+    }
+
+    pub fn set_deprecated(&mut self) -> Result<(), ConstantPoolError> {
+        let mut class = self.class.borrow_mut();
+        let attribute = class.deprecated_attribute()?;
+        let method = class.methods[self.index as usize].method;
+        let method = &mut class.class_file.method_info[method as usize];
+        set_attribute(
+            &mut method.attributes,
+            &mut method.attributes_count,
+            attribute,
+        );
+        Ok(())
+    }
+
+    /// Annotates the method with `annotation`.
+    pub fn add_annotation(
+        &mut self,
+        annotation: &AnnotationBuilder,
+        retention: Retention,
+    ) -> Result<(), BuilderError> {
+        let mut class = self.class.borrow_mut();
+        let class = &mut *class;
+        let method = class.methods[self.index as usize].method;
+        let method = &mut class.class_file.method_info[method as usize];
+        annotation::add_annotation(
+            &mut method.attributes,
+            &mut method.attributes_count,
+            &mut class.constant_pool,
+            annotation,
+            retention,
+        )
+    }
+
+    /// Annotates the parameter at position `parameter`, counting from 0 and
+    /// excluding `this`.
+    pub fn add_parameter_annotation(
+        &mut self,
+        parameter: u8,
+        annotation: &AnnotationBuilder,
+        retention: Retention,
+    ) -> Result<(), BuilderError> {
+        let mut class = self.class.borrow_mut();
+        let class = &mut *class;
+        let method = class.methods[self.index as usize].method;
+        let method = &mut class.class_file.method_info[method as usize];
+        let descriptor = class.constant_pool.get_utf8(method.descriptor_index);
+        let num_parameters =
+            match descriptor.and_then(|descriptor| Descriptor::parse_method(descriptor).ok()) {
+                Some(Descriptor::Function(parameters, _)) => parameters.len() as u8,
+                _ => 0,
+            };
+        annotation::add_parameter_annotation(
+            &mut method.attributes,
+            &mut method.attributes_count,
+            &mut class.constant_pool,
+            num_parameters,
+            parameter,
+            annotation,
+            retention,
+        )
+    }
+
+    /// Sets the default value of an element of an annotation interface, which
+    /// is declared as an abstract method.
+    pub fn set_annotation_default(
+        &mut self,
+        value: impl Into<AnnotationValue>,
+    ) -> Result<(), BuilderError> {
+        let mut class = self.class.borrow_mut();
+        let class = &mut *class;
+        let attribute = annotation::annotation_default(&mut class.constant_pool, &value.into())?;
+        let method = class.methods[self.index as usize].method;
+        let method = &mut class.class_file.method_info[method as usize];
+        set_attribute(
+            &mut method.attributes,
+            &mut method.attributes_count,
+            attribute,
+        );
+        Ok(())
+    }
+
+    /// Adds an attribute named `name` with `info` as its contents, replacing
+    /// an attribute of the same name.
+    pub fn add_attribute(&mut self, name: &str, info: Vec<u8>) -> Result<(), ConstantPoolError> {
+        let mut class = self.class.borrow_mut();
+        let attribute = class.custom_attribute(name, info)?;
+        let method = class.methods[self.index as usize].method;
+        let method = &mut class.class_file.method_info[method as usize];
+        set_attribute(
+            &mut method.attributes,
+            &mut method.attributes_count,
+            attribute,
+        );
+        Ok(())
+    }
+
+    /// Creates a label to be placed with `place_label` and used as a branch
+    /// target in this method.
+    pub fn new_label(&mut self) -> Label {
+        let mut state = self.state();
+        state.labels.push(None);
+        Label {
+            method: self.index,
+            index: state.labels.len() - 1,
+        }
+    }
+
+    /// Binds `label` to the position of the next instruction.
+    pub fn place_label(&mut self, label: Label) -> Result<(), BuilderError> {
+        if label.method != self.index {
+            return Err(BuilderError::ForeignLabel(label));
+        }
+        let mut state = self.state();
+        if state.labels[label.index].is_some() {
+            return Err(BuilderError::LabelPlacedTwice(label));
+        }
+        state.labels[label.index] = Some(state.code.len());
+        Ok(())
+    }
+
+    /// Registers a handler for exceptions of class `catch_type`, or of any class
+    /// if `None`, thrown between `start` (inclusive) and `end` (exclusive).
+    /// Handlers are tried in the order they are added, so inner handlers must
+    /// be added before the ones enclosing them.
+    pub fn add_exception_handler(
+        &mut self,
+        start: Label,
+        end: Label,
+        handler: Label,
+        catch_type: Option<u16>,
+    ) {
+        self.state().handlers.push(Handler {
+            start,
+            end,
+            handler,
+            catch_type: catch_type.unwrap_or(0),
+        });
+    }
+
+    /// Attributes the instructions emitted from now on to source line `line`,
+    /// as shown in stack traces.
+    pub fn mark_line(&mut self, line: u16) {
+        let mut state = self.state();
+        let index = state.code.len();
+        if let Some(last) = state.lines.last_mut().filter(|(i, _)| *i == index) {
+            last.1 = line;
+        } else {
+            state.lines.push((index, line));
+        }
+    }
+
+    /// Names the local in `slot` for debuggers between `start` (inclusive)
+    /// and `end` (exclusive).
+    pub fn declare_local(
+        &mut self,
+        name: &str,
+        descriptor: Descriptor,
+        start: Label,
+        end: Label,
+        slot: u16,
+    ) -> Result<(), BuilderError> {
+        self.add_local(name, descriptor, None, start, end, slot)
+    }
+
+    /// Like `declare_local`, for a local with a generic type such as
+    /// `Ljava/util/List<Ljava/lang/String;>;`.
+    pub fn declare_generic_local(
+        &mut self,
+        name: &str,
+        descriptor: Descriptor,
+        signature: &str,
+        start: Label,
+        end: Label,
+        slot: u16,
+    ) -> Result<(), BuilderError> {
+        self.add_local(name, descriptor, Some(signature), start, end, slot)
+    }
+
+    /// Emits `body` followed by `finally`, which also runs when `body` throws
+    /// and before every return, `build_break` and `build_continue` emitted by
+    /// `body`, like a Java `try { } finally { }`. As with javac, the finally
+    /// code is duplicated on each of these paths. Any other branch from `body`
+    /// to a label outside of it fails with `BuilderError::JumpOutOfFinally`,
+    /// as it would skip the finally code.
+    pub fn build_try_finally<B, F>(&mut self, body: B, finally: F) -> Result<(), BuilderError>
+    where
+        B: FnOnce(&mut Self) -> Result<(), BuilderError>,
+        F: Fn(&mut Self) -> Result<(), BuilderError> + 'static,
+    {
+        let finally: Rc<Finally> = Rc::new(finally);
+        let first_fixup = self.state().fixups.len();
+        let start = self.place_new_label();
+        self.finally_blocks.push(FinallyBlock {
+            finally: finally.clone(),
+            start,
+            ranges: vec![],
+        });
+        let result = body(self);
+        let mut block = self.finally_blocks.pop().unwrap();
+        result?;
+        let end = self.place_new_label();
+        block.ranges.push((block.start, end));
+        self.check_jumps_out(first_fixup, start, end)?;
+
+        // Labels bounding protected ranges aren't branch targets, so they don't
+        // make the code after `body` reachable.
+        let ranges = self.finally_blocks.iter().map(|b| b.start);
+        let ignored: Vec<Label> = ranges
+            .chain(block.ranges.iter().flat_map(|(start, end)| [*start, *end]))
+            .collect();
+        let after = if self.ends_unconditionally(&ignored) {
+            None
+        } else {
+            let after = self.new_label();
+            finally(self)?;
+            self.build_goto(after);
+            Some(after)
+        };
+        let handler = self.place_new_label();
+        for (start, end) in block.ranges {
+            self.add_exception_handler(start, end, handler, None);
+        }
+        let exception = self.new_temp(1);
+        self.emit_temp(ASTORE, exception);
+        finally(self)?;
+        self.emit_temp(ALOAD, exception);
+        self.build_athrow();
+        if let Some(after) = after {
+            self.place_label(after)?;
+        }
+        Ok(())
+    }
+
+    /// Fails if a branch emitted since fixup `first_fixup` targets a label
+    /// outside of the code between `start` and `end`, other than the jumps
+    /// emitted by `exit_finally_blocks`. Labels that aren't placed yet can
+    /// only be placed after `end`.
+    fn check_jumps_out(
+        &self,
+        first_fixup: usize,
+        start: Label,
+        end: Label,
+    ) -> Result<(), BuilderError> {
+        let state = self.state();
+        let body = state.labels[start.index].unwrap()..=state.labels[end.index].unwrap();
+        for fixup in &state.fixups[first_fixup..] {
+            if self.finally_exits.contains(&fixup.index) {
+                continue;
+            }
+            for target in &fixup.targets {
+                let inside = target.method == self.index
+                    && state.labels[target.index].is_some_and(|index| body.contains(&index));
+                if !inside {
+                    return Err(BuilderError::JumpOutOfFinally(*target));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Pushes `value` with the shortest instruction: `iconst_<i>`, `bipush`,
+    /// `sipush`, or `ldc` of an `Integer` constant.
+    pub fn push_int(&mut self, value: i32) -> Result<(), BuilderError> {
+        if let Ok(i) = i8::try_from(value) {
+            if (-1..=5).contains(&i) {
+                self.emit(Opcode::IconstI(i));
+            } else {
+                self.build_bipush(i);
+            }
+        } else if let Ok(value) = i16::try_from(value) {
+            self.build_sipush(value);
+        } else {
+            let constant = self.class.borrow_mut().constant_pool.integer(value)?;
+            self.build_ldc(constant);
+        }
+        Ok(())
+    }
+
+    pub fn push_long(&mut self, value: i64) -> Result<(), BuilderError> {
+        match value {
+            0 | 1 => self.emit(Opcode::LconstL(value as u8)),
+            _ => {
+                let constant = self.class.borrow_mut().constant_pool.long(value)?;
+                self.build_ldc2_w(constant);
+            }
+        }
+        Ok(())
+    }
+
+    /// Pushes `value` with `fconst_<f>` when possible, which excludes `-0.0`.
+    pub fn push_float(&mut self, value: f32) -> Result<(), BuilderError> {
+        match [0.0f32, 1.0, 2.0]
+            .iter()
+            .position(|f| f.to_bits() == value.to_bits())
+        {
+            Some(f) => self.emit(Opcode::FconstF(f as u8)),
+            None => {
+                let constant = self.class.borrow_mut().constant_pool.float(value)?;
+                self.build_ldc(constant);
+            }
+        }
+        Ok(())
+    }
+
+    /// Pushes `value` with `dconst_<d>` when possible, which excludes `-0.0`.
+    pub fn push_double(&mut self, value: f64) -> Result<(), BuilderError> {
+        match [0.0f64, 1.0]
+            .iter()
+            .position(|d| d.to_bits() == value.to_bits())
+        {
+            Some(d) => self.emit(Opcode::DconstD(d as u8)),
+            None => {
+                let constant = self.class.borrow_mut().constant_pool.double(value)?;
+                self.build_ldc2_w(constant);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn push_string(&mut self, value: &str) -> Result<(), BuilderError> {
+        let constant = self.class.borrow_mut().constant_pool.string(value)?;
+        self.build_ldc(constant);
+        Ok(())
+    }
+
+    /// Pushes the `Class` object of `class`, an internal name such as
+    /// `java/lang/String` or an array descriptor such as `[I`.
+    pub fn push_class(
+        &mut self,
+        class: impl TryInto<ClassName, Error: Into<BuilderError>>,
+    ) -> Result<(), BuilderError> {
+        let class = class.try_into().map_err(Into::into)?;
+        let constant = self
+            .class
+            .borrow_mut()
+            .constant_pool
+            .class(class.as_str())?;
+        self.build_ldc(constant);
+        Ok(())
+    }
+
+    pub fn push_null(&mut self) {
+        self.build_aconst_null();
+    }
+
+    fn add_local(
+        &mut self,
+        name: &str,
+        descriptor: Descriptor,
+        signature: Option<&str>,
+        start: Label,
+        end: Label,
+        slot: u16,
+    ) -> Result<(), BuilderError> {
+        for label in [start, end] {
+            if label.method != self.index {
+                return Err(BuilderError::ForeignLabel(label));
+            }
+        }
+        if matches!(descriptor, Descriptor::Function(..) | Descriptor::Void) {
+            return Err(BuilderError::InvalidDescriptor(descriptor.serialize()));
+        }
+        let mut class = self.class.borrow_mut();
+        let pool = &mut class.constant_pool;
+        let name_index = pool.utf8(name)?;
+        let descriptor_index = pool.utf8(&descriptor.serialize())?;
+        let signature_index = signature.map(|s| pool.utf8(s)).transpose()?;
+        class.methods[self.index as usize]
+            .locals
+            .push(LocalVariable {
+                name_index,
+                descriptor_index,
+                signature_index,
+                start,
+                end,
+                slot,
+            });
+        Ok(())
+    }
+
+    fn state(&self) -> RefMut<'_, MethodState> {
+        let index = self.index as usize;
+        RefMut::map(self.class.borrow_mut(), |class| &mut class.methods[index])
+    }
+
+    fn place_new_label(&mut self) -> Label {
+        let label = self.new_label();
+        let mut state = self.state();
+        state.labels[label.index] = Some(state.code.len());
+        label
+    }
+
+    /// Returns `true` if the last instruction never falls through and no label
+    /// other than `ignored` points past it, so code emitted next is unreachable.
+    pub(super) fn ends_unconditionally(&self, ignored: &[Label]) -> bool {
+        let state = self.state();
+        let targeted = state.labels.iter().enumerate().any(|(index, position)| {
+            let label = Label {
+                method: self.index,
+                index,
+            };
+            *position == Some(state.code.len()) && !ignored.contains(&label)
+        });
+        let last = state.code.last();
+        last.is_some_and(Opcode::is_unconditional) && !targeted
+    }
+
+    fn new_temp(&mut self, size: u16) -> u16 {
+        let mut state = self.state();
+        state.temp_slots += size;
+        state.temp_slots - size
+    }
+
+    /// Uses the `wide` form, as the slot is only known at build time.
+    fn emit_temp(&mut self, opcode: u8, temp: u16) {
+        let mut state = self.state();
+        let index = state.code.len();
+        state.temps.push((index, temp));
+        state.code.push(Opcode::Wide(opcode, 0, 0));
+    }
+
+    /// Runs the enclosing finally blocks first, keeping the returned value in
+    /// a temporary.
+    fn emit_return(&mut self, op: Opcode) -> Result<(), BuilderError> {
+        if self.finally_blocks.is_empty() {
+            self.emit(op);
+            return Ok(());
+        }
+        let value = match op {
+            Opcode::Ireturn => Some((ISTORE, ILOAD, 1)),
+            Opcode::Lreturn => Some((LSTORE, LLOAD, 2)),
+            Opcode::Freturn => Some((FSTORE, FLOAD, 1)),
+            Opcode::Dreturn => Some((DSTORE, DLOAD, 2)),
+            Opcode::Areturn => Some((ASTORE, ALOAD, 1)),
+            _ => None,
+        };
+        let temp = value.map(|(store, _, size)| {
+            let temp = self.new_temp(size);
+            self.emit_temp(store, temp);
+            temp
+        });
+        self.exit_finally_blocks(0, |m| {
+            if let (Some((_, load, _)), Some(temp)) = (value, temp) {
+                m.emit_temp(load, temp);
+            }
+            m.emit(op);
+        })
+    }
+
+    pub(super) fn finally_depth(&self) -> usize {
+        self.finally_blocks.len()
+    }
+
+    /// Runs the finally blocks nested deeper than `depth`, innermost first.
+    pub(super) fn exit_finally_blocks<E>(
+        &mut self,
+        depth: usize,
+        exit: E,
+    ) -> Result<(), BuilderError>
+    where
+        E: FnOnce(&mut Self),
+    {
+        let mut blocks = std::mem::take(&mut self.finally_blocks);
+        for i in (depth..blocks.len()).rev() {
+            let mut inner = blocks.split_off(i);
+            let end = self.place_new_label();
+            let start = inner[0].start;
+            inner[0].ranges.push((start, end));
+            // The finally code is protected by the enclosing blocks only.
+            self.finally_blocks = blocks;
+            let finally = inner[0].finally.clone();
+            let result = finally(self);
+            blocks = std::mem::take(&mut self.finally_blocks);
+            blocks.extend(inner);
+            if let Err(e) = result {
+                self.finally_blocks = blocks;
+                return Err(e);
+            }
+        }
+        let index = self.state().code.len();
+        self.finally_exits.push(index);
+        exit(self);
+        for block in &mut blocks[depth..] {
+            block.start = self.place_new_label();
+        }
+        self.finally_blocks = blocks;
+        Ok(())
+    }
+
+    fn emit(&mut self, op: Opcode) {
+        self.state().code.push(op);
+    }
+
+    fn emit_branch(&mut self, op: Opcode, targets: Vec<Label>) {
+        let mut state = self.state();
+        let index = state.code.len();
+        state.fixups.push(Fixup { index, targets });
+        state.code.push(op);
+    }
+
+    fn emit_local(&mut self, op: fn(u8) -> Opcode, opcode: u8, local: u16) {
+        match u8::try_from(local) {
+            Ok(local) => self.emit(op(local)),
+            Err(_) => {
+                let (high, low) = compute_byte(local);
+                self.emit(Opcode::Wide(opcode, high, low));
+            }
+        }
+    }
+
+    fn emit_n(&mut self, op: fn(u8) -> Opcode, n: u8, max: u8) -> Result<(), BuilderError> {
+        if n > max {
+            return Err(BuilderError::InvalidOperand(op(n)));
+        }
+        self.emit(op(n));
+        Ok(())
+    }
+
+    /// Loads the local `local` of type `descriptor` with the matching
+    /// instruction, such as `lload_2` for a `long`.
+    pub fn build_load(&mut self, descriptor: &Descriptor, local: u16) -> Result<(), BuilderError> {
+        let op = descriptor
+            .load_opcode(local)
+            .ok_or_else(|| BuilderError::InvalidDescriptor(descriptor.serialize()))?;
+        self.emit(op);
+        Ok(())
+    }
+
+    /// Stores a value of type `descriptor` to the local `local`.
+    pub fn build_store(&mut self, descriptor: &Descriptor, local: u16) -> Result<(), BuilderError> {
+        let op = descriptor
+            .store_opcode(local)
+            .ok_or_else(|| BuilderError::InvalidDescriptor(descriptor.serialize()))?;
+        self.emit(op);
+        Ok(())
+    }
+
+    /// Returns a value of type `descriptor`, or nothing for `void`.
+    pub fn build_typed_return(&mut self, descriptor: &Descriptor) -> Result<(), BuilderError> {
+        let op = descriptor
+            .return_opcode()
+            .ok_or_else(|| BuilderError::InvalidDescriptor(descriptor.serialize()))?;
+        self.emit_return(op)
+    }
+
+    /// Loads an element of an array of type `array`, such as `[I`.
+    pub fn build_array_load(&mut self, array: &Descriptor) -> Result<(), BuilderError> {
+        let op = array
+            .array_load_opcode()
+            .ok_or_else(|| BuilderError::InvalidDescriptor(array.serialize()))?;
+        self.emit(op);
+        Ok(())
+    }
+
+    /// Stores an element into an array of type `array`.
+    pub fn build_array_store(&mut self, array: &Descriptor) -> Result<(), BuilderError> {
+        let op = array
+            .array_store_opcode()
+            .ok_or_else(|| BuilderError::InvalidDescriptor(array.serialize()))?;
+        self.emit(op);
+        Ok(())
+    }
+
+    pub fn build_aaload(&mut self) {
+        self.emit(Opcode::Aaload);
+    }
+
+    pub fn build_aastore(&mut self) {
+        self.emit(Opcode::Aastore);
+    }
+
+    pub fn build_aconst_null(&mut self) {
+        self.emit(Opcode::AconstNull);
+    }
+
+    /// Emits `wide aload` when `local` doesn't fit in a byte.
+    pub fn build_aload(&mut self, local: u16) {
+        self.emit_local(Opcode::Aload, ALOAD, local);
+    }
+
+    pub fn build_aload_n(&mut self, n: u8) -> Result<(), BuilderError> {
+        self.emit_n(Opcode::AloadN, n, 3)
+    }
+
+    pub fn build_anewarray(&mut self, class: u16) {
+        let (high, low) = compute_byte(class);
+        self.emit(Opcode::Anewarray(high, low));
+    }
+
+    pub fn build_areturn(&mut self) -> Result<(), BuilderError> {
+        self.emit_return(Opcode::Areturn)
+    }
+
+    pub fn build_arraylength(&mut self) {
+        self.emit(Opcode::Arraylength);
+    }
+
+    /// Emits `wide astore` when `local` doesn't fit in a byte.
+    pub fn build_astore(&mut self, local: u16) {
+        self.emit_local(Opcode::Astore, ASTORE, local);
+    }
+
+    pub fn build_astore_n(&mut self, n: u8) -> Result<(), BuilderError> {
+        self.emit_n(Opcode::AstoreN, n, 3)
+    }
+
+    pub fn build_athrow(&mut self) {
+        self.emit(Opcode::Athrow);
+    }
+
+    pub fn build_baload(&mut self) {
+        self.emit(Opcode::Baload);
+    }
+
+    pub fn build_bastore(&mut self) {
+        self.emit(Opcode::Bastore);
+    }
+
+    pub fn build_bipush(&mut self, value: i8) {
+        self.emit(Opcode::Bipush(value as u8));
+    }
+
+    pub fn build_caload(&mut self) {
+        self.emit(Opcode::Caload);
+    }
+
+    pub fn build_castore(&mut self) {
+        self.emit(Opcode::Castore);
+    }
+
+    pub fn build_checkcast(&mut self, class: u16) {
+        let (high, low) = compute_byte(class);
+        self.emit(Opcode::Checkcast(high, low));
+    }
+
+    pub fn build_d2f(&mut self) {
+        self.emit(Opcode::D2f);
+    }
+
+    pub fn build_d2i(&mut self) {
+        self.emit(Opcode::D2i);
+    }
+
+    pub fn build_d2l(&mut self) {
+        self.emit(Opcode::D2l);
+    }
+
+    pub fn build_dadd(&mut self) {
+        self.emit(Opcode::Dadd);
+    }
+
+    pub fn build_daload(&mut self) {
+        self.emit(Opcode::Daload);
+    }
+
+    pub fn build_dastore(&mut self) {
+        self.emit(Opcode::Dastore);
+    }
+
+    pub fn build_dcmpg(&mut self) {
+        self.emit(Opcode::Dcmpg);
+    }
+
+    pub fn build_dcmpl(&mut self) {
+        self.emit(Opcode::Dcmpl);
+    }
+
+    pub fn build_dconst_d(&mut self, d: u8) -> Result<(), BuilderError> {
+        self.emit_n(Opcode::DconstD, d, 1)
+    }
+
+    pub fn build_ddiv(&mut self) {
+        self.emit(Opcode::Ddiv);
+    }
+
+    /// Emits `wide dload` when `local` doesn't fit in a byte.
+    pub fn build_dload(&mut self, local: u16) {
+        self.emit_local(Opcode::Dload, DLOAD, local);
+    }
+
+    pub fn build_dload_n(&mut self, n: u8) -> Result<(), BuilderError> {
+        self.emit_n(Opcode::DloadN, n, 3)
+    }
+
+    pub fn build_dmul(&mut self) {
+        self.emit(Opcode::Dmul);
+    }
+
+    pub fn build_dneg(&mut self) {
+        self.emit(Opcode::Dneg);
+    }
+
+    pub fn build_drem(&mut self) {
+        self.emit(Opcode::Drem);
+    }
+
+    pub fn build_dreturn(&mut self) -> Result<(), BuilderError> {
+        self.emit_return(Opcode::Dreturn)
+    }
+
+    /// Emits `wide dstore` when `local` doesn't fit in a byte.
+    pub fn build_dstore(&mut self, local: u16) {
+        self.emit_local(Opcode::Dstore, DSTORE, local);
+    }
+
+    pub fn build_dstore_n(&mut self, n: u8) -> Result<(), BuilderError> {
+        self.emit_n(Opcode::DstoreN, n, 3)
+    }
+
+    pub fn build_dsub(&mut self) {
+        self.emit(Opcode::Dsub);
+    }
+
+    pub fn build_dup(&mut self) {
+        self.emit(Opcode::Dup);
+    }
+
+    pub fn build_dup2(&mut self) {
+        self.emit(Opcode::Dup2);
+    }
+
+    pub fn build_dup2_x1(&mut self) {
+        self.emit(Opcode::Dup2X1);
+    }
+
+    pub fn build_dup2_x2(&mut self) {
+        self.emit(Opcode::Dup2X2);
+    }
+
+    pub fn build_dup_x1(&mut self) {
+        self.emit(Opcode::DupX1);
+    }
+
+    pub fn build_dup_x2(&mut self) {
+        self.emit(Opcode::DupX2);
+    }
+
+    pub fn build_f2d(&mut self) {
+        self.emit(Opcode::F2d);
+    }
+
+    pub fn build_f2i(&mut self) {
+        self.emit(Opcode::F2i);
+    }
+
+    pub fn build_f2l(&mut self) {
+        self.emit(Opcode::F2l);
+    }
+
+    pub fn build_fadd(&mut self) {
+        self.emit(Opcode::Fadd);
+    }
+
+    pub fn build_faload(&mut self) {
+        self.emit(Opcode::Faload);
+    }
+
+    pub fn build_fastore(&mut self) {
+        self.emit(Opcode::Fastore);
+    }
+
+    pub fn build_fcmpg(&mut self) {
+        self.emit(Opcode::Fcmpg);
+    }
+
+    pub fn build_fcmpl(&mut self) {
+        self.emit(Opcode::Fcmpl);
+    }
+
+    pub fn build_fconst_f(&mut self, f: u8) -> Result<(), BuilderError> {
+        self.emit_n(Opcode::FconstF, f, 2)
+    }
+
+    pub fn build_fdiv(&mut self) {
+        self.emit(Opcode::Fdiv);
+    }
+
+    /// Emits `wide fload` when `local` doesn't fit in a byte.
+    pub fn build_fload(&mut self, local: u16) {
+        self.emit_local(Opcode::Fload, FLOAD, local);
+    }
+
+    pub fn build_fload_n(&mut self, n: u8) -> Result<(), BuilderError> {
+        self.emit_n(Opcode::FloadN, n, 3)
+    }
+
+    pub fn build_fmul(&mut self) {
+        self.emit(Opcode::Fmul);
+    }
+
+    pub fn build_fneg(&mut self) {
+        self.emit(Opcode::Fneg);
+    }
+
+    pub fn build_frem(&mut self) {
+        self.emit(Opcode::Frem);
+    }
+
+    pub fn build_freturn(&mut self) -> Result<(), BuilderError> {
+        self.emit_return(Opcode::Freturn)
+    }
+
+    /// Emits `wide fstore` when `local` doesn't fit in a byte.
+    pub fn build_fstore(&mut self, local: u16) {
+        self.emit_local(Opcode::Fstore, FSTORE, local);
+    }
+
+    pub fn build_fstore_n(&mut self, n: u8) -> Result<(), BuilderError> {
+        self.emit_n(Opcode::FstoreN, n, 3)
+    }
+
+    pub fn build_fsub(&mut self) {
+        self.emit(Opcode::Fsub);
+    }
+
+    pub fn build_getfield(&mut self, field: u16) {
+        let (high, low) = compute_byte(field);
+        self.emit(Opcode::Getfield(high, low));
+    }
+
+    pub fn build_getstatic(&mut self, field: u16) {
+        let (high, low) = compute_byte(field);
+        self.emit(Opcode::Getstatic(high, low));
+    }
+
+    pub fn build_goto(&mut self, target: Label) {
+        self.emit_branch(Opcode::Goto(0, 0), vec![target]);
+    }
+
+    pub fn build_goto_w(&mut self, target: Label) {
+        self.emit_branch(Opcode::GotoW(0, 0, 0, 0), vec![target]);
+    }
+
+    pub fn build_i2b(&mut self) {
+        self.emit(Opcode::I2b);
+    }
+
+    pub fn build_i2c(&mut self) {
+        self.emit(Opcode::I2c);
+    }
+
+    pub fn build_i2d(&mut self) {
+        self.emit(Opcode::I2d);
+    }
+
+    pub fn build_i2f(&mut self) {
+        self.emit(Opcode::I2f);
+    }
+
+    pub fn build_i2l(&mut self) {
+        self.emit(Opcode::I2l);
+    }
+
+    pub fn build_i2s(&mut self) {
+        self.emit(Opcode::I2s);
+    }
+
+    pub fn build_iadd(&mut self) {
+        self.emit(Opcode::Iadd);
+    }
+
+    pub fn build_iaload(&mut self) {
+        self.emit(Opcode::Iaload);
+    }
+
+    pub fn build_iand(&mut self) {
+        self.emit(Opcode::Iand);
+    }
+
+    pub fn build_iastore(&mut self) {
+        self.emit(Opcode::Iastore);
+    }
+
+    pub fn build_iconst_i(&mut self, i: i8) -> Result<(), BuilderError> {
+        if !(-1..=5).contains(&i) {
+            return Err(BuilderError::InvalidOperand(Opcode::IconstI(i)));
+        }
+        self.emit(Opcode::IconstI(i));
+        Ok(())
+    }
+
+    pub fn build_idiv(&mut self) {
+        self.emit(Opcode::Idiv);
+    }
+
+    pub fn build_if_acmpeq(&mut self, target: Label) {
+        self.emit_branch(Opcode::IfAcmCond(Cond::Eq, 0, 0), vec![target]);
+    }
+
+    pub fn build_if_acmpne(&mut self, target: Label) {
+        self.emit_branch(Opcode::IfAcmCond(Cond::Ne, 0, 0), vec![target]);
+    }
+
+    pub fn build_ifeq(&mut self, target: Label) {
+        self.emit_branch(Opcode::ICOND(Cond::Eq, 0, 0), vec![target]);
+    }
+
+    pub fn build_ifge(&mut self, target: Label) {
+        self.emit_branch(Opcode::ICOND(Cond::Ge, 0, 0), vec![target]);
+    }
+
+    pub fn build_ifgt(&mut self, target: Label) {
+        self.emit_branch(Opcode::ICOND(Cond::Gt, 0, 0), vec![target]);
+    }
+
+    pub fn build_if_icmpeq(&mut self, target: Label) {
+        self.emit_branch(Opcode::IfIcmCond(Cond::Eq, 0, 0), vec![target]);
+    }
+
+    pub fn build_if_icmpge(&mut self, target: Label) {
+        self.emit_branch(Opcode::IfIcmCond(Cond::Ge, 0, 0), vec![target]);
+    }
+
+    pub fn build_if_icmpgt(&mut self, target: Label) {
+        self.emit_branch(Opcode::IfIcmCond(Cond::Gt, 0, 0), vec![target]);
+    }
+
+    pub fn build_if_icmple(&mut self, target: Label) {
+        self.emit_branch(Opcode::IfIcmCond(Cond::Le, 0, 0), vec![target]);
+    }
+
+    pub fn build_if_icmplt(&mut self, target: Label) {
+        self.emit_branch(Opcode::IfIcmCond(Cond::Lt, 0, 0), vec![target]);
+    }
+
+    pub fn build_if_icmpne(&mut self, target: Label) {
+        self.emit_branch(Opcode::IfIcmCond(Cond::Ne, 0, 0), vec![target]);
+    }
+
+    pub fn build_ifle(&mut self, target: Label) {
+        self.emit_branch(Opcode::ICOND(Cond::Le, 0, 0), vec![target]);
+    }
+
+    pub fn build_iflt(&mut self, target: Label) {
+        self.emit_branch(Opcode::ICOND(Cond::Lt, 0, 0), vec![target]);
+    }
+
+    pub fn build_ifne(&mut self, target: Label) {
+        self.emit_branch(Opcode::ICOND(Cond::Ne, 0, 0), vec![target]);
+    }
+
+    pub fn build_ifnonnull(&mut self, target: Label) {
+        self.emit_branch(Opcode::Ifnonnull(0, 0), vec![target]);
+    }
+
+    pub fn build_ifnull(&mut self, target: Label) {
+        self.emit_branch(Opcode::Ifnull(0, 0), vec![target]);
+    }
+
+    /// Emits `wide iinc` when `local` or `value` doesn't fit in a byte.
+    pub fn build_iinc(&mut self, local: u16, value: i16) {
+        match (u8::try_from(local), i8::try_from(value)) {
+            (Ok(local), Ok(value)) => self.emit(Opcode::Iinc(local, value as u8)),
+            _ => {
+                let (index1, index2) = compute_byte(local);
+                let [const1, const2] = value.to_be_bytes();
+                self.emit(Opcode::WideIinc(index1, index2, const1, const2))
+            }
+        };
+    }
+
+    /// Emits `wide iload` when `local` doesn't fit in a byte.
+    pub fn build_iload(&mut self, local: u16) {
+        self.emit_local(Opcode::Iload, ILOAD, local);
+    }
+
+    pub fn build_iload_n(&mut self, n: u8) -> Result<(), BuilderError> {
+        self.emit_n(Opcode::IloadN, n, 3)
+    }
+
+    pub fn build_imul(&mut self) {
+        self.emit(Opcode::Imul);
+    }
+
+    pub fn build_ineg(&mut self) {
+        self.emit(Opcode::Ineg);
+    }
+
+    pub fn build_instanceof(&mut self, class: u16) {
+        let (high, low) = compute_byte(class);
+        self.emit(Opcode::Instanceof(high, low));
+    }
+
+    pub fn build_invokedynamic(&mut self, call_site: u16) {
+        let (high, low) = compute_byte(call_site);
+        self.emit(Opcode::Invokedynamic(high, low, 0, 0));
+    }
+
+    /// Emits `invokeinterface` of `method`, an `InterfaceMethodref`, with the
+    /// count of argument slots taken from its descriptor.
+    pub fn build_invokeinterface(&mut self, method: u16) -> Result<(), BuilderError> {
+        let count = {
+            let class = self.class.borrow();
+            let descriptor = match class.constant_pool.get(method).map(|cp| &cp.info) {
+                Some(CpInfoType::InterfaceMethodref {
+                    name_and_type_index,
+                    ..
+                }) => class.constant_pool.get_name_and_type(*name_and_type_index),
+                _ => None,
+            };
+            let slots = descriptor
+                .and_then(|(_, descriptor)| Descriptor::parse_method(descriptor).ok())
+                .and_then(|descriptor| descriptor.argument_slots(false));
+            match slots.and_then(|slots| u8::try_from(slots).ok()) {
+                Some(count) => count,
+                None => return Err(BuilderError::InvalidConstant(method)),
+            }
+        };
+        let (high, low) = compute_byte(method);
+        self.emit(Opcode::Invokeinterface(high, low, count, 0));
+        Ok(())
+    }
+
+    /// Emits `invokespecial` of `method`, a `Methodref`, or an
+    /// `InterfaceMethodref` for a private interface method or a default method
+    /// called through `super`.
+    pub fn build_invokespecial(&mut self, method: u16) {
+        let (high, low) = compute_byte(method);
+        self.emit(Opcode::Invokespecial(high, low));
+    }
+
+    /// Emits `invokestatic` of `method`, a `Methodref`, or an
+    /// `InterfaceMethodref` for a static interface method.
+    pub fn build_invokestatic(&mut self, method: u16) {
+        let (high, low) = compute_byte(method);
+        self.emit(Opcode::Invokestatic(high, low));
+    }
+
+    pub fn build_invokevirtual(&mut self, method: u16) {
+        let (high, low) = compute_byte(method);
+        self.emit(Opcode::Invokevirtual(high, low));
+    }
+
+    pub fn build_ior(&mut self) {
+        self.emit(Opcode::Ior);
+    }
+
+    pub fn build_irem(&mut self) {
+        self.emit(Opcode::Irem);
+    }
+
+    pub fn build_ireturn(&mut self) -> Result<(), BuilderError> {
+        self.emit_return(Opcode::Ireturn)
+    }
+
+    pub fn build_ishl(&mut self) {
+        self.emit(Opcode::Ishl);
+    }
+
+    pub fn build_ishr(&mut self) {
+        self.emit(Opcode::Ishr);
+    }
+
+    /// Emits `wide istore` when `local` doesn't fit in a byte.
+    pub fn build_istore(&mut self, local: u16) {
+        self.emit_local(Opcode::Istore, ISTORE, local);
+    }
+
+    pub fn build_istore_n(&mut self, n: u8) -> Result<(), BuilderError> {
+        self.emit_n(Opcode::IstoreN, n, 3)
+    }
+
+    pub fn build_isub(&mut self) {
+        self.emit(Opcode::Isub);
+    }
+
+    pub fn build_iushr(&mut self) {
+        self.emit(Opcode::Iushr);
+    }
+
+    pub fn build_ixor(&mut self) {
+        self.emit(Opcode::Ixor);
+    }
+
+    pub fn build_jsr(&mut self, target: Label) {
+        self.emit_branch(Opcode::Jsr(0, 0), vec![target]);
+    }
+
+    pub fn build_jsr_w(&mut self, target: Label) {
+        self.emit_branch(Opcode::JsrW(0, 0, 0, 0), vec![target]);
+    }
+
+    pub fn build_l2d(&mut self) {
+        self.emit(Opcode::L2d);
+    }
+
+    pub fn build_l2f(&mut self) {
+        self.emit(Opcode::L2f);
+    }
+
+    pub fn build_l2i(&mut self) {
+        self.emit(Opcode::L2i);
+    }
+
+    pub fn build_ladd(&mut self) {
+        self.emit(Opcode::Ladd);
+    }
+
+    pub fn build_laload(&mut self) {
+        self.emit(Opcode::Laload);
+    }
+
+    pub fn build_land(&mut self) {
+        self.emit(Opcode::Land);
+    }
+
+    pub fn build_lastore(&mut self) {
+        self.emit(Opcode::Lastore);
+    }
+
+    pub fn build_lcmp(&mut self) {
+        self.emit(Opcode::Lcmp);
+    }
+
+    pub fn build_lconst_l(&mut self, l: u8) -> Result<(), BuilderError> {
+        self.emit_n(Opcode::LconstL, l, 1)
+    }
+
+    /// Emits `ldc`, or `ldc_w` when `constant` doesn't fit in a byte.
+    pub fn build_ldc(&mut self, constant: u16) {
+        match u8::try_from(constant) {
+            Ok(constant) => self.emit(Opcode::Ldc(constant)),
+            Err(_) => self.build_ldc_w(constant),
+        }
+    }
+
+    pub fn build_ldc2_w(&mut self, constant: u16) {
+        let (high, low) = compute_byte(constant);
+        self.emit(Opcode::Ldc2W(high, low));
+    }
+
+    pub fn build_ldc_w(&mut self, constant: u16) {
+        let (high, low) = compute_byte(constant);
+        self.emit(Opcode::LdcW(high, low));
+    }
+
+    pub fn build_ldiv(&mut self) {
+        self.emit(Opcode::Ldiv);
+    }
+
+    /// Emits `wide lload` when `local` doesn't fit in a byte.
+    pub fn build_lload(&mut self, local: u16) {
+        self.emit_local(Opcode::Lload, LLOAD, local);
+    }
+
+    pub fn build_lload_n(&mut self, n: u8) -> Result<(), BuilderError> {
+        self.emit_n(Opcode::LloadN, n, 3)
+    }
+
+    pub fn build_lmul(&mut self) {
+        self.emit(Opcode::Lmul);
+    }
+
+    pub fn build_lneg(&mut self) {
+        self.emit(Opcode::Lneg);
+    }
+
+    /// Emits a `lookupswitch` jumping to the label of the matching key, or to
+    /// `default`. The cases may be given in any order.
+    pub fn build_lookupswitch(
+        &mut self,
+        default: Label,
+        cases: &[(i32, Label)],
+    ) -> Result<(), BuilderError> {
+        let mut cases = cases.to_vec();
+        cases.sort_by_key(|(key, _)| *key);
+        if let Some(pair) = cases.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(BuilderError::DuplicateSwitchKey(pair[0].0));
+        }
+        let pairs = cases.iter().map(|(key, _)| (*key, 0)).collect();
+        let targets = std::iter::once(default)
+            .chain(cases.iter().map(|(_, label)| *label))
+            .collect();
+        self.emit_branch(Opcode::Lookupswitch(0, pairs), targets);
+        Ok(())
+    }
+
+    pub fn build_lor(&mut self) {
+        self.emit(Opcode::Lor);
+    }
+
+    pub fn build_lrem(&mut self) {
+        self.emit(Opcode::Lrem);
+    }
+
+    pub fn build_lreturn(&mut self) -> Result<(), BuilderError> {
+        self.emit_return(Opcode::Lreturn)
+    }
+
+    pub fn build_lshl(&mut self) {
+        self.emit(Opcode::Lshl);
+    }
+
+    pub fn build_lshr(&mut self) {
+        self.emit(Opcode::Lshr);
+    }
+
+    /// Emits `wide lstore` when `local` doesn't fit in a byte.
+    pub fn build_lstore(&mut self, local: u16) {
+        self.emit_local(Opcode::Lstore, LSTORE, local);
+    }
+
+    pub fn build_lstore_n(&mut self, n: u8) -> Result<(), BuilderError> {
+        self.emit_n(Opcode::LstoreN, n, 3)
+    }
+
+    pub fn build_lsub(&mut self) {
+        self.emit(Opcode::Lsub);
+    }
+
+    pub fn build_lushr(&mut self) {
+        self.emit(Opcode::Lushr);
+    }
+
+    pub fn build_lxor(&mut self) {
+        self.emit(Opcode::Lxor);
+    }
+
+    pub fn build_monitorenter(&mut self) {
+        self.emit(Opcode::Monitorenter);
+    }
+
+    pub fn build_monitorexit(&mut self) {
+        self.emit(Opcode::Monitorexit);
+    }
+
+    pub fn build_multianewarray(&mut self, class: u16, dimensions: u8) {
+        let (high, low) = compute_byte(class);
+        self.emit(Opcode::Multianewarray(high, low, dimensions));
+    }
+
+    pub fn build_new(&mut self, class: u16) {
+        let (high, low) = compute_byte(class);
+        self.emit(Opcode::New(high, low));
+    }
+
+    pub fn build_newarray(&mut self, element_type: ArrayType) {
+        self.emit(Opcode::Newarray(element_type as u8));
+    }
+
+    pub fn build_nop(&mut self) {
+        self.emit(Opcode::Nop);
+    }
+
+    pub fn build_pop(&mut self) {
+        self.emit(Opcode::Pop);
+    }
+
+    pub fn build_pop2(&mut self) {
+        self.emit(Opcode::Pop2);
+    }
+
+    pub fn build_putfield(&mut self, field: u16) {
+        let (high, low) = compute_byte(field);
+        self.emit(Opcode::Putfield(high, low));
+    }
+
+    pub fn build_putstatic(&mut self, field: u16) {
+        let (high, low) = compute_byte(field);
+        self.emit(Opcode::Putstatic(high, low));
+    }
+
+    /// Emits `wide ret` when `local` doesn't fit in a byte.
+    pub fn build_ret(&mut self, local: u16) {
+        self.emit_local(Opcode::Ret, RET, local);
+    }
+
+    pub fn build_return(&mut self) -> Result<(), BuilderError> {
+        self.emit_return(Opcode::Return_)
+    }
+
+    pub fn build_saload(&mut self) {
+        self.emit(Opcode::Saload);
+    }
+
+    pub fn build_sastore(&mut self) {
+        self.emit(Opcode::Sastore);
+    }
+
+    pub fn build_sipush(&mut self, value: i16) {
+        let [high, low] = value.to_be_bytes();
+        self.emit(Opcode::Sipush(high, low));
+    }
+
+    pub fn build_swap(&mut self) {
+        self.emit(Opcode::Swap);
+    }
+
+    /// Emits a `tableswitch` jumping to `targets[key - low]`, or to `default`
+    /// when the key is out of range.
+    pub fn build_tableswitch(
+        &mut self,
+        low: i32,
+        default: Label,
+        targets: &[Label],
+    ) -> Result<(), BuilderError> {
+        let high = i32::try_from(targets.len())
+            .ok()
+            .filter(|count| *count > 0)
+            .and_then(|count| low.checked_add(count - 1))
+            .ok_or(BuilderError::InvalidTableswitch {
+                low,
+                count: targets.len(),
+            })?;
+        let offsets = vec![0; targets.len()];
+        let targets = std::iter::once(default)
+            .chain(targets.iter().copied())
+            .collect();
+        self.emit_branch(Opcode::Tableswitch(0, low, high, offsets), targets);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::ClassBuilder;
+    use crate::bytecode::{
+        AttributeInfoKind, ClassFile, ExceptionTable, LineNumberTable, LocalVariableTable,
+        LocalVariableTypeTable, METHOD_ACC_STATIC, PUBLIC, SUPER,
+    };
+
+    fn new_class() -> ClassBuilder {
+        let mut builder = ClassBuilder::new();
+        builder.set_access_flags(PUBLIC | SUPER);
+        builder.set_class_name("Finally").unwrap();
+        let object = builder.load_class("java/lang/Object").unwrap();
+        builder.set_super_class(object);
+        builder
+    }
+
+    fn new_method(builder: &mut ClassBuilder) -> MethodBuilder {
+        let descriptor = Descriptor::Function(vec![Descriptor::Int], Box::new(Descriptor::Void));
+        builder
+            .method("run", descriptor, METHOD_ACC_STATIC)
+            .unwrap()
+    }
+
+    fn code(class_file: &ClassFile) -> Vec<Opcode> {
+        class_file.method_info[0]
+            .attributes
+            .iter()
+            .find_map(|a| match &a.info {
+                AttributeInfoKind::Code { code, .. } => Some(code.clone()),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    fn exception_table(class_file: &ClassFile) -> Vec<ExceptionTable> {
+        class_file.method_info[0]
+            .attributes
+            .iter()
+            .find_map(|a| match &a.info {
+                AttributeInfoKind::Code {
+                    exception_table, ..
+                } => Some(exception_table.clone()),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn exception_handlers_are_resolved_to_pcs() {
+        let mut builder = new_class();
+        let arithmetic = builder.load_class("java/lang/ArithmeticException").unwrap();
+        let mut method = new_method(&mut builder);
+        let [start, end, caught, any, done] = [(); 5].map(|_| method.new_label());
+        method.place_label(start).unwrap();
+        method.build_iconst_i(1).unwrap(); // 0
+        method.build_iload_n(0).unwrap(); // 1
+        method.build_idiv(); // 2
+        method.build_pop(); // 3
+        method.place_label(end).unwrap();
+        method.build_goto(done); // 4
+        method.place_label(caught).unwrap();
+        method.build_pop(); // 7
+        method.build_goto(done); // 8
+        method.place_label(any).unwrap();
+        method.build_athrow(); // 11
+        method.place_label(done).unwrap();
+        method.build_return().unwrap(); // 12
+        method.add_exception_handler(start, end, caught, Some(arithmetic));
+        method.add_exception_handler(start, end, any, None);
+        let class_file = builder.build().unwrap();
+        let entry = |handler_pc, catch_type| ExceptionTable {
+            start_pc: 0,
+            end_pc: 4,
+            handler_pc,
+            catch_type,
+        };
+        assert_eq!(
+            exception_table(&class_file),
+            [entry(7, arithmetic), entry(11, 0)]
+        );
+    }
+
+    #[test]
+    fn exception_handlers_need_placed_labels() {
+        let mut builder = new_class();
+        let mut method = new_method(&mut builder);
+        let [start, end, handler] = [(); 3].map(|_| method.new_label());
+        method.place_label(start).unwrap();
+        method.build_return().unwrap();
+        method.place_label(handler).unwrap();
+        method.build_athrow();
+        method.add_exception_handler(start, end, handler, None);
+        assert_eq!(builder.build(), Err(BuilderError::LabelNotPlaced(end)));
+    }
+
+    #[test]
+    fn try_finally_rejects_branches_leaving_the_body() {
+        let mut builder = new_class();
+        let mut method = new_method(&mut builder);
+        let outside = method.new_label();
+        let result = method.build_try_finally(
+            |m| {
+                m.build_iload_n(0)?;
+                m.build_ifeq(outside);
+                Ok(())
+            },
+            |m| {
+                m.build_iinc(0, 1);
+                Ok(())
+            },
+        );
+        assert_eq!(result, Err(BuilderError::JumpOutOfFinally(outside)));
+    }
+
+    #[test]
+    fn try_finally_allows_branches_inside_the_body() {
+        let mut builder = new_class();
+        let mut method = new_method(&mut builder);
+        method
+            .build_try_finally(
+                |m| {
+                    let skip = m.new_label();
+                    m.build_iload_n(0)?;
+                    m.build_ifeq(skip);
+                    m.build_return()?;
+                    m.place_label(skip)
+                },
+                |m| {
+                    m.build_iinc(0, 1);
+                    Ok(())
+                },
+            )
+            .unwrap();
+        method.build_return().unwrap();
+        let code = code(&builder.build().unwrap());
+        // The finally code runs before the early return, on fall-through and
+        // in the handler.
+        let finally_runs = code
+            .iter()
+            .filter(|op| matches!(op, Opcode::Iinc(0, 1)))
+            .count();
+        assert_eq!(finally_runs, 3);
+    }
+
+    #[test]
+    fn branches_are_encoded_relative_to_their_instruction() {
+        let mut builder = new_class();
+        let mut method = new_method(&mut builder);
+        let top = method.new_label();
+        let end = method.new_label();
+        method.place_label(top).unwrap();
+        method.build_iload_n(0).unwrap();
+        method.build_ifeq(end);
+        method.build_iinc(0, -1);
+        method.build_goto(top);
+        method.place_label(end).unwrap();
+        method.build_return().unwrap();
+        let code = code(&builder.build().unwrap());
+        // iload_0 at 0, ifeq at 1, iinc at 4, goto at 7 and return at 10.
+        assert_eq!(code[1], Opcode::ICOND(Cond::Eq, 0, 9));
+        assert_eq!(code[3], Opcode::Goto(0xff, 0xf9));
+    }
+
+    #[test]
+    fn branches_beyond_a_short_offset_need_goto_w() {
+        let build = |wide: bool| {
+            let mut builder = new_class();
+            let mut method = new_method(&mut builder);
+            let end = method.new_label();
+            if wide {
+                method.build_goto_w(end);
+            } else {
+                method.build_goto(end);
+            }
+            for _ in 0..i16::MAX {
+                method.build_nop();
+            }
+            method.place_label(end).unwrap();
+            method.build_return().unwrap();
+            builder.build()
+        };
+        assert_eq!(
+            build(false).unwrap_err(),
+            BuilderError::BranchOutOfRange {
+                pc: 0,
+                offset: 3 + i16::MAX as i32
+            }
+        );
+        let code = code(&build(true).unwrap());
+        let offset = (5 + i16::MAX as i32).to_be_bytes();
+        assert_eq!(
+            code[0],
+            Opcode::GotoW(offset[0], offset[1], offset[2], offset[3])
+        );
+    }
+
+    #[test]
+    fn methods_can_be_built_in_any_order() {
+        let mut builder = new_class();
+        let int = Descriptor::Function(vec![], Box::new(Descriptor::Int));
+        let mut caller = builder
+            .method("caller", int.clone(), METHOD_ACC_STATIC)
+            .unwrap();
+        let mut lambda = builder
+            .method("lambda", int.clone(), METHOD_ACC_STATIC)
+            .unwrap();
+        let this_class = builder.load_class("Finally").unwrap();
+        let lambda_ref = builder.load_method(this_class, "lambda", int).unwrap();
+        caller.build_invokestatic(lambda_ref);
+        lambda.build_iconst_i(3).unwrap();
+        lambda.build_ireturn().unwrap();
+        caller.build_ireturn().unwrap();
+        let class_file = builder.build().unwrap();
+        let code = |index: usize| {
+            class_file.method_info[index]
+                .attributes
+                .iter()
+                .find_map(|a| match &a.info {
+                    AttributeInfoKind::Code { code, .. } => Some(code.clone()),
+                    _ => None,
+                })
+                .unwrap()
+        };
+        let [high, low] = lambda_ref.to_be_bytes();
+        assert_eq!(code(0), [Opcode::Invokestatic(high, low), Opcode::Ireturn]);
+        assert_eq!(code(1), [Opcode::IconstI(3), Opcode::Ireturn]);
+    }
+
+    #[test]
+    fn invalid_operands_are_errors() {
+        let mut builder = new_class();
+        let mut method = new_method(&mut builder);
+        assert_eq!(
+            method.build_iconst_i(6),
+            Err(BuilderError::InvalidOperand(Opcode::IconstI(6)))
+        );
+        assert_eq!(
+            method.build_iload_n(4),
+            Err(BuilderError::InvalidOperand(Opcode::IloadN(4)))
+        );
+        let label = method.new_label();
+        method.place_label(label).unwrap();
+        assert_eq!(
+            method.place_label(label),
+            Err(BuilderError::LabelPlacedTwice(label))
+        );
+    }
+
+    #[test]
+    fn push_int_uses_the_shortest_instruction() {
+        let mut builder = new_class();
+        let mut method = new_method(&mut builder);
+        for value in [-1, 5, 6, -128, 128, -32768, 32768] {
+            method.push_int(value).unwrap();
+        }
+        let constant = builder.constant_pool().count();
+        assert_eq!(
+            method.state().code,
+            [
+                Opcode::IconstI(-1),
+                Opcode::IconstI(5),
+                Opcode::Bipush(6),
+                Opcode::Bipush(0x80),
+                Opcode::Sipush(0, 0x80),
+                Opcode::Sipush(0x80, 0),
+                Opcode::Ldc(constant as u8),
+            ]
+        );
+    }
+
+    #[test]
+    fn push_picks_constants_and_their_load_instruction() {
+        let mut builder = new_class();
+        let mut method = new_method(&mut builder);
+        method.push_long(1).unwrap();
+        method.push_float(2.0).unwrap();
+        method.push_double(-0.0).unwrap();
+        method.push_null();
+        let double = builder.constant_pool_mut().double(-0.0).unwrap();
+        let [high, low] = double.to_be_bytes();
+        assert_eq!(
+            method.state().code,
+            [
+                Opcode::LconstL(1),
+                Opcode::FconstF(2),
+                Opcode::Ldc2W(high, low),
+                Opcode::AconstNull,
+            ]
+        );
+    }
+
+    #[test]
+    fn push_uses_ldc_w_past_index_255() {
+        let mut builder = new_class();
+        let mut method = new_method(&mut builder);
+        method.push_string("low").unwrap();
+        for value in 0..300 {
+            builder
+                .constant_pool_mut()
+                .integer(1000000 + value)
+                .unwrap();
+        }
+        method.push_string("high").unwrap();
+        method.push_class("[I").unwrap();
+        method.push_float(0.5).unwrap();
+        let mut pool = builder.constant_pool_mut();
+        let [low, high, class, float] = [
+            pool.string("low").unwrap(),
+            pool.string("high").unwrap(),
+            pool.class("[I").unwrap(),
+            pool.float(0.5).unwrap(),
+        ];
+        drop(pool);
+        assert!(low <= 255 && high > 255);
+        let wide = |index: u16| {
+            let [high, low] = index.to_be_bytes();
+            Opcode::LdcW(high, low)
+        };
+        assert_eq!(
+            method.state().code,
+            [Opcode::Ldc(low as u8), wide(high), wide(class), wide(float)]
+        );
+        method.build_return().unwrap();
+        builder.build().unwrap();
+    }
+
+    #[test]
+    fn invokeinterface_counts_argument_slots() {
+        let mut builder = new_class();
+        let object = Descriptor::Object(ClassName::new("java/lang/Object").unwrap());
+        let descriptor = Descriptor::Function(
+            vec![Descriptor::Int, Descriptor::Long, object],
+            Box::new(Descriptor::Void),
+        );
+        let consumer = builder.load_class("Consumer").unwrap();
+        let accept = builder
+            .load_interface_method(consumer, "accept", descriptor.clone())
+            .unwrap();
+        let entry = builder.constant_pool().get(accept).unwrap().clone();
+        assert!(matches!(entry.info, CpInfoType::InterfaceMethodref { .. }));
+        let virtual_accept = builder.load_method(consumer, "accept", descriptor).unwrap();
+        let mut method = new_method(&mut builder);
+        method.build_invokeinterface(accept).unwrap();
+        method.build_invokestatic(accept);
+        assert_eq!(
+            method.build_invokeinterface(virtual_accept),
+            Err(BuilderError::InvalidConstant(virtual_accept))
+        );
+        let [high, low] = accept.to_be_bytes();
+        assert_eq!(
+            method.state().code,
+            [
+                Opcode::Invokeinterface(high, low, 5, 0),
+                Opcode::Invokestatic(high, low),
+            ]
+        );
+    }
+
+    #[test]
+    fn debug_information_is_resolved_to_pcs() {
+        let mut builder = new_class();
+        builder.set_source_file("Finally.java").unwrap();
+        let mut method = new_method(&mut builder);
+        let [start, end] = [(); 2].map(|_| method.new_label());
+        method.mark_line(3);
+        method.build_iconst_i(0).unwrap(); // 0
+        method.build_istore(1); // 1
+        method.place_label(start).unwrap();
+        method.mark_line(4);
+        method.mark_line(5);
+        method.build_iinc(1, 1); // 3
+        method.place_label(end).unwrap();
+        method.mark_line(6);
+        method.build_return().unwrap(); // 6
+        method.mark_line(7);
+        let list = "Ljava/util/List<Ljava/lang/String;>;";
+        let list_type = Descriptor::Object(ClassName::new("java/util/List").unwrap());
+        method
+            .declare_local("i", Descriptor::Int, start, end, 1)
+            .unwrap();
+        method
+            .declare_generic_local("names", list_type, list, start, end, 0)
+            .unwrap();
+        method
+            .declare_local("empty", Descriptor::Int, end, end, 2)
+            .unwrap();
+        assert_eq!(
+            method.declare_local("v", Descriptor::Void, start, end, 2),
+            Err(BuilderError::InvalidDescriptor("V".to_string()))
+        );
+        let class_file = builder.build().unwrap();
+        let pool = builder.constant_pool();
+        let utf8 = |string: &str| {
+            (1..pool.count())
+                .find(|i| pool.get_utf8(*i) == Some(string))
+                .unwrap()
+        };
+
+        let code_attributes = class_file.method_info[0]
+            .attributes
+            .iter()
+            .find_map(|a| match &a.info {
+                AttributeInfoKind::Code { attributes, .. } => Some(attributes.clone()),
+                _ => None,
+            })
+            .unwrap();
+        let mut lines = None;
+        let mut locals = None;
+        let mut local_types = None;
+        for attribute in code_attributes {
+            match attribute.info {
+                AttributeInfoKind::LineNumberTable {
+                    line_number_table, ..
+                } => lines = Some(line_number_table),
+                AttributeInfoKind::LocalVariableTable {
+                    local_variable_table,
+                    ..
+                } => locals = Some(local_variable_table),
+                AttributeInfoKind::LocalVariableTypeTable {
+                    local_variable_type_table,
+                    ..
+                } => local_types = Some(local_variable_type_table),
+                _ => {}
+            }
+        }
+        let line = |start_pc, line_number| LineNumberTable {
+            start_pc,
+            line_number,
+        };
+        // Line 4 marks no instruction and line 7 follows the last one.
+        assert_eq!(lines.unwrap(), [line(0, 3), line(3, 5), line(6, 6)]);
+        // A local whose range is empty is left out.
+        assert_eq!(
+            locals.unwrap(),
+            [
+                LocalVariableTable {
+                    start_pc: 3,
+                    length: 3,
+                    name_index: utf8("i"),
+                    descriptor_index: utf8("I"),
+                    index: 1,
+                },
+                LocalVariableTable {
+                    start_pc: 3,
+                    length: 3,
+                    name_index: utf8("names"),
+                    descriptor_index: utf8("Ljava/util/List;"),
+                    index: 0,
+                },
+            ]
+        );
+        assert_eq!(
+            local_types.unwrap(),
+            [LocalVariableTypeTable {
+                start_pc: 3,
+                length: 3,
+                name_index: utf8("names"),
+                signature_index: utf8(list),
+                index: 0,
+            }]
+        );
+        assert!(class_file.attributes.iter().any(
+            |a| matches!(a.info, AttributeInfoKind::SourceFile { sourcefile_index }
+                if class_file.get_utf8(sourcefile_index) == Some("Finally.java"))
+        ));
+    }
+}
@@ -0,0 +1,2071 @@
+use std::{
+    cell::{Ref, RefCell, RefMut},
+    convert::Infallible,
+    fmt,
+    rc::Rc,
+};
+
+use crate::{
+    bytecode::{instr::*, *},
+    constant_pool::{ConstantPool, ConstantPoolError},
+    descriptor::Descriptor,
+    flags::{AccessError, ClassAccess, FieldAccess, InnerClassAccess, MethodAccess},
+    frame::FrameError,
+    hierarchy::{ClassHierarchy, SimpleHierarchy},
+    name::{ClassName, FieldName, MethodName, NameError},
+    stackmap::compute_method_stack_map_tables,
+};
+
+mod annotation;
+mod control;
+mod field;
+mod method;
+mod template;
+
+pub use annotation::{AnnotationBuilder, AnnotationValue, Retention};
+pub use control::{Condition, Loop};
+pub use field::{ConstantValue, FieldBuilder};
+pub use method::MethodBuilder;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuilderError {
+    ConstantPool(ConstantPoolError),
+    /// The code of a method can't be verified.
+    Frame(FrameError),
+    /// A method was given a descriptor that isn't a method descriptor, or a
+    /// field one that isn't a field descriptor.
+    InvalidDescriptor(String),
+    /// A `ConstantValue` doesn't match the type of its field.
+    InvalidConstantValue {
+        descriptor: String,
+        value: ConstantValue,
+    },
+    /// An instruction operand is out of range, such as `iconst_<i>` with 6.
+    InvalidOperand(Opcode),
+    InvalidTableswitch {
+        low: i32,
+        count: usize,
+    },
+    DuplicateSwitchKey(i32),
+    /// A constant pool index refers to an entry of the wrong kind.
+    InvalidConstant(u16),
+    /// A call site refers to a missing `BootstrapMethods` entry.
+    InvalidBootstrapMethod(u16),
+    /// A parameter annotation refers to a parameter the method doesn't have.
+    InvalidParameter(u8),
+    /// Code was emitted in the named `abstract` or `native` method.
+    UnexpectedCode(String),
+    /// A `<clinit>` read from a class file returns at this pc, before its
+    /// end, so static initializer fragments can't be appended to it.
+    EarlyReturn(u16),
+    /// A class or member name breaks the JVM naming rules.
+    InvalidName(NameError),
+    /// The named class or member has flags the JVM rejects.
+    InvalidAccess {
+        name: String,
+        error: AccessError,
+    },
+    /// A member of the class was referred to before its name was set.
+    MissingClassName,
+    LabelPlacedTwice(Label),
+    LabelNotPlaced(Label),
+    /// A branch in the body of `MethodBuilder::build_try_finally` targets a
+    /// label outside of it, and would skip the finally code.
+    JumpOutOfFinally(Label),
+    /// A label was used in a method other than the one that created it.
+    ForeignLabel(Label),
+    BranchOutOfRange {
+        pc: usize,
+        offset: i32,
+    },
+    CodeTooLarge(usize),
+}
+
+impl fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuilderError::ConstantPool(e) => write!(f, "{}", e),
+            BuilderError::Frame(e) => write!(f, "invalid method code: {}", e),
+            BuilderError::InvalidDescriptor(d) => write!(f, "invalid descriptor {}", d),
+            BuilderError::InvalidConstantValue { descriptor, value } => write!(
+                f,
+                "constant value {:?} doesn't match field descriptor {}",
+                value, descriptor
+            ),
+            BuilderError::InvalidOperand(op) => write!(f, "invalid operand in {:?}", op),
+            BuilderError::InvalidTableswitch { low, count } => write!(
+                f,
+                "tableswitch with {} targets starting at {} is invalid",
+                count, low
+            ),
+            BuilderError::InvalidConstant(index) => {
+                write!(f, "constant pool entry {} can't be used here", index)
+            }
+            BuilderError::InvalidBootstrapMethod(index) => {
+                write!(f, "no bootstrap method at index {}", index)
+            }
+            BuilderError::InvalidParameter(parameter) => {
+                write!(f, "method has no parameter {}", parameter)
+            }
+            BuilderError::UnexpectedCode(name) => {
+                write!(f, "abstract or native method {} can't have code", name)
+            }
+            BuilderError::EarlyReturn(pc) => {
+                write!(f, "static initializer returns at pc {} before its end", pc)
+            }
+            BuilderError::InvalidName(e) => write!(f, "{}", e),
+            BuilderError::InvalidAccess { name, error } => {
+                write!(f, "invalid access flags of {}: {}", name, error)
+            }
+            BuilderError::DuplicateSwitchKey(key) => {
+                write!(f, "duplicate lookupswitch key {}", key)
+            }
+            BuilderError::MissingClassName => write!(f, "class name not set"),
+            BuilderError::LabelPlacedTwice(label) => write!(f, "{:?} placed twice", label),
+            BuilderError::LabelNotPlaced(label) => write!(f, "{:?} never placed", label),
+            BuilderError::JumpOutOfFinally(label) => {
+                write!(f, "jump to {:?} skips a finally block", label)
+            }
+            BuilderError::ForeignLabel(label) => {
+                write!(f, "{:?} used outside of its method", label)
+            }
+            BuilderError::BranchOutOfRange { pc, offset } => write!(
+                f,
+                "branch offset {} at pc {} out of range, use goto_w",
+                offset, pc
+            ),
+            BuilderError::CodeTooLarge(length) => {
+                write!(f, "method code of {} bytes exceeds 65535 bytes", length)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuilderError {}
+
+impl From<ConstantPoolError> for BuilderError {
+    fn from(e: ConstantPoolError) -> Self {
+        BuilderError::ConstantPool(e)
+    }
+}
+
+impl From<FrameError> for BuilderError {
+    fn from(e: FrameError) -> Self {
+        BuilderError::Frame(e)
+    }
+}
+
+impl From<NameError> for BuilderError {
+    fn from(e: NameError) -> Self {
+        BuilderError::InvalidName(e)
+    }
+}
+
+/// Lets a name that is already validated, such as a `ClassName`, be passed
+/// where a `&str` is checked.
+impl From<Infallible> for BuilderError {
+    fn from(e: Infallible) -> Self {
+        match e {}
+    }
+}
+
+/// A position in the code of a method, used as a branch target. Labels are
+/// created with `MethodBuilder::new_label` and bound with
+/// `MethodBuilder::place_label`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Label {
+    method: u16,
+    index: usize,
+}
+
+/// A branch instruction whose offsets are filled in once its targets are known.
+#[derive(Debug, Clone, PartialEq)]
+struct Fixup {
+    index: usize,
+    targets: Vec<Label>,
+}
+
+/// An exception table entry whose pcs are filled in once its labels are placed.
+#[derive(Debug, Clone, PartialEq)]
+struct Handler {
+    start: Label,
+    end: Label,
+    handler: Label,
+    catch_type: u16,
+}
+
+/// A local variable declared with `MethodBuilder::declare_local`, whose range
+/// is filled in once its labels are placed.
+#[derive(Debug, Clone, PartialEq)]
+struct LocalVariable {
+    name_index: u16,
+    descriptor_index: u16,
+    signature_index: Option<u16>,
+    start: Label,
+    end: Label,
+    slot: u16,
+}
+
+/// Code generation state of a method.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct MethodState {
+    /// The index of the method in `ClassFile::method_info`. Several states
+    /// refer to the static initializer, one for each fragment.
+    method: u16,
+    code: Vec<Opcode>,
+    argument_slots: u16,
+    /// The number of slots reserved for temporaries, which are allocated after
+    /// every other local once the whole method is known.
+    temp_slots: u16,
+    /// The index of the instruction each label is placed before, which is
+    /// `code.len()` for a label placed at the end.
+    labels: Vec<Option<usize>>,
+    fixups: Vec<Fixup>,
+    handlers: Vec<Handler>,
+    /// Loads and stores of temporaries, as the instruction index and slot
+    /// relative to the first temporary.
+    temps: Vec<(usize, u16)>,
+    /// The source line of the instructions from each index onwards.
+    lines: Vec<(usize, u16)>,
+    locals: Vec<LocalVariable>,
+}
+
+impl MethodState {
+    /// Returns the code of the builder `id` with its branch offsets and
+    /// temporaries filled in, along with its exception table and debug
+    /// information, for code starting at `start_pc`. Also returns the pc
+    /// following the code.
+    fn resolve(&self, id: u16, start_pc: usize) -> Result<ResolvedCode, BuilderError> {
+        let mut pcs = Vec::with_capacity(self.code.len() + 1);
+        let mut pc = start_pc;
+        for op in &self.code {
+            pcs.push(pc);
+            pc += op.size(pc);
+        }
+        pcs.push(pc);
+        if pc > u16::MAX as usize {
+            return Err(BuilderError::CodeTooLarge(pc));
+        }
+        let label_pc = |label: Label| {
+            if label.method != id {
+                return Err(BuilderError::ForeignLabel(label));
+            }
+            match self.labels[label.index] {
+                Some(index) => Ok(pcs[index] as u16),
+                None => Err(BuilderError::LabelNotPlaced(label)),
+            }
+        };
+        let mut code = self.code.clone();
+        for fixup in &self.fixups {
+            let pc = pcs[fixup.index];
+            let offsets = fixup
+                .targets
+                .iter()
+                .map(|label| Ok(label_pc(*label)? as i32 - pc as i32))
+                .collect::<Result<Vec<i32>, BuilderError>>()?;
+            set_branch_offsets(&mut code[fixup.index], pc, &offsets)?;
+        }
+        let mut exception_table = vec![];
+        for handler in &self.handlers {
+            let start_pc = label_pc(handler.start)?;
+            let end_pc = label_pc(handler.end)?;
+            let handler_pc = label_pc(handler.handler)?;
+            if start_pc < end_pc {
+                exception_table.push(ExceptionTable {
+                    start_pc,
+                    end_pc,
+                    handler_pc,
+                    catch_type: handler.catch_type,
+                });
+            }
+        }
+        // A line marked after the last instruction doesn't start any code.
+        let line_numbers = self
+            .lines
+            .iter()
+            .filter(|(index, _)| *index < self.code.len())
+            .map(|(index, line)| LineNumberTable {
+                start_pc: pcs[*index] as u16,
+                line_number: *line,
+            })
+            .collect();
+        let mut local_variables = vec![];
+        let mut local_variable_types = vec![];
+        for local in &self.locals {
+            let start_pc = label_pc(local.start)?;
+            let end_pc = label_pc(local.end)?;
+            if start_pc >= end_pc {
+                continue;
+            }
+            local_variables.push(LocalVariableTable {
+                start_pc,
+                length: end_pc - start_pc,
+                name_index: local.name_index,
+                descriptor_index: local.descriptor_index,
+                index: local.slot,
+            });
+            if let Some(signature_index) = local.signature_index {
+                local_variable_types.push(LocalVariableTypeTable {
+                    start_pc,
+                    length: end_pc - start_pc,
+                    name_index: local.name_index,
+                    signature_index,
+                    index: local.slot,
+                });
+            }
+        }
+        if !self.temps.is_empty() {
+            let first_temp = code
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !self.temps.iter().any(|t| t.0 == *i))
+                .filter_map(|(_, op)| local_slots(op))
+                .fold(self.argument_slots, u16::max);
+            for (index, temp) in &self.temps {
+                if let Opcode::Wide(_, b1, b2) = &mut code[*index] {
+                    [*b1, *b2] = (first_temp + temp).to_be_bytes();
+                }
+            }
+        }
+        Ok(ResolvedCode {
+            code,
+            exception_table,
+            line_numbers,
+            local_variables,
+            local_variable_types,
+            end_pc: pc,
+        })
+    }
+}
+
+/// The code of a method, or of the static initializer fragments so far.
+struct ResolvedCode {
+    code: Vec<Opcode>,
+    exception_table: Vec<ExceptionTable>,
+    line_numbers: Vec<LineNumberTable>,
+    local_variables: Vec<LocalVariableTable>,
+    local_variable_types: Vec<LocalVariableTypeTable>,
+    end_pc: usize,
+}
+
+impl ResolvedCode {
+    fn append(&mut self, other: ResolvedCode) {
+        self.code.extend(other.code);
+        self.exception_table.extend(other.exception_table);
+        self.line_numbers.extend(other.line_numbers);
+        self.local_variables.extend(other.local_variables);
+        self.local_variable_types.extend(other.local_variable_types);
+        self.end_pc = other.end_pc;
+    }
+}
+
+/// The state shared by a `ClassBuilder` and its method and field builders.
+#[derive(Debug, Clone, PartialEq)]
+struct ClassState {
+    class_file: ClassFile,
+    constant_pool: ConstantPool,
+    /// The code generation state of each method builder.
+    methods: Vec<MethodState>,
+    /// The index of the `<clinit>` method created by `static_initializer`.
+    static_initializer: Option<u16>,
+    /// The indices of the fields and methods removed or replaced, which are
+    /// only dropped by `build` so that the other indices stay valid.
+    removed_fields: Vec<u16>,
+    removed_methods: Vec<u16>,
+    /// The constants returned by `MethodBuilder::method_ref`, which must be
+    /// `InterfaceMethodref`s exactly when the built class is an interface.
+    method_refs: Vec<u16>,
+}
+
+impl ClassState {
+    /// Returns the index of the field named `name` with type `descriptor`.
+    fn find_field(&self, name: &str, descriptor: &str) -> Option<u16> {
+        let fields = self.class_file.fields.iter().enumerate();
+        fields
+            .filter(|(index, _)| !self.removed_fields.contains(&(*index as u16)))
+            .find(|(_, field)| {
+                self.constant_pool.get_utf8(field.name_index) == Some(name)
+                    && self.constant_pool.get_utf8(field.descriptor_index) == Some(descriptor)
+            })
+            .map(|(index, _)| index as u16)
+    }
+
+    /// Returns the index of the method named `name` with type `descriptor`.
+    fn find_method(&self, name: &str, descriptor: &str) -> Option<u16> {
+        let methods = self.class_file.method_info.iter().enumerate();
+        methods
+            .filter(|(index, _)| !self.removed_methods.contains(&(*index as u16)))
+            .find(|(_, method)| {
+                self.constant_pool.get_utf8(method.name_index) == Some(name)
+                    && self.constant_pool.get_utf8(method.descriptor_index) == Some(descriptor)
+            })
+            .map(|(index, _)| index as u16)
+    }
+
+    /// Returns the `this_class` constant, which refers to the class name.
+    fn this_class(&self) -> Result<u16, BuilderError> {
+        match self.class_file.this_class {
+            0 => Err(BuilderError::MissingClassName),
+            this_class => Ok(this_class),
+        }
+    }
+
+    /// Returns the name and descriptor of a member from their indices.
+    fn member_name(&self, name_index: u16, descriptor_index: u16) -> (String, String) {
+        let get = |index| self.constant_pool.get_utf8(index).unwrap_or_default();
+        (
+            get(name_index).to_string(),
+            get(descriptor_index).to_string(),
+        )
+    }
+
+    /// Returns the `Fieldref` constant referring to the field at `index`.
+    fn field_ref(&mut self, index: u16) -> Result<u16, BuilderError> {
+        let this_class = self.this_class()?;
+        let field = &self.class_file.fields[index as usize];
+        let (name, descriptor) = self.member_name(field.name_index, field.descriptor_index);
+        Ok(self
+            .constant_pool
+            .field_ref(this_class, &name, &descriptor)?)
+    }
+
+    /// Returns the constant referring to the method at `index`, which is an
+    /// `InterfaceMethodref` if the class is an interface.
+    fn method_ref(&mut self, index: u16) -> Result<u16, BuilderError> {
+        let this_class = self.this_class()?;
+        let method = &self.class_file.method_info[index as usize];
+        let (name, descriptor) = self.member_name(method.name_index, method.descriptor_index);
+        let pool = &mut self.constant_pool;
+        let method_ref = match self.class_file.access_flags.is_set(INTERFACE) {
+            true => pool.interface_method_ref(this_class, &name, &descriptor)?,
+            false => pool.method_ref(this_class, &name, &descriptor)?,
+        };
+        if !self.method_refs.contains(&method_ref) {
+            self.method_refs.push(method_ref);
+        }
+        Ok(method_ref)
+    }
+
+    /// Checks the flags of the class and its members against JVMS 4.1, 4.5
+    /// and 4.6, naming the first offender in the error. Removed and replaced
+    /// members aren't built, so they aren't checked.
+    fn check_access_flags(&self) -> Result<(), BuilderError> {
+        let (class_file, constant_pool) = (&self.class_file, &self.constant_pool);
+        let version = class_file.major_version;
+        let class_name = constant_pool
+            .get_class_name(class_file.this_class)
+            .unwrap_or_default();
+        let invalid = |name: String| move |error| BuilderError::InvalidAccess { name, error };
+        let flags = class_file.access_flags;
+        flags
+            .check(version)
+            .map_err(invalid(class_name.to_string()))?;
+        let in_interface = flags.is_set(INTERFACE);
+        let fields = class_file.fields.iter().enumerate();
+        for (_, field) in fields.filter(|(i, _)| !self.removed_fields.contains(&(*i as u16))) {
+            let name = constant_pool.get_utf8(field.name_index).unwrap_or_default();
+            field
+                .access_flags
+                .check(version, in_interface)
+                .map_err(invalid(format!("{}.{}", class_name, name)))?;
+        }
+        let methods = class_file.method_info.iter().enumerate();
+        for (_, method) in methods.filter(|(i, _)| !self.removed_methods.contains(&(*i as u16))) {
+            let name = constant_pool
+                .get_utf8(method.name_index)
+                .unwrap_or_default();
+            let descriptor = constant_pool
+                .get_utf8(method.descriptor_index)
+                .unwrap_or_default();
+            method
+                .access_flags
+                .check(name, version, in_interface)
+                .map_err(invalid(format!("{}.{}{}", class_name, name, descriptor)))?;
+        }
+        Ok(())
+    }
+
+    /// Fails if a constant returned by `MethodBuilder::method_ref` doesn't
+    /// match whether the class is an interface, as its `INTERFACE` flag was
+    /// changed afterwards.
+    fn check_method_refs(&self) -> Result<(), BuilderError> {
+        let interface = self.class_file.access_flags.is_set(INTERFACE);
+        for index in &self.method_refs {
+            let entry = self.constant_pool.get(*index).map(|entry| &entry.info);
+            if matches!(entry, Some(CpInfoType::InterfaceMethodref { .. })) != interface {
+                return Err(BuilderError::InvalidConstant(*index));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if the `BootstrapMethods` attribute has no entry `index`.
+    fn check_bootstrap_method(&self, index: u16) -> Result<(), BuilderError> {
+        let count = self
+            .class_file
+            .attributes
+            .iter()
+            .find_map(|a| match a.info {
+                AttributeInfoKind::BootstrapMethods {
+                    num_bootstrap_methods,
+                    ..
+                } => Some(num_bootstrap_methods),
+                _ => None,
+            })
+            .unwrap_or(0);
+        if index >= count {
+            return Err(BuilderError::InvalidBootstrapMethod(index));
+        }
+        Ok(())
+    }
+
+    fn remove_method(&mut self, index: u16) {
+        self.removed_methods.push(index);
+        if self.static_initializer == Some(index) {
+            self.static_initializer = None;
+        }
+    }
+
+    /// Makes the code of the `<clinit>` method at `index`, read from a class
+    /// file, the first static initializer fragment. Its final `return` is
+    /// dropped, so branches to it continue with the next fragment.
+    fn adopt_static_initializer(&mut self, index: u16) -> Result<(), BuilderError> {
+        let method = &self.class_file.method_info[index as usize];
+        let mut state = MethodState {
+            method: index,
+            ..Default::default()
+        };
+        for attribute in &method.attributes {
+            let AttributeInfoKind::Code {
+                code,
+                exception_table,
+                attributes,
+                ..
+            } = &attribute.info
+            else {
+                continue;
+            };
+            let mut pcs = vec![];
+            let mut end = 0;
+            for op in code {
+                pcs.push(end);
+                end += op.size(end);
+            }
+            state.code = code.clone();
+            if let Some(Opcode::Return_) = state.code.last() {
+                state.code.pop();
+            }
+            if let Some(index) = state.code.iter().position(|op| *op == Opcode::Return_) {
+                return Err(BuilderError::EarlyReturn(pcs[index] as u16));
+            }
+            let mut label = |pc: u16| {
+                let index = match pcs.iter().position(|p| *p == pc as usize) {
+                    Some(index) => index,
+                    None if pc as usize == end => state.code.len(),
+                    None => {
+                        return Err(FrameError::InvalidBranchTarget {
+                            pc,
+                            target: pc as i32,
+                        })
+                    }
+                };
+                state.labels.push(Some(index));
+                Ok(Label {
+                    method: self.methods.len() as u16,
+                    index: state.labels.len() - 1,
+                })
+            };
+            for entry in exception_table {
+                state.handlers.push(Handler {
+                    start: label(entry.start_pc)?,
+                    end: label(entry.end_pc)?,
+                    handler: label(entry.handler_pc)?,
+                    catch_type: entry.catch_type,
+                });
+            }
+            for attribute in attributes {
+                if let AttributeInfoKind::LineNumberTable {
+                    line_number_table, ..
+                } = &attribute.info
+                {
+                    for line in line_number_table {
+                        if let Some(index) = pcs.iter().position(|pc| *pc == line.start_pc as usize)
+                        {
+                            state.lines.push((index, line.line_number));
+                        }
+                    }
+                }
+            }
+        }
+        self.methods.push(state);
+        self.static_initializer = Some(index);
+        Ok(())
+    }
+
+    fn signature_attribute(&mut self, signature: &str) -> Result<AttributeInfo, ConstantPoolError> {
+        Ok(AttributeInfo {
+            attribute_name_index: self.constant_pool.utf8("Signature")?,
+            attribute_length: 0,
+            info: AttributeInfoKind::Signature {
+                signature_index: self.constant_pool.utf8(signature)?,
+            },
+        })
+    }
+
+    fn deprecated_attribute(&mut self) -> Result<AttributeInfo, ConstantPoolError> {
+        Ok(AttributeInfo {
+            attribute_name_index: self.constant_pool.utf8("Deprecated")?,
+            attribute_length: 0,
+            info: AttributeInfoKind::Deprecated,
+        })
+    }
+
+    fn custom_attribute(
+        &mut self,
+        name: &str,
+        info: Vec<u8>,
+    ) -> Result<AttributeInfo, ConstantPoolError> {
+        Ok(AttributeInfo {
+            attribute_name_index: self.constant_pool.utf8(name)?,
+            attribute_length: 0,
+            info: AttributeInfoKind::Custom { info },
+        })
+    }
+}
+
+fn compute_byte(byte: u16) -> (u8, u8) {
+    (((byte >> 8) & 0xFF) as u8, (byte & 0xFF) as u8)
+}
+
+/// Builds a class file. Fields and methods are added with `field` and
+/// `method`, which return builders that can be used in any order.
+#[derive(Debug)]
+pub struct ClassBuilder {
+    class: Rc<RefCell<ClassState>>,
+}
+
+impl Default for ClassBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClassBuilder {
+    pub fn new() -> Self {
+        let class = ClassState {
+            class_file: ClassFile {
+                magic: JVM_MAGIC,
+                minor_version: 0,
+                major_version: 61,
+                ..Default::default()
+            },
+            constant_pool: ConstantPool::new(),
+            methods: vec![],
+            static_initializer: None,
+            removed_fields: vec![],
+            removed_methods: vec![],
+            method_refs: vec![],
+        };
+        ClassBuilder {
+            class: Rc::new(RefCell::new(class)),
+        }
+    }
+
+    /// Creates a builder that modifies `class_file`, such as a class read by
+    /// `Parser`. Its constant pool entries are reused by the `load_*` methods,
+    /// and its methods keep their code and stack map frames unless they are
+    /// replaced.
+    pub fn from_class_file(class_file: ClassFile) -> Result<Self, BuilderError> {
+        let constant_pool = ConstantPool::from_entries(class_file.constant_pool.clone())?;
+        let class = ClassState {
+            class_file,
+            constant_pool,
+            methods: vec![],
+            static_initializer: None,
+            removed_fields: vec![],
+            removed_methods: vec![],
+            method_refs: vec![],
+        };
+        Ok(ClassBuilder {
+            class: Rc::new(RefCell::new(class)),
+        })
+    }
+
+    pub fn set_access_flags(&mut self, access_flags: impl Into<ClassAccess>) {
+        self.class.borrow_mut().class_file.access_flags = access_flags.into();
+    }
+
+    /// Returns the constant pool, whose entries are shared by every
+    /// `load_*` and `add_*` call that refers to the same value.
+    pub fn constant_pool(&self) -> Ref<'_, ConstantPool> {
+        Ref::map(self.class.borrow(), |class| &class.constant_pool)
+    }
+
+    pub fn constant_pool_mut(&mut self) -> RefMut<'_, ConstantPool> {
+        RefMut::map(self.class.borrow_mut(), |class| &mut class.constant_pool)
+    }
+
+    /// Loads the class `class_name`, a `ClassName` or a string checked as
+    /// one, such as `java/lang/String` or `[I`.
+    pub fn load_class(
+        &mut self,
+        class_name: impl TryInto<ClassName, Error: Into<BuilderError>>,
+    ) -> Result<u16, BuilderError> {
+        let class_name = class_name.try_into().map_err(Into::into)?;
+        Ok(self.constant_pool_mut().class(class_name.as_str())?)
+    }
+
+    pub fn load_field(
+        &mut self,
+        class: u16,
+        name: impl TryInto<FieldName, Error: Into<BuilderError>>,
+        descriptor: Descriptor,
+    ) -> Result<u16, BuilderError> {
+        let name = name.try_into().map_err(Into::into)?;
+        Ok(self
+            .constant_pool_mut()
+            .field_ref(class, name.as_str(), &descriptor.serialize())?)
+    }
+
+    pub fn load_method(
+        &mut self,
+        class: u16,
+        name: impl TryInto<MethodName, Error: Into<BuilderError>>,
+        descriptor: Descriptor,
+    ) -> Result<u16, BuilderError> {
+        let name = name.try_into().map_err(Into::into)?;
+        Ok(self
+            .constant_pool_mut()
+            .method_ref(class, name.as_str(), &descriptor.serialize())?)
+    }
+
+    /// Loads a method declared by an interface, to be called with
+    /// `build_invokeinterface`, or with `build_invokestatic` and
+    /// `build_invokespecial` for static methods and default super calls.
+    pub fn load_interface_method(
+        &mut self,
+        interface: u16,
+        name: impl TryInto<MethodName, Error: Into<BuilderError>>,
+        descriptor: Descriptor,
+    ) -> Result<u16, BuilderError> {
+        let name = name.try_into().map_err(Into::into)?;
+        Ok(self.constant_pool_mut().interface_method_ref(
+            interface,
+            name.as_str(),
+            &descriptor.serialize(),
+        )?)
+    }
+
+    pub fn load_string(&mut self, string: &str) -> Result<u16, ConstantPoolError> {
+        self.constant_pool_mut().string(string)
+    }
+
+    /// Loads a method handle to `reference`, which must be a `Fieldref` for
+    /// the field kinds, a constructor `Methodref` for `NewInvokeSpecial`, and a
+    /// `Methodref` or `InterfaceMethodref` of a method otherwise.
+    pub fn load_method_handle(
+        &mut self,
+        kind: ReferenceKind,
+        reference: u16,
+    ) -> Result<u16, BuilderError> {
+        let mut pool = self.constant_pool_mut();
+        let info = pool.get(reference).map(|cp| &cp.info);
+        let name = pool.get_member_ref(reference).map(|(_, name, _)| name);
+        let is_method = !matches!(name, Some("<init>" | "<clinit>"));
+        let valid = match (kind, info) {
+            (
+                ReferenceKind::GetField
+                | ReferenceKind::GetStatic
+                | ReferenceKind::PutField
+                | ReferenceKind::PutStatic,
+                Some(CpInfoType::Fieldref { .. }),
+            ) => true,
+            (ReferenceKind::InvokeVirtual, Some(CpInfoType::Methodref { .. })) => is_method,
+            (
+                ReferenceKind::InvokeStatic | ReferenceKind::InvokeSpecial,
+                Some(CpInfoType::Methodref { .. } | CpInfoType::InterfaceMethodref { .. }),
+            ) => is_method,
+            (ReferenceKind::NewInvokeSpecial, Some(CpInfoType::Methodref { .. })) => {
+                name == Some("<init>")
+            }
+            (ReferenceKind::InvokeInterface, Some(CpInfoType::InterfaceMethodref { .. })) => {
+                is_method
+            }
+            _ => false,
+        };
+        if !valid {
+            return Err(BuilderError::InvalidConstant(reference));
+        }
+        Ok(pool.method_handle(kind, reference)?)
+    }
+
+    pub fn load_method_type(&mut self, descriptor: Descriptor) -> Result<u16, BuilderError> {
+        if !matches!(descriptor, Descriptor::Function(..)) {
+            return Err(BuilderError::InvalidDescriptor(descriptor.serialize()));
+        }
+        Ok(self
+            .constant_pool_mut()
+            .method_type(&descriptor.serialize())?)
+    }
+
+    /// Adds an entry to the `BootstrapMethods` attribute and returns its
+    /// index, reusing an identical entry if there is one. `method_handle` is
+    /// the bootstrap method, and `arguments` are loadable constants passed to
+    /// it after the lookup, name and type.
+    pub fn add_bootstrap_method(
+        &mut self,
+        method_handle: u16,
+        arguments: &[u16],
+    ) -> Result<u16, BuilderError> {
+        let mut class = self.class.borrow_mut();
+        let pool = &class.constant_pool;
+        if !matches!(
+            pool.get(method_handle).map(|cp| &cp.info),
+            Some(CpInfoType::MethodHandle { .. })
+        ) {
+            return Err(BuilderError::InvalidConstant(method_handle));
+        }
+        for argument in arguments {
+            let loadable = matches!(
+                pool.get(*argument).map(|cp| &cp.info),
+                Some(
+                    CpInfoType::Integer { .. }
+                        | CpInfoType::Float { .. }
+                        | CpInfoType::Long { .. }
+                        | CpInfoType::Double { .. }
+                        | CpInfoType::Class { .. }
+                        | CpInfoType::String { .. }
+                        | CpInfoType::MethodHandle { .. }
+                        | CpInfoType::MethodType { .. }
+                        | CpInfoType::Dynamic { .. }
+                )
+            );
+            if !loadable {
+                return Err(BuilderError::InvalidConstant(*argument));
+            }
+        }
+        let method = BootstrapMethod {
+            bootstrap_method_ref: method_handle,
+            num_bootstrap_arguments: arguments.len() as u16,
+            bootstrap_arguments: arguments.to_vec(),
+        };
+        let existing = class
+            .class_file
+            .attributes
+            .iter_mut()
+            .find_map(|a| match &mut a.info {
+                AttributeInfoKind::BootstrapMethods {
+                    num_bootstrap_methods,
+                    bootstrap_methods,
+                } => Some((num_bootstrap_methods, bootstrap_methods)),
+                _ => None,
+            });
+        if let Some((num_bootstrap_methods, bootstrap_methods)) = existing {
+            if let Some(index) = bootstrap_methods.iter().position(|m| *m == method) {
+                return Ok(index as u16);
+            }
+            bootstrap_methods.push(method);
+            *num_bootstrap_methods += 1;
+            return Ok(*num_bootstrap_methods - 1);
+        }
+        let attribute = AttributeInfo {
+            attribute_name_index: class.constant_pool.utf8("BootstrapMethods")?,
+            attribute_length: 0,
+            info: AttributeInfoKind::BootstrapMethods {
+                num_bootstrap_methods: 1,
+                bootstrap_methods: vec![method],
+            },
+        };
+        let class_file = &mut class.class_file;
+        add_attribute(
+            &mut class_file.attributes,
+            &mut class_file.attributes_count,
+            attribute,
+        );
+        Ok(0)
+    }
+
+    /// Loads a call site for `build_invokedynamic`, linked by the bootstrap
+    /// method at index `bootstrap_method` of the `BootstrapMethods` attribute.
+    pub fn load_invoke_dynamic(
+        &mut self,
+        bootstrap_method: u16,
+        name: impl TryInto<MethodName, Error: Into<BuilderError>>,
+        descriptor: Descriptor,
+    ) -> Result<u16, BuilderError> {
+        let name = name.try_into().map_err(Into::into)?;
+        if !matches!(descriptor, Descriptor::Function(..)) {
+            return Err(BuilderError::InvalidDescriptor(descriptor.serialize()));
+        }
+        let mut class = self.class.borrow_mut();
+        class.check_bootstrap_method(bootstrap_method)?;
+        Ok(class.constant_pool.invoke_dynamic(
+            bootstrap_method,
+            name.as_str(),
+            &descriptor.serialize(),
+        )?)
+    }
+
+    /// Loads a dynamically computed constant of type `descriptor`, for `ldc`
+    /// or as a bootstrap argument, produced by the bootstrap method at index
+    /// `bootstrap_method` of the `BootstrapMethods` attribute.
+    pub fn load_dynamic(
+        &mut self,
+        bootstrap_method: u16,
+        name: impl TryInto<FieldName, Error: Into<BuilderError>>,
+        descriptor: Descriptor,
+    ) -> Result<u16, BuilderError> {
+        let name = name.try_into().map_err(Into::into)?;
+        if let Descriptor::Function(..) | Descriptor::Void = descriptor {
+            return Err(BuilderError::InvalidDescriptor(descriptor.serialize()));
+        }
+        let mut class = self.class.borrow_mut();
+        class.check_bootstrap_method(bootstrap_method)?;
+        Ok(class
+            .constant_pool
+            .dynamic(bootstrap_method, name.as_str(), &descriptor.serialize())?)
+    }
+
+    pub fn set_super_class(&mut self, class: u16) {
+        self.class.borrow_mut().class_file.super_class = class;
+    }
+
+    pub fn set_class_name(
+        &mut self,
+        name: impl TryInto<ClassName, Error: Into<BuilderError>>,
+    ) -> Result<(), BuilderError> {
+        let name = name.try_into().map_err(Into::into)?;
+        let mut class = self.class.borrow_mut();
+        class.class_file.this_class = class.constant_pool.class(name.as_str())?;
+        Ok(())
+    }
+
+    /// Adds `interface`, a class constant, to the interfaces the class
+    /// implements, or extends if the class is an interface.
+    pub fn add_interface(&mut self, interface: u16) {
+        let class_file = &mut self.class.borrow_mut().class_file;
+        if !class_file.interfaces.contains(&interface) {
+            class_file.interfaces.push(interface);
+            class_file.interfaces_count += 1;
+        }
+    }
+
+    /// Sets the generic signature of the class, such as
+    /// `<T:Ljava/lang/Object;>Ljava/lang/Object;Ljava/lang/Comparable<TT;>;`.
+    pub fn set_signature(&mut self, signature: &str) -> Result<(), ConstantPoolError> {
+        let mut class = self.class.borrow_mut();
+        let attribute = class.signature_attribute(signature)?;
+        let class_file = &mut class.class_file;
+        set_attribute(
+            &mut class_file.attributes,
+            &mut class_file.attributes_count,
+            attribute,
+        );
+        Ok(())
+    }
+
+    pub fn set_source_file(&mut self, file: &str) -> Result<(), ConstantPoolError> {
+        let mut class = self.class.borrow_mut();
+        let attribute = AttributeInfo {
+            attribute_name_index: class.constant_pool.utf8("SourceFile")?,
+            attribute_length: 0,
+            info: AttributeInfoKind::SourceFile {
+                sourcefile_index: class.constant_pool.utf8(file)?,
+            },
+        };
+        let class_file = &mut class.class_file;
+        set_attribute(
+            &mut class_file.attributes,
+            &mut class_file.attributes_count,
+            attribute,
+        );
+        Ok(())
+    }
+
+    pub fn set_deprecated(&mut self) -> Result<(), ConstantPoolError> {
+        let mut class = self.class.borrow_mut();
+        let attribute = class.deprecated_attribute()?;
+        let class_file = &mut class.class_file;
+        set_attribute(
+            &mut class_file.attributes,
+            &mut class_file.attributes_count,
+            attribute,
+        );
+        Ok(())
+    }
+
+    /// Annotates the class with `annotation`.
+    pub fn add_annotation(
+        &mut self,
+        annotation: &AnnotationBuilder,
+        retention: Retention,
+    ) -> Result<(), BuilderError> {
+        let mut class = self.class.borrow_mut();
+        let class = &mut *class;
+        annotation::add_annotation(
+            &mut class.class_file.attributes,
+            &mut class.class_file.attributes_count,
+            &mut class.constant_pool,
+            annotation,
+            retention,
+        )
+    }
+
+    /// Records `inner_class` in the `InnerClasses` attribute. `outer_class` is
+    /// `None` for local and anonymous classes, and `inner_name` is `None` for
+    /// anonymous classes.
+    pub fn add_inner_class(
+        &mut self,
+        inner_class: u16,
+        outer_class: Option<u16>,
+        inner_name: Option<&str>,
+        access_flags: impl Into<InnerClassAccess>,
+    ) -> Result<(), ConstantPoolError> {
+        let mut class = self.class.borrow_mut();
+        let inner_name_index = match inner_name {
+            Some(name) => class.constant_pool.utf8(name)?,
+            None => 0,
+        };
+        let inner = InnerClass {
+            inner_class_info_index: inner_class,
+            outer_class_info_index: outer_class.unwrap_or(0),
+            inner_name_index,
+            inner_class_access_flags: access_flags.into(),
+        };
+        let existing = class
+            .class_file
+            .attributes
+            .iter_mut()
+            .find_map(|a| match &mut a.info {
+                AttributeInfoKind::InnerClasses {
+                    number_of_classes,
+                    classes,
+                } => Some((number_of_classes, classes)),
+                _ => None,
+            });
+        if let Some((number_of_classes, classes)) = existing {
+            if !classes.contains(&inner) {
+                classes.push(inner);
+                *number_of_classes += 1;
+            }
+            return Ok(());
+        }
+        let attribute = AttributeInfo {
+            attribute_name_index: class.constant_pool.utf8("InnerClasses")?,
+            attribute_length: 0,
+            info: AttributeInfoKind::InnerClasses {
+                number_of_classes: 1,
+                classes: vec![inner],
+            },
+        };
+        let class_file = &mut class.class_file;
+        add_attribute(
+            &mut class_file.attributes,
+            &mut class_file.attributes_count,
+            attribute,
+        );
+        Ok(())
+    }
+
+    /// Adds a class attribute named `name` with `info` as its contents,
+    /// replacing an attribute of the same name.
+    pub fn add_attribute(&mut self, name: &str, info: Vec<u8>) -> Result<(), ConstantPoolError> {
+        let mut class = self.class.borrow_mut();
+        let attribute = class.custom_attribute(name, info)?;
+        let class_file = &mut class.class_file;
+        set_attribute(
+            &mut class_file.attributes,
+            &mut class_file.attributes_count,
+            attribute,
+        );
+        Ok(())
+    }
+
+    /// Adds a field to the class and returns a builder for its attributes. A
+    /// field with the same name and descriptor is replaced.
+    pub fn field(
+        &mut self,
+        name: impl TryInto<FieldName, Error: Into<BuilderError>>,
+        descriptor: Descriptor,
+        access_flags: impl Into<FieldAccess>,
+    ) -> Result<FieldBuilder, BuilderError> {
+        let access_flags = access_flags.into();
+        let name = name.try_into().map_err(Into::into)?;
+        let name = name.as_str();
+        if let Descriptor::Function(..) | Descriptor::Void = descriptor {
+            return Err(BuilderError::InvalidDescriptor(descriptor.serialize()));
+        }
+        let serialized = descriptor.serialize();
+        let mut class = self.class.borrow_mut();
+        let class = &mut *class;
+        if let Some(existing) = class.find_field(name, &serialized) {
+            class.removed_fields.push(existing);
+        }
+        let name_index = class.constant_pool.utf8(name)?;
+        let descriptor_index = class.constant_pool.utf8(&serialized)?;
+        class.class_file.fields.push(FieldInfo {
+            access_flags,
+            name_index,
+            descriptor_index,
+            attributes_count: 0,
+            attributes: vec![],
+        });
+        class.class_file.fields_count += 1;
+        let index = class.class_file.fields_count - 1;
+        Ok(FieldBuilder::new(self.class.clone(), index, descriptor))
+    }
+
+    /// Adds a method to the class and returns a builder for its code. A
+    /// method with the same name and descriptor is replaced.
+    ///
+    /// `abstract` and `native` methods have no code, so only their attributes
+    /// can be set. The methods of an interface are referred to by an
+    /// `InterfaceMethodref`, so `MethodBuilder::method_ref` must be called
+    /// after setting the `INTERFACE` flag, or `build` fails.
+    pub fn method(
+        &mut self,
+        name: impl TryInto<MethodName, Error: Into<BuilderError>>,
+        descriptor: Descriptor,
+        access_flags: impl Into<MethodAccess>,
+    ) -> Result<MethodBuilder, BuilderError> {
+        let access_flags = access_flags.into();
+        let name = name.try_into().map_err(Into::into)?;
+        let name = name.as_str();
+        let argument_slots = descriptor
+            .argument_slots(access_flags.is_set(METHOD_ACC_STATIC))
+            .ok_or_else(|| BuilderError::InvalidDescriptor(descriptor.serialize()))?;
+        let descriptor = descriptor.serialize();
+        let mut class = self.class.borrow_mut();
+        let class = &mut *class;
+        if let Some(existing) = class.find_method(name, &descriptor) {
+            class.remove_method(existing);
+        }
+        let name_index = class.constant_pool.utf8(name)?;
+        let descriptor_index = class.constant_pool.utf8(&descriptor)?;
+        let mut attributes = vec![];
+        if (access_flags & (METHOD_ACC_ABSTRACT | METHOD_ACC_NATIVE)).is_empty() {
+            attributes.push(AttributeInfo {
+                attribute_name_index: class.constant_pool.utf8("Code")?,
+                attribute_length: 0,
+                info: AttributeInfoKind::Code {
+                    max_stack: 0,
+                    max_locals: argument_slots,
+                    code_length: 0,
+                    code: vec![],
+                    exception_table_length: 0,
+                    exception_table: vec![],
+                    attributes_count: 0,
+                    attributes: vec![],
+                },
+            });
+        }
+        class.class_file.method_info.push(MethodInfo {
+            access_flags,
+            name_index,
+            descriptor_index,
+            attributes_count: attributes.len() as u16,
+            attributes,
+        });
+        class.class_file.method_count += 1;
+        class.methods.push(MethodState {
+            method: class.class_file.method_count - 1,
+            argument_slots,
+            ..Default::default()
+        });
+        let id = class.methods.len() as u16 - 1;
+        Ok(MethodBuilder::new(self.class.clone(), id))
+    }
+
+    /// Returns a builder for a new fragment of the static initializer. The
+    /// fragments are joined into a single `<clinit>` method in the order they
+    /// are created, followed by a `return`, so they must not return themselves.
+    /// The code of a `<clinit>` method read from a class file is kept as the
+    /// first fragment, which fails with `BuilderError::EarlyReturn` if it
+    /// returns before its end.
+    pub fn static_initializer(&mut self) -> Result<MethodBuilder, BuilderError> {
+        let existing = {
+            let mut class = self.class.borrow_mut();
+            let parsed = class
+                .find_method("<clinit>", "()V")
+                .filter(|index| !class.methods.iter().any(|m| m.method == *index));
+            match (class.static_initializer, parsed) {
+                (Some(method), _) => Some(method),
+                (None, Some(method)) => {
+                    class.adopt_static_initializer(method)?;
+                    Some(method)
+                }
+                (None, None) => None,
+            }
+        };
+        let Some(method) = existing else {
+            let descriptor = Descriptor::Function(vec![], Box::new(Descriptor::Void));
+            let builder = self.method("<clinit>", descriptor, METHOD_ACC_STATIC)?;
+            let mut class = self.class.borrow_mut();
+            class.static_initializer = Some(class.class_file.method_count - 1);
+            return Ok(builder);
+        };
+        let mut class = self.class.borrow_mut();
+        class.methods.push(MethodState {
+            method,
+            ..Default::default()
+        });
+        let id = class.methods.len() as u16 - 1;
+        Ok(MethodBuilder::new(self.class.clone(), id))
+    }
+
+    /// Removes the field named `name` with type `descriptor`. Returns `false`
+    /// if the class has no such field.
+    pub fn remove_field(&mut self, name: &str, descriptor: Descriptor) -> bool {
+        let mut class = self.class.borrow_mut();
+        match class.find_field(name, &descriptor.serialize()) {
+            Some(index) => {
+                class.removed_fields.push(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the method named `name` with type `descriptor`. Returns `false`
+    /// if the class has no such method.
+    pub fn remove_method(&mut self, name: &str, descriptor: Descriptor) -> bool {
+        let mut class = self.class.borrow_mut();
+        match class.find_method(name, &descriptor.serialize()) {
+            Some(index) => {
+                class.remove_method(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Builds the class file. Stack map frames are computed knowing only the
+    /// class being built, so other object types merged at branch targets are
+    /// widened to `java/lang/Object` even when they share a closer super class.
+    /// The verifier rejects code using such a merged value as that super class,
+    /// such as by calling one of its methods, so that code must be built with
+    /// `build_with_hierarchy`.
+    pub fn build(&self) -> Result<ClassFile, BuilderError> {
+        self.build_with_hierarchy(&SimpleHierarchy::new())
+    }
+
+    /// Builds the class file, using `hierarchy` to find the common super class
+    /// of object types merged at branch targets when computing stack map frames.
+    pub fn build_with_hierarchy(
+        &self,
+        hierarchy: &dyn ClassHierarchy,
+    ) -> Result<ClassFile, BuilderError> {
+        let class = self.class.borrow();
+        let mut class_file = class.class_file.clone();
+        let mut constant_pool = class.constant_pool.clone();
+        class.check_access_flags()?;
+        class.check_method_refs()?;
+        let mut methods: Vec<Option<ResolvedCode>> =
+            class_file.method_info.iter().map(|_| None).collect();
+        for (id, state) in class.methods.iter().enumerate() {
+            if class.removed_methods.contains(&state.method) {
+                continue;
+            }
+            let method = &mut methods[state.method as usize];
+            let start_pc = method.as_ref().map_or(0, |m| m.end_pc);
+            let resolved = state.resolve(id as u16, start_pc)?;
+            match method {
+                Some(method) => method.append(resolved),
+                None => *method = Some(resolved),
+            }
+        }
+        if let Some(method) = class.static_initializer {
+            if let Some(method) = &mut methods[method as usize] {
+                method.code.push(Opcode::Return_);
+                method.end_pc += 1;
+                if method.end_pc > u16::MAX as usize {
+                    return Err(BuilderError::CodeTooLarge(method.end_pc));
+                }
+            }
+        }
+        // Methods read from a class file keep their stack map frames.
+        let built: Vec<usize> = (0..methods.len())
+            .filter(|index| methods[*index].is_some())
+            .collect();
+        for (method, resolved) in class_file.method_info.iter_mut().zip(methods) {
+            let Some(resolved) = resolved else {
+                continue;
+            };
+            let has_code = method
+                .attributes
+                .iter()
+                .any(|a| matches!(a.info, AttributeInfoKind::Code { .. }));
+            if !has_code && !resolved.code.is_empty() {
+                let name = constant_pool
+                    .get_utf8(method.name_index)
+                    .unwrap_or_default();
+                return Err(BuilderError::UnexpectedCode(name.to_string()));
+            }
+            let mut debug_attributes = vec![];
+            if !resolved.line_numbers.is_empty() {
+                debug_attributes.push(AttributeInfo {
+                    attribute_name_index: constant_pool.utf8("LineNumberTable")?,
+                    attribute_length: 0,
+                    info: AttributeInfoKind::LineNumberTable {
+                        line_number_table_length: resolved.line_numbers.len() as u16,
+                        line_number_table: resolved.line_numbers.clone(),
+                    },
+                });
+            }
+            if !resolved.local_variables.is_empty() {
+                debug_attributes.push(AttributeInfo {
+                    attribute_name_index: constant_pool.utf8("LocalVariableTable")?,
+                    attribute_length: 0,
+                    info: AttributeInfoKind::LocalVariableTable {
+                        local_variable_table_length: resolved.local_variables.len() as u16,
+                        local_variable_table: resolved.local_variables.clone(),
+                    },
+                });
+            }
+            if !resolved.local_variable_types.is_empty() {
+                debug_attributes.push(AttributeInfo {
+                    attribute_name_index: constant_pool.utf8("LocalVariableTypeTable")?,
+                    attribute_length: 0,
+                    info: AttributeInfoKind::LocalVariableTypeTable {
+                        local_variable_type_table_length: resolved.local_variable_types.len()
+                            as u16,
+                        local_variable_type_table: resolved.local_variable_types.clone(),
+                    },
+                });
+            }
+            for attribute in &mut method.attributes {
+                if let AttributeInfoKind::Code {
+                    code,
+                    exception_table_length,
+                    exception_table,
+                    attributes_count,
+                    attributes,
+                    ..
+                } = &mut attribute.info
+                {
+                    *code = resolved.code.clone();
+                    *exception_table_length = resolved.exception_table.len() as u16;
+                    *exception_table = resolved.exception_table.clone();
+                    for debug_attribute in debug_attributes.clone() {
+                        set_attribute(attributes, attributes_count, debug_attribute);
+                    }
+                }
+            }
+        }
+        class_file.constant_pool_count = constant_pool.count();
+        class_file.constant_pool = constant_pool.into_entries();
+        let hierarchy = BuiltClassHierarchy {
+            class: class_file
+                .get_class_name(class_file.this_class)
+                .map(str::to_string),
+            super_class: class_file
+                .get_class_name(class_file.super_class)
+                .map(str::to_string),
+            is_interface: class_file.access_flags.is_set(INTERFACE),
+            inner: hierarchy,
+        };
+        compute_method_stack_map_tables(&mut class_file, &built, &hierarchy)?;
+
+        let mut index = 0;
+        class_file.fields.retain(|_| {
+            index += 1;
+            !class.removed_fields.contains(&(index - 1))
+        });
+        class_file.fields_count = class_file.fields.len() as u16;
+        let mut index = 0;
+        class_file.method_info.retain(|_| {
+            index += 1;
+            !class.removed_methods.contains(&(index - 1))
+        });
+        class_file.method_count = class_file.method_info.len() as u16;
+        Ok(class_file)
+    }
+}
+
+/// Adds `attribute`, replacing an existing attribute of the same kind. Custom
+/// attributes are of the same kind only if they have the same name.
+fn set_attribute(attributes: &mut Vec<AttributeInfo>, count: &mut u16, attribute: AttributeInfo) {
+    let kind = std::mem::discriminant(&attribute.info);
+    attributes.retain(|a| match (&a.info, &attribute.info) {
+        (AttributeInfoKind::Custom { .. }, AttributeInfoKind::Custom { .. }) => {
+            a.attribute_name_index != attribute.attribute_name_index
+        }
+        _ => std::mem::discriminant(&a.info) != kind,
+    });
+    add_attribute(attributes, count, attribute);
+}
+
+fn add_attribute(attributes: &mut Vec<AttributeInfo>, count: &mut u16, attribute: AttributeInfo) {
+    attributes.push(attribute);
+    *count = attributes.len() as u16;
+}
+
+/// Returns the number of local slots needed by `op` if it accesses a local.
+fn local_slots(op: &Opcode) -> Option<u16> {
+    let (index, size) = match *op {
+        Opcode::Aload(i)
+        | Opcode::Astore(i)
+        | Opcode::Iload(i)
+        | Opcode::Istore(i)
+        | Opcode::Fload(i)
+        | Opcode::Fstore(i)
+        | Opcode::Ret(i)
+        | Opcode::Iinc(i, _)
+        | Opcode::AloadN(i)
+        | Opcode::AstoreN(i)
+        | Opcode::IloadN(i)
+        | Opcode::IstoreN(i)
+        | Opcode::FloadN(i)
+        | Opcode::FstoreN(i) => (i as u16, 1),
+        Opcode::Lload(i)
+        | Opcode::Lstore(i)
+        | Opcode::Dload(i)
+        | Opcode::Dstore(i)
+        | Opcode::LloadN(i)
+        | Opcode::LstoreN(i)
+        | Opcode::DloadN(i)
+        | Opcode::DstoreN(i) => (i as u16, 2),
+        Opcode::Wide(opcode, b1, b2) => {
+            let size = match opcode {
+                LLOAD | LSTORE | DLOAD | DSTORE => 2,
+                _ => 1,
+            };
+            (u16::from_be_bytes([b1, b2]), size)
+        }
+        Opcode::WideIinc(b1, b2, _, _) => (u16::from_be_bytes([b1, b2]), 1),
+        _ => return None,
+    };
+    Some(index + size)
+}
+
+/// `offsets` are in the order returned by `Opcode::branch_targets`.
+fn set_branch_offsets(op: &mut Opcode, pc: usize, offsets: &[i32]) -> Result<(), BuilderError> {
+    let short = |offset: i32| match i16::try_from(offset) {
+        Ok(offset) => Ok(offset.to_be_bytes()),
+        Err(_) => Err(BuilderError::BranchOutOfRange { pc, offset }),
+    };
+    match op {
+        Opcode::Goto(b1, b2)
+        | Opcode::IfAcmCond(_, b1, b2)
+        | Opcode::IfIcmCond(_, b1, b2)
+        | Opcode::ICOND(_, b1, b2)
+        | Opcode::Ifnonnull(b1, b2)
+        | Opcode::Ifnull(b1, b2)
+        | Opcode::Jsr(b1, b2) => [*b1, *b2] = short(offsets[0])?,
+        Opcode::GotoW(b1, b2, b3, b4) | Opcode::JsrW(b1, b2, b3, b4) => {
+            [*b1, *b2, *b3, *b4] = offsets[0].to_be_bytes()
+        }
+        Opcode::Lookupswitch(default, pairs) => {
+            *default = offsets[0];
+            for (pair, offset) in pairs.iter_mut().zip(&offsets[1..]) {
+                pair.1 = *offset;
+            }
+        }
+        Opcode::Tableswitch(default, _, _, jumps) => {
+            *default = offsets[0];
+            jumps.copy_from_slice(&offsets[1..]);
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Adds the class being built to a caller supplied hierarchy.
+struct BuiltClassHierarchy<'a> {
+    class: Option<String>,
+    super_class: Option<String>,
+    is_interface: bool,
+    inner: &'a dyn ClassHierarchy,
+}
+
+impl ClassHierarchy for BuiltClassHierarchy<'_> {
+    fn super_class(&self, class: &str) -> Option<String> {
+        if self.class.as_deref() == Some(class) {
+            return self.super_class.clone();
+        }
+        self.inner.super_class(class)
+    }
+
+    fn is_interface(&self, class: &str) -> bool {
+        if self.class.as_deref() == Some(class) {
+            return self.is_interface;
+        }
+        self.inner.is_interface(class)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        constant_pool::MAX_CONSTANT_POOL_INDEX, flags::InnerClassFlag, parser::Parser,
+        reader::Reader,
+    };
+
+    fn reparse(class_file: &ClassFile) -> ClassFile {
+        Parser {
+            reader: Reader::new(class_file.to_jvm_bytecode()),
+        }
+        .parse()
+    }
+
+    fn new_class(name: &str) -> ClassBuilder {
+        let mut builder = ClassBuilder::new();
+        builder.set_access_flags(PUBLIC | SUPER);
+        builder.set_class_name(name).unwrap();
+        let object = builder.load_class("java/lang/Object").unwrap();
+        builder.set_super_class(object);
+        builder
+    }
+
+    fn custom_attributes(class_file: &ClassFile) -> Vec<(&str, &[u8])> {
+        class_file
+            .attributes
+            .iter()
+            .filter_map(|a| match &a.info {
+                AttributeInfoKind::Custom { info } => {
+                    Some((class_file.get_utf8(a.attribute_name_index)?, &info[..]))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn add_attribute_only_replaces_the_attribute_of_the_same_name() {
+        let mut builder = new_class("Custom");
+        builder.add_attribute("First", vec![1]).unwrap();
+        builder.add_attribute("Second", vec![2]).unwrap();
+        let parsed = reparse(&builder.build().unwrap());
+
+        let mut builder = ClassBuilder::from_class_file(parsed).unwrap();
+        builder.add_attribute("First", vec![3]).unwrap();
+        let class_file = reparse(&builder.build().unwrap());
+        assert_eq!(
+            custom_attributes(&class_file),
+            [("Second", &[2][..]), ("First", &[3][..])]
+        );
+    }
+
+    fn void_method() -> Descriptor {
+        Descriptor::Function(vec![], Box::new(Descriptor::Void))
+    }
+
+    #[test]
+    fn member_refs_require_a_class_name() {
+        let mut builder = ClassBuilder::new();
+        let field = builder
+            .field("count", Descriptor::Int, FIELD_ACC_PRIVATE)
+            .unwrap();
+        let method = builder
+            .method("run", void_method(), METHOD_ACC_PUBLIC)
+            .unwrap();
+        assert_eq!(field.field_ref(), Err(BuilderError::MissingClassName));
+        assert_eq!(method.method_ref(), Err(BuilderError::MissingClassName));
+    }
+
+    #[test]
+    fn member_refs_are_created_when_first_requested() {
+        let mut builder = ClassBuilder::new();
+        let field = builder
+            .field("count", Descriptor::Int, FIELD_ACC_PRIVATE)
+            .unwrap();
+        let method = builder
+            .method("run", void_method(), METHOD_ACC_PUBLIC)
+            .unwrap();
+        let count = builder.constant_pool().count();
+        builder.set_class_name("Renamed").unwrap();
+
+        let field_ref = field.field_ref().unwrap();
+        let method_ref = method.method_ref().unwrap().unwrap();
+        let pool = builder.constant_pool();
+        assert!(field_ref >= count && method_ref >= count);
+        assert_eq!(
+            pool.get_member_ref(field_ref),
+            Some(("Renamed", "count", "I"))
+        );
+        assert_eq!(
+            pool.get_member_ref(method_ref),
+            Some(("Renamed", "run", "()V"))
+        );
+    }
+
+    #[test]
+    fn removed_members_are_not_checked() {
+        let mut builder = new_class("Removed");
+        let flags = FIELD_ACC_PUBLIC | FIELD_ACC_PRIVATE;
+        builder.field("count", Descriptor::Int, flags).unwrap();
+        builder
+            .field("count", Descriptor::Int, FIELD_ACC_PRIVATE)
+            .unwrap();
+        let flags = METHOD_ACC_ABSTRACT | METHOD_ACC_FINAL;
+        builder.method("run", void_method(), flags).unwrap();
+        assert!(matches!(
+            builder.build(),
+            Err(BuilderError::InvalidAccess { .. })
+        ));
+        assert!(builder.remove_method("run", void_method()));
+        builder.build().unwrap();
+    }
+
+    #[test]
+    fn method_refs_follow_the_interface_flag() {
+        let mut builder = new_class("Shape");
+        let flags = METHOD_ACC_PUBLIC | METHOD_ACC_ABSTRACT;
+        let method = builder.method("area", void_method(), flags).unwrap();
+        builder.set_access_flags(PUBLIC | INTERFACE | ABSTRACT);
+        let method_ref = method.method_ref().unwrap().unwrap();
+        let entry = builder.constant_pool().get(method_ref).unwrap().clone();
+        assert!(matches!(entry.info, CpInfoType::InterfaceMethodref { .. }));
+        builder.build().unwrap();
+
+        builder.set_access_flags(PUBLIC | SUPER | ABSTRACT);
+        assert_eq!(
+            builder.build(),
+            Err(BuilderError::InvalidConstant(method_ref))
+        );
+    }
+
+    #[test]
+    fn loads_reuse_existing_entries() {
+        let mut builder = new_class("Shared");
+        let string = builder.load_class("java/lang/String").unwrap();
+        assert_eq!(builder.load_class("java/lang/String"), Ok(string));
+        let hello = builder.load_string("hello").unwrap();
+        assert_eq!(builder.load_string("hello"), Ok(hello));
+        let length = Descriptor::Function(vec![], Box::new(Descriptor::Int));
+        let method = builder.load_method(string, "length", length.clone());
+        assert_eq!(builder.load_method(string, "length", length), method);
+        for name in ["first", "second"] {
+            let mut method = builder
+                .method(name, void_method(), METHOD_ACC_STATIC)
+                .unwrap();
+            method.build_return().unwrap();
+        }
+        let class_file = builder.build().unwrap();
+        let code_names = (1..class_file.constant_pool_count)
+            .filter(|i| class_file.get_utf8(*i) == Some("Code"))
+            .count();
+        assert_eq!(code_names, 1);
+    }
+
+    #[test]
+    fn loads_report_constant_pool_overflow() {
+        let mut builder = new_class("Full");
+        let mut result = Ok(0);
+        for i in 0..u16::MAX {
+            result = builder.load_string(&i.to_string());
+            if result.is_err() {
+                break;
+            }
+        }
+        assert_eq!(result, Err(ConstantPoolError::Overflow));
+        assert_eq!(builder.constant_pool().count(), MAX_CONSTANT_POOL_INDEX);
+    }
+
+    #[test]
+    fn class_metadata_keeps_counts_consistent() {
+        let mut builder = new_class("Box");
+        let runnable = builder.load_class("java/lang/Runnable").unwrap();
+        let cloneable = builder.load_class("java/lang/Cloneable").unwrap();
+        builder.add_interface(runnable);
+        builder.add_interface(cloneable);
+        builder.add_interface(runnable);
+        builder
+            .set_signature("<T:Ljava/lang/Object;>Ljava/lang/Object;")
+            .unwrap();
+        builder.set_source_file("Box.java").unwrap();
+        builder.set_source_file("Box.kt").unwrap();
+        builder.set_deprecated().unwrap();
+        let inner = builder.load_class("Box$Entry").unwrap();
+        let this_class = builder.load_class("Box").unwrap();
+        let flags = InnerClassFlag::Public | InnerClassFlag::Static;
+        builder
+            .add_inner_class(inner, Some(this_class), Some("Entry"), flags)
+            .unwrap();
+        builder
+            .add_inner_class(inner, Some(this_class), Some("Entry"), flags)
+            .unwrap();
+        let mut field = builder
+            .field(
+                "value",
+                Descriptor::Object(ClassName::new("java/lang/Object").unwrap()),
+                FIELD_ACC_PRIVATE,
+            )
+            .unwrap();
+        field.set_signature("TT;").unwrap();
+        builder
+            .field("size", Descriptor::Int, FIELD_ACC_PRIVATE)
+            .unwrap();
+        let class_file = reparse(&builder.build().unwrap());
+
+        assert_eq!(class_file.interfaces, [runnable, cloneable]);
+        assert_eq!(class_file.interfaces_count, 2);
+        assert_eq!(class_file.fields_count, 2);
+        assert_eq!(class_file.attributes_count, 4);
+        assert_eq!(
+            class_file.get_signature(&class_file.attributes),
+            Some("<T:Ljava/lang/Object;>Ljava/lang/Object;")
+        );
+        assert_eq!(
+            class_file.get_signature(&class_file.fields[0].attributes),
+            Some("TT;")
+        );
+        let source_files: Vec<_> = class_file
+            .attributes
+            .iter()
+            .filter_map(|a| match a.info {
+                AttributeInfoKind::SourceFile { sourcefile_index } => {
+                    class_file.get_utf8(sourcefile_index)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(source_files, ["Box.kt"]);
+        assert!(class_file.attributes.iter().any(|a| matches!(
+            &a.info,
+            AttributeInfoKind::InnerClasses {
+                number_of_classes: 1,
+                ..
+            }
+        )));
+    }
+
+    fn method_code(class_file: &ClassFile, name: &str) -> Vec<Opcode> {
+        let method = class_file
+            .method_info
+            .iter()
+            .find(|m| class_file.get_utf8(m.name_index) == Some(name))
+            .unwrap();
+        method
+            .attributes
+            .iter()
+            .find_map(|a| match &a.info {
+                AttributeInfoKind::Code { code, .. } => Some(code.clone()),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn static_initializer_fragments_share_one_clinit() {
+        let mut builder = new_class("Counters");
+        let flags = FIELD_ACC_PUBLIC | FIELD_ACC_STATIC;
+        let first = builder.field("first", Descriptor::Int, flags).unwrap();
+        let second = builder.field("second", Descriptor::Int, flags).unwrap();
+        let mut initializer = builder.static_initializer().unwrap();
+        initializer.build_iconst_i(1).unwrap();
+        let mut other = builder.static_initializer().unwrap();
+        other.build_iconst_i(2).unwrap();
+        other.build_putstatic(second.field_ref().unwrap());
+        initializer.build_putstatic(first.field_ref().unwrap());
+        let class_file = builder.build().unwrap();
+
+        let clinits = class_file
+            .method_info
+            .iter()
+            .filter(|m| class_file.get_utf8(m.name_index) == Some("<clinit>"))
+            .count();
+        assert_eq!(clinits, 1);
+        let [first, second] = [first, second].map(|f| f.field_ref().unwrap().to_be_bytes());
+        assert_eq!(
+            method_code(&class_file, "<clinit>"),
+            [
+                Opcode::IconstI(1),
+                Opcode::Putstatic(first[0], first[1]),
+                Opcode::IconstI(2),
+                Opcode::Putstatic(second[0], second[1]),
+                Opcode::Return_,
+            ]
+        );
+    }
+
+    #[test]
+    fn parsed_static_initializer_is_kept_as_the_first_fragment() {
+        let mut builder = new_class("Counters");
+        let flags = FIELD_ACC_PUBLIC | FIELD_ACC_STATIC;
+        let first = builder.field("first", Descriptor::Int, flags).unwrap();
+        let first = first.field_ref().unwrap();
+        let mut initializer = builder.static_initializer().unwrap();
+        let end = initializer.new_label();
+        initializer.build_iconst_i(1).unwrap(); // 0
+        initializer.build_ifeq(end); // 1
+        initializer.build_iconst_i(1).unwrap(); // 4
+        initializer.build_putstatic(first); // 5
+        initializer.place_label(end).unwrap();
+        let parsed = reparse(&builder.build().unwrap());
+
+        let mut builder = ClassBuilder::from_class_file(parsed).unwrap();
+        let second = builder.field("second", Descriptor::Int, flags).unwrap();
+        let second = second.field_ref().unwrap();
+        let mut initializer = builder.static_initializer().unwrap();
+        initializer.build_iconst_i(2).unwrap(); // 8
+        initializer.build_putstatic(second); // 9
+        let class_file = reparse(&builder.build().unwrap());
+        let [first, second] = [first, second].map(u16::to_be_bytes);
+        // The branch to the old `return` now continues with the new fragment.
+        assert_eq!(
+            method_code(&class_file, "<clinit>"),
+            [
+                Opcode::IconstI(1),
+                Opcode::ICOND(Cond::Eq, 0, 7),
+                Opcode::IconstI(1),
+                Opcode::Putstatic(first[0], first[1]),
+                Opcode::IconstI(2),
+                Opcode::Putstatic(second[0], second[1]),
+                Opcode::Return_,
+            ]
+        );
+    }
+
+    #[test]
+    fn parsed_static_initializer_returning_early_is_rejected() {
+        let mut builder = new_class("Early");
+        let mut clinit = builder
+            .method("<clinit>", void_method(), METHOD_ACC_STATIC)
+            .unwrap();
+        let end = clinit.new_label();
+        clinit.build_iconst_i(0).unwrap(); // 0
+        clinit.build_ifeq(end); // 1
+        clinit.build_return().unwrap(); // 4
+        clinit.place_label(end).unwrap();
+        clinit.build_return().unwrap(); // 5
+        let parsed = reparse(&builder.build().unwrap());
+
+        let mut builder = ClassBuilder::from_class_file(parsed.clone()).unwrap();
+        assert_eq!(
+            builder.static_initializer().err(),
+            Some(BuilderError::EarlyReturn(4))
+        );
+        assert_eq!(reparse(&builder.build().unwrap()), parsed);
+    }
+
+    fn string_descriptor() -> Descriptor {
+        Descriptor::Object(ClassName::new("java/lang/String").unwrap())
+    }
+
+    #[test]
+    fn method_handles_check_their_reference() {
+        let mut builder = new_class("Handles");
+        let this_class = builder.load_class("Handles").unwrap();
+        let field = builder
+            .load_field(this_class, "name", string_descriptor())
+            .unwrap();
+        let method = builder
+            .load_method(this_class, "run", void_method())
+            .unwrap();
+        let constructor = builder
+            .load_method(this_class, "<init>", void_method())
+            .unwrap();
+        let runnable = builder.load_class("java/lang/Runnable").unwrap();
+        let run = builder
+            .load_interface_method(runnable, "run", void_method())
+            .unwrap();
+        let valid = [
+            (ReferenceKind::GetField, field),
+            (ReferenceKind::PutStatic, field),
+            (ReferenceKind::InvokeVirtual, method),
+            (ReferenceKind::InvokeStatic, run),
+            (ReferenceKind::NewInvokeSpecial, constructor),
+            (ReferenceKind::InvokeInterface, run),
+        ];
+        for (kind, reference) in valid {
+            let handle = builder.load_method_handle(kind, reference).unwrap();
+            assert_eq!(builder.load_method_handle(kind, reference), Ok(handle));
+        }
+        let invalid = [
+            (ReferenceKind::GetField, method),
+            (ReferenceKind::InvokeVirtual, constructor),
+            (ReferenceKind::InvokeVirtual, run),
+            (ReferenceKind::NewInvokeSpecial, method),
+            (ReferenceKind::InvokeInterface, method),
+        ];
+        for (kind, reference) in invalid {
+            assert_eq!(
+                builder.load_method_handle(kind, reference),
+                Err(BuilderError::InvalidConstant(reference))
+            );
+        }
+        assert_eq!(
+            builder.load_method_type(Descriptor::Int),
+            Err(BuilderError::InvalidDescriptor("I".to_string()))
+        );
+    }
+
+    #[test]
+    fn bootstrap_methods_are_shared_and_checked() {
+        let mut builder = new_class("Concat");
+        let factory = builder
+            .load_class("java/lang/invoke/StringConcatFactory")
+            .unwrap();
+        let bootstrap = builder
+            .load_method(factory, "makeConcatWithConstants", void_method())
+            .unwrap();
+        let handle = builder
+            .load_method_handle(ReferenceKind::InvokeStatic, bootstrap)
+            .unwrap();
+        let recipe = builder.load_string("\u{1}!").unwrap();
+        let other = builder.load_string("<\u{1}>").unwrap();
+        assert_eq!(builder.add_bootstrap_method(handle, &[recipe]), Ok(0));
+        assert_eq!(builder.add_bootstrap_method(handle, &[other]), Ok(1));
+        assert_eq!(builder.add_bootstrap_method(handle, &[recipe]), Ok(0));
+        assert_eq!(
+            builder.add_bootstrap_method(bootstrap, &[]),
+            Err(BuilderError::InvalidConstant(bootstrap))
+        );
+        let name = builder.constant_pool_mut().utf8("name").unwrap();
+        assert_eq!(
+            builder.add_bootstrap_method(handle, &[name]),
+            Err(BuilderError::InvalidConstant(name))
+        );
+
+        let concat = Descriptor::Function(vec![string_descriptor()], Box::new(string_descriptor()));
+        assert_eq!(
+            builder.load_invoke_dynamic(2, "makeConcatWithConstants", concat.clone()),
+            Err(BuilderError::InvalidBootstrapMethod(2))
+        );
+        let call_site = builder
+            .load_invoke_dynamic(1, "makeConcatWithConstants", concat.clone())
+            .unwrap();
+        let mut method = builder
+            .method("wrap", concat, METHOD_ACC_PUBLIC | METHOD_ACC_STATIC)
+            .unwrap();
+        method.build_aload_n(0).unwrap();
+        method.build_invokedynamic(call_site);
+        method.build_areturn().unwrap();
+        let class_file = reparse(&builder.build().unwrap());
+
+        let [high, low] = call_site.to_be_bytes();
+        assert_eq!(
+            method_code(&class_file, "wrap"),
+            [
+                Opcode::AloadN(0),
+                Opcode::Invokedynamic(high, low, 0, 0),
+                Opcode::Areturn,
+            ]
+        );
+        let bootstrap_methods = class_file.attributes.iter().find_map(|a| match &a.info {
+            AttributeInfoKind::BootstrapMethods {
+                num_bootstrap_methods,
+                bootstrap_methods,
+            } => Some((*num_bootstrap_methods, bootstrap_methods.clone())),
+            _ => None,
+        });
+        let (count, methods) = bootstrap_methods.unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(methods[1].bootstrap_arguments, [other]);
+    }
+
+    #[test]
+    fn dynamic_constants_are_bootstrap_arguments() {
+        let mut builder = new_class("Condy");
+        let bootstraps = builder
+            .load_class("java/lang/invoke/ConstantBootstraps")
+            .unwrap();
+        let descriptor = Descriptor::Function(vec![], Box::new(Descriptor::Int));
+        let bootstrap = builder
+            .load_method(bootstraps, "invoke", descriptor)
+            .unwrap();
+        let handle = builder
+            .load_method_handle(ReferenceKind::InvokeStatic, bootstrap)
+            .unwrap();
+        assert_eq!(
+            builder.load_dynamic(0, "size", Descriptor::Int),
+            Err(BuilderError::InvalidBootstrapMethod(0))
+        );
+        builder.add_bootstrap_method(handle, &[]).unwrap();
+        assert_eq!(
+            builder.load_dynamic(0, "size", void_method()),
+            Err(BuilderError::InvalidDescriptor("()V".to_string()))
+        );
+        let size = builder.load_dynamic(0, "size", Descriptor::Int).unwrap();
+        assert_eq!(builder.add_bootstrap_method(handle, &[size]), Ok(1));
+        let class_file = reparse(&builder.build().unwrap());
+        assert!(matches!(
+            class_file.get_constant(size).unwrap().info,
+            CpInfoType::Dynamic {
+                bootstrap_method_attr_index: 0,
+                ..
+            }
+        ));
+    }
+
+    fn member_names(class_file: &ClassFile) -> (Vec<&str>, Vec<&str>) {
+        let name = |index| class_file.get_utf8(index).unwrap();
+        let fields = class_file.fields.iter().map(|f| name(f.name_index));
+        let methods = class_file.method_info.iter().map(|m| name(m.name_index));
+        (fields.collect(), methods.collect())
+    }
+
+    fn int_method() -> Descriptor {
+        Descriptor::Function(vec![], Box::new(Descriptor::Int))
+    }
+
+    #[test]
+    fn parsed_classes_reuse_their_constant_pool() {
+        let mut builder = new_class("Parsed");
+        builder
+            .field("count", Descriptor::Int, FIELD_ACC_PRIVATE)
+            .unwrap();
+        let parsed = reparse(&builder.build().unwrap());
+        let super_class = parsed.super_class;
+        let count = parsed.constant_pool_count;
+
+        let mut builder = ClassBuilder::from_class_file(parsed).unwrap();
+        assert_eq!(builder.load_class("java/lang/Object"), Ok(super_class));
+        builder
+            .field("count", Descriptor::Int, FIELD_ACC_PUBLIC)
+            .unwrap();
+        assert_eq!(builder.constant_pool().count(), count);
+        let class_file = builder.build().unwrap();
+        assert_eq!(class_file.constant_pool_count, count);
+        assert_eq!(class_file.fields_count, 1);
+        assert_eq!(class_file.fields[0].access_flags, FIELD_ACC_PUBLIC);
+    }
+
+    #[test]
+    fn parsed_classes_can_gain_replace_and_lose_members() {
+        let mut builder = new_class("Parsed");
+        builder
+            .field("kept", Descriptor::Int, FIELD_ACC_PRIVATE)
+            .unwrap();
+        builder
+            .field("removed", Descriptor::Long, FIELD_ACC_PRIVATE)
+            .unwrap();
+        let flags = METHOD_ACC_PUBLIC | METHOD_ACC_STATIC;
+        let mut method = builder.method("value", int_method(), flags).unwrap();
+        method.build_iconst_i(1).unwrap();
+        method.build_ireturn().unwrap();
+        let mut method = builder.method("removed", void_method(), flags).unwrap();
+        method.build_return().unwrap();
+        let mut method = builder.method("kept", void_method(), flags).unwrap();
+        method.build_return().unwrap();
+        let parsed = reparse(&builder.build().unwrap());
+
+        let mut builder = ClassBuilder::from_class_file(parsed).unwrap();
+        assert!(builder.remove_field("removed", Descriptor::Long));
+        assert!(!builder.remove_field("removed", Descriptor::Int));
+        assert!(builder.remove_method("removed", void_method()));
+        builder
+            .field("added", Descriptor::Double, FIELD_ACC_PRIVATE)
+            .unwrap();
+        let mut method = builder.method("value", int_method(), flags).unwrap();
+        method.build_iconst_i(2).unwrap();
+        method.build_ireturn().unwrap();
+        let mut method = builder.method("added", void_method(), flags).unwrap();
+        method.build_return().unwrap();
+        let class_file = reparse(&builder.build().unwrap());
+
+        assert_eq!(class_file.fields_count, 2);
+        assert_eq!(class_file.method_count, 3);
+        let (fields, mut methods) = member_names(&class_file);
+        assert_eq!(fields, ["kept", "added"]);
+        methods.sort_unstable();
+        assert_eq!(methods, ["added", "kept", "value"]);
+        assert_eq!(method_code(&class_file, "kept"), [Opcode::Return_]);
+        assert_eq!(
+            method_code(&class_file, "value"),
+            [Opcode::IconstI(2), Opcode::Ireturn]
+        );
+    }
+}
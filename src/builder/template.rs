@@ -0,0 +1,704 @@
+use super::{BuilderError, ClassBuilder, MethodBuilder};
+use crate::{bytecode::*, descriptor::Descriptor, flags::MethodAccess, name::ClassName};
+
+const OBJECT_METHODS_BOOTSTRAP: &str = "(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;Ljava/lang/invoke/TypeDescriptor;Ljava/lang/Class;Ljava/lang/String;[Ljava/lang/invoke/MethodHandle;)Ljava/lang/Object;";
+
+fn function(parameters: Vec<Descriptor>, return_type: Descriptor) -> Descriptor {
+    Descriptor::Function(parameters, Box::new(return_type))
+}
+
+fn object(class: &str) -> Descriptor {
+    Descriptor::Object(ClassName::new(class).unwrap())
+}
+
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+impl ClassBuilder {
+    fn field_parts(
+        &mut self,
+        field: &FieldInfo,
+    ) -> Result<(String, Descriptor, u16), BuilderError> {
+        let mut class = self.class.borrow_mut();
+        let pool = &class.constant_pool;
+        let name = pool
+            .get_utf8(field.name_index)
+            .ok_or(BuilderError::InvalidConstant(field.name_index))?
+            .to_string();
+        let descriptor = pool
+            .get_utf8(field.descriptor_index)
+            .ok_or(BuilderError::InvalidConstant(field.descriptor_index))?
+            .to_string();
+        let this_class = class.class_file.this_class;
+        let field_ref = class
+            .constant_pool
+            .field_ref(this_class, &name, &descriptor)?;
+        let descriptor = Descriptor::parse_field(&descriptor)
+            .map_err(|_| BuilderError::InvalidDescriptor(descriptor))?;
+        Ok((name, descriptor, field_ref))
+    }
+
+    fn super_constructor(&mut self, parameters: Vec<Descriptor>) -> Result<u16, BuilderError> {
+        let super_class = self.class.borrow().class_file.super_class;
+        if self.constant_pool().get_class_name(super_class).is_none() {
+            return Err(BuilderError::InvalidConstant(super_class));
+        }
+        self.load_method(
+            super_class,
+            "<init>",
+            function(parameters, Descriptor::Void),
+        )
+    }
+
+    /// Adds a constructor taking no arguments that calls the one of the super
+    /// class, which must be set first.
+    pub fn add_default_constructor(
+        &mut self,
+        access_flags: impl Into<MethodAccess>,
+    ) -> Result<MethodBuilder, BuilderError> {
+        let super_init = self.super_constructor(vec![])?;
+        let mut constructor =
+            self.method("<init>", function(vec![], Descriptor::Void), access_flags)?;
+        constructor.build_aload_n(0)?;
+        constructor.build_invokespecial(super_init);
+        constructor.build_return()?;
+        Ok(constructor)
+    }
+
+    /// Adds a constructor taking a parameter for each of `fields`, in order,
+    /// that calls the constructor of the super class taking no arguments and
+    /// then initializes the fields.
+    pub fn add_field_constructor(
+        &mut self,
+        fields: &[FieldInfo],
+        access_flags: impl Into<MethodAccess>,
+    ) -> Result<MethodBuilder, BuilderError> {
+        let fields = fields
+            .iter()
+            .map(|field| self.field_parts(field))
+            .collect::<Result<Vec<_>, _>>()?;
+        let super_init = self.super_constructor(vec![])?;
+        let parameters = fields
+            .iter()
+            .map(|(_, descriptor, _)| descriptor.clone())
+            .collect();
+        let mut constructor = self.method(
+            "<init>",
+            function(parameters, Descriptor::Void),
+            access_flags,
+        )?;
+        constructor.build_aload_n(0)?;
+        constructor.build_invokespecial(super_init);
+        let mut slot = 1;
+        for (_, descriptor, field_ref) in &fields {
+            constructor.build_aload_n(0)?;
+            constructor.build_load(descriptor, slot)?;
+            constructor.build_putfield(*field_ref);
+            slot += descriptor.slot_size();
+        }
+        constructor.build_return()?;
+        Ok(constructor)
+    }
+
+    /// Adds a public getter for `field`, named `isName` for a `boolean` field
+    /// and `getName` otherwise. The getter of a static field is static.
+    pub fn add_getter(&mut self, field: &FieldInfo) -> Result<MethodBuilder, BuilderError> {
+        let (name, descriptor, field_ref) = self.field_parts(field)?;
+        let prefix = match descriptor {
+            Descriptor::Boolean => "is",
+            _ => "get",
+        };
+        let is_static = field.access_flags.is_set(FIELD_ACC_STATIC);
+        let access_flags = if is_static {
+            METHOD_ACC_PUBLIC | METHOD_ACC_STATIC
+        } else {
+            METHOD_ACC_PUBLIC
+        };
+        let getter_name = format!("{}{}", prefix, capitalize(&name));
+        let mut getter = self.method(
+            &getter_name,
+            function(vec![], descriptor.clone()),
+            access_flags,
+        )?;
+        if is_static {
+            getter.build_getstatic(field_ref);
+        } else {
+            getter.build_aload_n(0)?;
+            getter.build_getfield(field_ref);
+        }
+        getter.build_typed_return(&descriptor)?;
+        Ok(getter)
+    }
+
+    /// Adds a public setter for `field`, named `setName`. The setter of a
+    /// static field is static.
+    pub fn add_setter(&mut self, field: &FieldInfo) -> Result<MethodBuilder, BuilderError> {
+        let (name, descriptor, field_ref) = self.field_parts(field)?;
+        let is_static = field.access_flags.is_set(FIELD_ACC_STATIC);
+        let access_flags = if is_static {
+            METHOD_ACC_PUBLIC | METHOD_ACC_STATIC
+        } else {
+            METHOD_ACC_PUBLIC
+        };
+        let setter_name = format!("set{}", capitalize(&name));
+        let setter_descriptor = function(vec![descriptor.clone()], Descriptor::Void);
+        let mut setter = self.method(&setter_name, setter_descriptor, access_flags)?;
+        if is_static {
+            setter.build_load(&descriptor, 0)?;
+            setter.build_putstatic(field_ref);
+        } else {
+            setter.build_aload_n(0)?;
+            setter.build_load(&descriptor, 1)?;
+            setter.build_putfield(field_ref);
+        }
+        setter.build_return()?;
+        Ok(setter)
+    }
+
+    /// Adds `public static void main(String[] args)` and returns a builder for
+    /// its code, which must end with a `return`.
+    pub fn add_main(&mut self) -> Result<MethodBuilder, BuilderError> {
+        let args = Descriptor::Array(Box::new(object("java/lang/String")));
+        self.method(
+            "main",
+            function(vec![args], Descriptor::Void),
+            METHOD_ACC_PUBLIC | METHOD_ACC_STATIC,
+        )
+    }
+
+    /// Creates a public interface named `name`. Methods added with the
+    /// `METHOD_ACC_ABSTRACT` flag are abstract, and the others are default or
+    /// static methods.
+    pub fn new_interface(
+        name: impl TryInto<ClassName, Error: Into<BuilderError>>,
+    ) -> Result<Self, BuilderError> {
+        let mut builder = ClassBuilder::new();
+        builder.set_access_flags(PUBLIC | INTERFACE | ABSTRACT);
+        builder.set_class_name(name)?;
+        let object = builder.load_class("java/lang/Object")?;
+        builder.set_super_class(object);
+        Ok(builder)
+    }
+
+    /// Creates a public enum named `name` with the given constants, as javac
+    /// compiles it: a field for each constant, the `$VALUES` array, `values()`,
+    /// `valueOf(String)`, a private constructor and a static initializer
+    /// fragment creating the constants.
+    pub fn new_enum(
+        name: impl TryInto<ClassName, Error: Into<BuilderError>>,
+        constants: &[&str],
+    ) -> Result<Self, BuilderError> {
+        let name = name.try_into().map_err(Into::into)?;
+        let this = Descriptor::Object(name.clone());
+        let mut builder = ClassBuilder::new();
+        builder.set_access_flags(PUBLIC | FINAL | SUPER | ENUM);
+        builder.set_class_name(&name)?;
+        let enum_class = builder.load_class("java/lang/Enum")?;
+        builder.set_super_class(enum_class);
+        builder.set_signature(&format!("Ljava/lang/Enum<L{};>;", name))?;
+
+        let this_class = builder.load_class(&name)?;
+        let array = Descriptor::Array(Box::new(this.clone()));
+        let array_class = builder.load_class(array.serialize())?;
+        let string = object("java/lang/String");
+
+        let constant_flags = FIELD_ACC_PUBLIC | FIELD_ACC_STATIC | FIELD_ACC_FINAL | FIELD_ACC_ENUM;
+        let mut fields = vec![];
+        for constant in constants {
+            let field = builder.field(*constant, this.clone(), constant_flags)?;
+            fields.push(field.field_ref()?);
+        }
+        let values_flags =
+            FIELD_ACC_PRIVATE | FIELD_ACC_STATIC | FIELD_ACC_FINAL | FIELD_ACC_SYNTHETIC;
+        let values_field = builder
+            .field("$VALUES", array.clone(), values_flags)?
+            .field_ref()?;
+
+        let mut values = builder.method(
+            "values",
+            function(vec![], array.clone()),
+            METHOD_ACC_PUBLIC | METHOD_ACC_STATIC,
+        )?;
+        let clone = builder.load_method(
+            array_class,
+            "clone",
+            function(vec![], object("java/lang/Object")),
+        )?;
+        values.build_getstatic(values_field);
+        values.build_invokevirtual(clone);
+        values.build_checkcast(array_class);
+        values.build_areturn()?;
+
+        let mut value_of = builder.method(
+            "valueOf",
+            function(vec![string.clone()], this),
+            METHOD_ACC_PUBLIC | METHOD_ACC_STATIC,
+        )?;
+        let enum_value_of = builder.load_method(
+            enum_class,
+            "valueOf",
+            function(
+                vec![object("java/lang/Class"), string.clone()],
+                object("java/lang/Enum"),
+            ),
+        )?;
+        value_of.push_class(&name)?;
+        value_of.build_aload_n(0)?;
+        value_of.build_invokestatic(enum_value_of);
+        value_of.build_checkcast(this_class);
+        value_of.build_areturn()?;
+
+        let constructor_descriptor =
+            function(vec![string.clone(), Descriptor::Int], Descriptor::Void);
+        let mut constructor =
+            builder.method("<init>", constructor_descriptor.clone(), METHOD_ACC_PRIVATE)?;
+        constructor.set_signature("()V")?;
+        let enum_init = builder.load_method(enum_class, "<init>", constructor_descriptor)?;
+        constructor.build_aload_n(0)?;
+        constructor.build_aload_n(1)?;
+        constructor.build_iload_n(2)?;
+        constructor.build_invokespecial(enum_init);
+        constructor.build_return()?;
+        let init = constructor.method_ref()?.unwrap();
+
+        let values_flags = METHOD_ACC_PRIVATE | METHOD_ACC_STATIC | METHOD_ACC_SYNTHETIC;
+        let mut all_values = builder.method("$values", function(vec![], array), values_flags)?;
+        all_values.push_int(constants.len() as i32)?;
+        all_values.build_anewarray(this_class);
+        for (ordinal, field) in fields.iter().enumerate() {
+            all_values.build_dup();
+            all_values.push_int(ordinal as i32)?;
+            all_values.build_getstatic(*field);
+            all_values.build_aastore();
+        }
+        all_values.build_areturn()?;
+        let all_values = all_values.method_ref()?.unwrap();
+
+        let mut initializer = builder.static_initializer()?;
+        for (ordinal, (constant, field)) in constants.iter().zip(&fields).enumerate() {
+            initializer.build_new(this_class);
+            initializer.build_dup();
+            initializer.push_string(constant)?;
+            initializer.push_int(ordinal as i32)?;
+            initializer.build_invokespecial(init);
+            initializer.build_putstatic(*field);
+        }
+        initializer.build_invokestatic(all_values);
+        initializer.build_putstatic(values_field);
+        Ok(builder)
+    }
+
+    /// Creates a public record named `name` with the given components, as
+    /// javac compiles it: a private final field and an accessor for each
+    /// component, the canonical constructor, and `toString`, `hashCode` and
+    /// `equals` implemented by the `ObjectMethods` bootstrap method.
+    pub fn new_record(
+        name: impl TryInto<ClassName, Error: Into<BuilderError>>,
+        components: &[(&str, Descriptor)],
+    ) -> Result<Self, BuilderError> {
+        let name = name.try_into().map_err(Into::into)?;
+        let mut builder = ClassBuilder::new();
+        builder.set_access_flags(PUBLIC | FINAL | SUPER);
+        builder.set_class_name(&name)?;
+        let record_class = builder.load_class("java/lang/Record")?;
+        builder.set_super_class(record_class);
+        let this_class = builder.load_class(&name)?;
+
+        let mut fields = vec![];
+        let mut field_infos = vec![];
+        let mut record_components = vec![];
+        for (component, descriptor) in components {
+            let field = builder.field(
+                *component,
+                descriptor.clone(),
+                FIELD_ACC_PRIVATE | FIELD_ACC_FINAL,
+            )?;
+            fields.push(field.field_ref()?);
+            field_infos.push(field.field_info());
+            let mut pool = builder.constant_pool_mut();
+            record_components.push(RecordComponent {
+                name_index: pool.utf8(component)?,
+                descriptor_index: pool.utf8(&descriptor.serialize())?,
+                attributes_count: 0,
+                attributes: vec![],
+            });
+        }
+        {
+            let mut class = builder.class.borrow_mut();
+            let class = &mut *class;
+            let attribute = AttributeInfo {
+                attribute_name_index: class.constant_pool.utf8("Record")?,
+                attribute_length: 0,
+                info: AttributeInfoKind::Record {
+                    components_count: record_components.len() as u16,
+                    components: record_components,
+                },
+            };
+            super::set_attribute(
+                &mut class.class_file.attributes,
+                &mut class.class_file.attributes_count,
+                attribute,
+            );
+        }
+
+        builder.add_field_constructor(&field_infos, METHOD_ACC_PUBLIC)?;
+
+        for ((component, descriptor), field) in components.iter().zip(&fields) {
+            let mut accessor = builder.method(
+                *component,
+                function(vec![], descriptor.clone()),
+                METHOD_ACC_PUBLIC,
+            )?;
+            accessor.build_aload_n(0)?;
+            accessor.build_getfield(*field);
+            accessor.build_typed_return(descriptor)?;
+        }
+
+        let object_methods = builder.load_class("java/lang/runtime/ObjectMethods")?;
+        let bootstrap = builder.load_method(
+            object_methods,
+            "bootstrap",
+            Descriptor::parse_method(OBJECT_METHODS_BOOTSTRAP).unwrap(),
+        )?;
+        let bootstrap = builder.load_method_handle(ReferenceKind::InvokeStatic, bootstrap)?;
+        let names: Vec<&str> = components.iter().map(|(component, _)| *component).collect();
+        let mut arguments = vec![this_class, builder.load_string(&names.join(";"))?];
+        for field in &fields {
+            arguments.push(builder.load_method_handle(ReferenceKind::GetField, *field)?);
+        }
+        let bootstrap = builder.add_bootstrap_method(bootstrap, &arguments)?;
+
+        let object_methods = [
+            ("toString", vec![], object("java/lang/String")),
+            ("hashCode", vec![], Descriptor::Int),
+            (
+                "equals",
+                vec![object("java/lang/Object")],
+                Descriptor::Boolean,
+            ),
+        ];
+        for (method_name, parameters, return_type) in object_methods {
+            let mut method = builder.method(
+                method_name,
+                function(parameters.clone(), return_type.clone()),
+                METHOD_ACC_PUBLIC | METHOD_ACC_FINAL,
+            )?;
+            let mut call_parameters = vec![Descriptor::Object(name.clone())];
+            call_parameters.extend(parameters.iter().cloned());
+            let call_site = builder.load_invoke_dynamic(
+                bootstrap,
+                method_name,
+                function(call_parameters, return_type.clone()),
+            )?;
+            for slot in 0..=parameters.len() as u16 {
+                method.build_aload(slot);
+            }
+            method.build_invokedynamic(call_site);
+            method.build_typed_return(&return_type)?;
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bytecode::instr::Opcode, parser::Parser, reader::Reader};
+
+    fn reparse(builder: &ClassBuilder) -> ClassFile {
+        let class_file = builder.build().unwrap();
+        Parser {
+            reader: Reader::new(class_file.to_jvm_bytecode()),
+        }
+        .parse()
+    }
+
+    fn method<'a>(class_file: &'a ClassFile, name: &str) -> &'a MethodInfo {
+        class_file
+            .method_info
+            .iter()
+            .find(|m| class_file.get_utf8(m.name_index) == Some(name))
+            .unwrap()
+    }
+
+    fn code(method: &MethodInfo) -> Option<&[Opcode]> {
+        method.attributes.iter().find_map(|a| match &a.info {
+            AttributeInfoKind::Code { code, .. } => Some(&code[..]),
+            _ => None,
+        })
+    }
+
+    fn descriptor<'a>(class_file: &'a ClassFile, name: &str) -> &'a str {
+        let method = method(class_file, name);
+        class_file.get_utf8(method.descriptor_index).unwrap()
+    }
+
+    #[test]
+    fn enums_have_the_members_javac_generates() {
+        let builder = ClassBuilder::new_enum("Color", &["RED", "GREEN"]).unwrap();
+        let class_file = reparse(&builder);
+        assert_eq!(class_file.access_flags, PUBLIC | FINAL | SUPER | ENUM);
+        assert_eq!(
+            class_file.get_class_name(class_file.super_class),
+            Some("java/lang/Enum")
+        );
+        assert_eq!(
+            class_file.get_signature(&class_file.attributes),
+            Some("Ljava/lang/Enum<LColor;>;")
+        );
+        let fields: Vec<_> = class_file
+            .fields
+            .iter()
+            .map(|f| class_file.get_utf8(f.name_index).unwrap())
+            .collect();
+        assert_eq!(fields, ["RED", "GREEN", "$VALUES"]);
+        assert_eq!(descriptor(&class_file, "values"), "()[LColor;");
+        assert_eq!(
+            descriptor(&class_file, "valueOf"),
+            "(Ljava/lang/String;)LColor;"
+        );
+        assert_eq!(descriptor(&class_file, "<init>"), "(Ljava/lang/String;I)V");
+        assert_eq!(
+            method(&class_file, "<init>").access_flags,
+            METHOD_ACC_PRIVATE
+        );
+        let initializer = code(method(&class_file, "<clinit>")).unwrap();
+        let created = initializer
+            .iter()
+            .filter(|op| matches!(op, Opcode::New(..)))
+            .count();
+        assert_eq!(created, 2);
+        assert_eq!(initializer.last(), Some(&Opcode::Return_));
+    }
+
+    #[test]
+    fn records_have_accessors_and_object_methods() {
+        let components = [("x", Descriptor::Int), ("y", Descriptor::Long)];
+        let builder = ClassBuilder::new_record("Point", &components).unwrap();
+        let class_file = reparse(&builder);
+        assert_eq!(
+            class_file.get_class_name(class_file.super_class),
+            Some("java/lang/Record")
+        );
+        let record = class_file.attributes.iter().find_map(|a| match &a.info {
+            AttributeInfoKind::Record { components, .. } => Some(components),
+            _ => None,
+        });
+        let names: Vec<_> = record
+            .unwrap()
+            .iter()
+            .map(|c| class_file.get_utf8(c.name_index).unwrap())
+            .collect();
+        assert_eq!(names, ["x", "y"]);
+        assert_eq!(descriptor(&class_file, "<init>"), "(IJ)V");
+        assert_eq!(descriptor(&class_file, "y"), "()J");
+        assert_eq!(
+            code(method(&class_file, "y")).unwrap().last(),
+            Some(&Opcode::Lreturn)
+        );
+
+        let bootstrap_methods = class_file.attributes.iter().find_map(|a| match &a.info {
+            AttributeInfoKind::BootstrapMethods {
+                bootstrap_methods, ..
+            } => Some(bootstrap_methods),
+            _ => None,
+        });
+        let bootstrap_methods = bootstrap_methods.unwrap();
+        assert_eq!(bootstrap_methods.len(), 1);
+        // The record class, the component names and a getter per component.
+        assert_eq!(bootstrap_methods[0].num_bootstrap_arguments, 4);
+        let equals = code(method(&class_file, "equals")).unwrap();
+        assert!(matches!(
+            equals,
+            [
+                Opcode::Aload(0),
+                Opcode::Aload(1),
+                Opcode::Invokedynamic(..),
+                Opcode::Ireturn
+            ]
+        ));
+    }
+
+    #[test]
+    fn interfaces_have_abstract_default_and_static_methods() {
+        let mut builder = ClassBuilder::new_interface("Shape").unwrap();
+        let area = function(vec![], Descriptor::Double);
+        let flags = METHOD_ACC_PUBLIC | METHOD_ACC_ABSTRACT;
+        let abstract_area = builder.method("area", area.clone(), flags).unwrap();
+        let area_ref = abstract_area.method_ref().unwrap().unwrap();
+        let mut twice = builder
+            .method("twice", area.clone(), METHOD_ACC_PUBLIC)
+            .unwrap();
+        twice.build_aload_n(0).unwrap();
+        twice.build_invokeinterface(area_ref).unwrap();
+        twice.build_dup2();
+        twice.build_dadd();
+        twice.build_dreturn().unwrap();
+        let flags = METHOD_ACC_PUBLIC | METHOD_ACC_STATIC;
+        let mut zero = builder.method("zero", area, flags).unwrap();
+        zero.build_dconst_d(0).unwrap();
+        zero.build_dreturn().unwrap();
+        let class_file = reparse(&builder);
+
+        assert_eq!(class_file.access_flags, PUBLIC | INTERFACE | ABSTRACT);
+        assert!(matches!(
+            class_file.get_constant(area_ref),
+            Some(CpInfo {
+                info: CpInfoType::InterfaceMethodref { .. },
+                ..
+            })
+        ));
+        assert_eq!(code(method(&class_file, "area")), None);
+        assert!(code(method(&class_file, "twice")).is_some());
+        assert_eq!(
+            code(method(&class_file, "zero")),
+            Some(&[Opcode::DconstD(0), Opcode::Dreturn][..])
+        );
+    }
+
+    fn new_class(name: &str) -> ClassBuilder {
+        let mut builder = ClassBuilder::new();
+        builder.set_access_flags(PUBLIC | SUPER);
+        builder.set_class_name(name).unwrap();
+        let object = builder.load_class("java/lang/Object").unwrap();
+        builder.set_super_class(object);
+        builder
+    }
+
+    fn max_locals(method: &MethodInfo) -> u16 {
+        method
+            .attributes
+            .iter()
+            .find_map(|a| match &a.info {
+                AttributeInfoKind::Code { max_locals, .. } => Some(*max_locals),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn default_constructor_calls_the_super_constructor() {
+        let mut builder = ClassBuilder::new();
+        builder.set_class_name("Orphan").unwrap();
+        assert_eq!(
+            builder.add_default_constructor(METHOD_ACC_PUBLIC).err(),
+            Some(BuilderError::InvalidConstant(0))
+        );
+
+        let mut builder = new_class("Plain");
+        builder.add_default_constructor(METHOD_ACC_PUBLIC).unwrap();
+        let class_file = reparse(&builder);
+        let constructor = code(method(&class_file, "<init>")).unwrap();
+        let [Opcode::AloadN(0), Opcode::Invokespecial(high, low), Opcode::Return_] = constructor
+        else {
+            panic!("unexpected constructor code {:?}", constructor);
+        };
+        assert_eq!(
+            class_file.get_member_ref(u16::from_be_bytes([*high, *low])),
+            Some(("java/lang/Object", "<init>", "()V"))
+        );
+    }
+
+    #[test]
+    fn field_constructor_stores_each_parameter() {
+        let mut builder = new_class("Pair");
+        let first = builder
+            .field("first", Descriptor::Int, FIELD_ACC_PRIVATE)
+            .unwrap()
+            .field_info();
+        let second = builder
+            .field("second", Descriptor::Long, FIELD_ACC_PRIVATE)
+            .unwrap()
+            .field_info();
+        builder
+            .add_field_constructor(&[first, second], METHOD_ACC_PUBLIC)
+            .unwrap();
+        let class_file = reparse(&builder);
+        assert_eq!(descriptor(&class_file, "<init>"), "(IJ)V");
+        let constructor = method(&class_file, "<init>");
+        assert_eq!(max_locals(constructor), 4);
+        assert!(matches!(
+            code(constructor).unwrap(),
+            [
+                Opcode::AloadN(0),
+                Opcode::Invokespecial(..),
+                Opcode::AloadN(0),
+                Opcode::IloadN(1),
+                Opcode::Putfield(..),
+                Opcode::AloadN(0),
+                Opcode::LloadN(2),
+                Opcode::Putfield(..),
+                Opcode::Return_,
+            ]
+        ));
+    }
+
+    #[test]
+    fn accessors_follow_the_field() {
+        let mut builder = new_class("Bean");
+        let active = builder
+            .field("active", Descriptor::Boolean, FIELD_ACC_PRIVATE)
+            .unwrap()
+            .field_info();
+        let flags = FIELD_ACC_PRIVATE | FIELD_ACC_STATIC;
+        let count = builder
+            .field("count", Descriptor::Long, flags)
+            .unwrap()
+            .field_info();
+        builder.add_getter(&active).unwrap();
+        builder.add_setter(&active).unwrap();
+        builder.add_getter(&count).unwrap();
+        builder.add_setter(&count).unwrap();
+        let class_file = reparse(&builder);
+
+        assert_eq!(descriptor(&class_file, "isActive"), "()Z");
+        assert_eq!(descriptor(&class_file, "setActive"), "(Z)V");
+        assert_eq!(descriptor(&class_file, "getCount"), "()J");
+        assert_eq!(
+            method(&class_file, "isActive").access_flags,
+            METHOD_ACC_PUBLIC
+        );
+        assert_eq!(
+            method(&class_file, "getCount").access_flags,
+            METHOD_ACC_PUBLIC | METHOD_ACC_STATIC
+        );
+        assert!(matches!(
+            code(method(&class_file, "setActive")).unwrap(),
+            [
+                Opcode::AloadN(0),
+                Opcode::IloadN(1),
+                Opcode::Putfield(..),
+                Opcode::Return_
+            ]
+        ));
+        assert!(matches!(
+            code(method(&class_file, "getCount")).unwrap(),
+            [Opcode::Getstatic(..), Opcode::Lreturn]
+        ));
+        let set_count = method(&class_file, "setCount");
+        assert_eq!(max_locals(set_count), 2);
+        assert!(matches!(
+            code(set_count).unwrap(),
+            [Opcode::LloadN(0), Opcode::Putstatic(..), Opcode::Return_]
+        ));
+    }
+
+    #[test]
+    fn main_is_public_and_static() {
+        let mut builder = new_class("App");
+        let mut main = builder.add_main().unwrap();
+        main.build_return().unwrap();
+        let class_file = reparse(&builder);
+        assert_eq!(descriptor(&class_file, "main"), "([Ljava/lang/String;)V");
+        assert_eq!(
+            method(&class_file, "main").access_flags,
+            METHOD_ACC_PUBLIC | METHOD_ACC_STATIC
+        );
+        assert_eq!(max_locals(method(&class_file, "main")), 1);
+    }
+}
@@ -7,13 +7,13 @@ use super::ToJvmBytecode;
 const AALOAD: u8 = 0x32;
 const AASTORE: u8 = 0x53;
 const ACONST_NULL: u8 = 0x1;
-const ALOAD: u8 = 0x19;
+pub(crate) const ALOAD: u8 = 0x19;
 const ALOAD_N: u8 = 0x2a;
 
 const ANEWARRAY: u8 = 0xbd;
 const ARETURN: u8 = 0xb0;
 const ARRAYLENGTH: u8 = 0xbe;
-const ASTORE: u8 = 0x3a;
+pub(crate) const ASTORE: u8 = 0x3a;
 const ASTORE_N: u8 = 0x4b;
 
 const ATHROW: u8 = 0xbf;
@@ -29,19 +29,20 @@ const D2L: u8 = 0x8f;
 const DADD: u8 = 0x63;
 const DALOAD: u8 = 0x31;
 const DASTORE: u8 = 0x52;
-const DCM_N: u8 = 0x98;
+const DCMPL: u8 = 0x97;
+const DCMPG: u8 = 0x98;
 
 const DCONST_N: u8 = 0xe;
 
 const DDIV: u8 = 0x6f;
-const DLOAD: u8 = 0x18;
+pub(crate) const DLOAD: u8 = 0x18;
 const DLOAD_N: u8 = 0x26;
 
 const DMUL: u8 = 0x6b;
 const DNEG: u8 = 0x77;
 const DREM: u8 = 0x73;
 const DRETURN: u8 = 0xaf;
-const DSTORE: u8 = 0x39;
+pub(crate) const DSTORE: u8 = 0x39;
 const DSTORE_N: u8 = 0x47;
 
 const DSUB: u8 = 0x67;
@@ -57,19 +58,20 @@ const F2L: u8 = 0x8c;
 const FADD: u8 = 0x62;
 const FALOAD: u8 = 0x30;
 const FASTORE: u8 = 0x51;
-const FCM_N: u8 = 0x96;
+const FCMPL: u8 = 0x95;
+const FCMPG: u8 = 0x96;
 
 const FCONST_N: u8 = 0xb;
 
 const FDIV: u8 = 0x6e;
-const FLOAD: u8 = 0x17;
+pub(crate) const FLOAD: u8 = 0x17;
 const FLOAD_N: u8 = 0x22;
 
 const FMUL: u8 = 0x6a;
 const FNEG: u8 = 0x76;
 const FREM: u8 = 0x72;
 const FRETURN: u8 = 0xae;
-const FSTORE: u8 = 0x38;
+pub(crate) const FSTORE: u8 = 0x38;
 const FSTORE_N: u8 = 0x43;
 
 const FSUB: u8 = 0x66;
@@ -99,7 +101,7 @@ const I_N: u8 = 0x99;
 const IFNONNULL: u8 = 0xc7;
 const IFNULL: u8 = 0xc6;
 const IINC: u8 = 0x84;
-const ILOAD: u8 = 0x15;
+pub(crate) const ILOAD: u8 = 0x15;
 const ILOAD_N: u8 = 0x1a;
 
 const IMUL: u8 = 0x68;
@@ -115,7 +117,7 @@ const IREM: u8 = 0x70;
 const IRETURN: u8 = 0xac;
 const ISHL: u8 = 0x78;
 const ISHR: u8 = 0x7a;
-const ISTORE: u8 = 0x36;
+pub(crate) const ISTORE: u8 = 0x36;
 const ISTORE_N: u8 = 0x3b;
 
 const ISUB: u8 = 0x64;
@@ -137,7 +139,7 @@ const LDC: u8 = 0x12;
 const LDC_W: u8 = 0x13;
 const LDC2_W: u8 = 0x14;
 const LDIV: u8 = 0x6d;
-const LLOAD: u8 = 0x16;
+pub(crate) const LLOAD: u8 = 0x16;
 const LLOAD_N: u8 = 0x1e;
 
 const LMUL: u8 = 0x69;
@@ -148,7 +150,7 @@ const LREM: u8 = 0x71;
 const LRETURN: u8 = 0xad;
 const LSHL: u8 = 0x79;
 const LSHR: u8 = 0x7b;
-const LSTORE: u8 = 0x37;
+pub(crate) const LSTORE: u8 = 0x37;
 const LSTORE_N: u8 = 0x3f;
 
 const LSUB: u8 = 0x65;
@@ -164,15 +166,66 @@ const POP: u8 = 0x57;
 const POP2: u8 = 0x58;
 const PUTFIELD: u8 = 0xb5;
 const PUTSTATIC: u8 = 0xb3;
-const RET: u8 = 0xa9;
+pub(crate) const RET: u8 = 0xa9;
 const RETURN: u8 = 0xb1;
 const SALOAD: u8 = 0x35;
 const SASTORE: u8 = 0x56;
 const SIPUSH: u8 = 0x11;
 const SWAP: u8 = 0x5f;
 const TABLESWITCH: u8 = 0xaa;
+const WIDE: u8 = 0xc4;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// The comparison made by an `if<cond>`, `if_icmp<cond>` or `if_acmp<cond>`
+/// instruction, in opcode order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cond {
+    Eq,
+    Ne,
+    Lt,
+    Ge,
+    Gt,
+    Le,
+}
+
+impl Cond {
+    fn from_offset(offset: u8) -> Cond {
+        match offset {
+            0 => Cond::Eq,
+            1 => Cond::Ne,
+            2 => Cond::Lt,
+            3 => Cond::Ge,
+            4 => Cond::Gt,
+            _ => Cond::Le,
+        }
+    }
+
+    /// Returns the condition that holds exactly when `self` doesn't.
+    pub fn negate(self) -> Cond {
+        match self {
+            Cond::Eq => Cond::Ne,
+            Cond::Ne => Cond::Eq,
+            Cond::Lt => Cond::Ge,
+            Cond::Ge => Cond::Lt,
+            Cond::Gt => Cond::Le,
+            Cond::Le => Cond::Gt,
+        }
+    }
+}
+
+/// The element type operand of `newarray`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrayType {
+    Boolean = 4,
+    Char = 5,
+    Float = 6,
+    Double = 7,
+    Byte = 8,
+    Short = 9,
+    Int = 10,
+    Long = 11,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Opcode {
     Aaload,                                                              // aaload
     Aastore,                                                             // aastore
@@ -197,17 +250,18 @@ pub enum Opcode {
     Dadd,                            // dadd
     Daload,                          // daload
     Dastore,                         // dastore
-    DcmOP,                           // dcmp<op>
-    DconstD,                         // dconst_<d>
+    Dcmpl,                           // dcmpl
+    Dcmpg,                           // dcmpg
+    DconstD(u8),                     // dconst_<d>
     Ddiv,                            // ddiv
     Dload(u8),                       // dload index
-    DloadN,                          // dload_<n>
+    DloadN(u8),                      // dload_<n>
     Dmul,                            // dmul
     Dneg,                            // dneg
     Drem,                            // drem
     Dreturn,                         // dreturn
     Dstore(u8),                      // dstore index
-    DstoreN,                         // dstore_<n>
+    DstoreN(u8),                     // dstore_<n>
     Dsub,                            // dsub
     Dup,                             // dup
     DupX1,                           // dup_x1
@@ -221,17 +275,18 @@ pub enum Opcode {
     Fadd,                            // fadd
     Faload,                          // faload
     Fastore,                         // fastore
-    FcmOP,                           // fcmp<op>
-    FconstF,                         // fconst_<f>
+    Fcmpl,                           // fcmpl
+    Fcmpg,                           // fcmpg
+    FconstF(u8),                     // fconst_<f>
     Fdiv,                            // fdiv
     Fload(u8),                       // fload index
-    FloadN,                          // fload_<n>
+    FloadN(u8),                      // fload_<n>
     Fmul,                            // fmul
     Fneg,                            // fneg
     Frem,                            // frem
     Freturn,                         // freturn
     Fstore(u8),                      // fstore index
-    FstoreN,                         // fstore_<n>
+    FstoreN(u8),                     // fstore_<n>
     Fsub,                            // fsub
     Getfield(u8, u8),                // getfield indexbyte1 indexbyte2
     Getstatic(u8, u8),               // getstatic indexbyte1 indexbyte2
@@ -247,11 +302,11 @@ pub enum Opcode {
     Iaload,                          // iaload
     Iand,                            // iand
     Iastore,                         // iastore
-    IconstI,                         // iconst_<i>
+    IconstI(i8),                     // iconst_<i>
     Idiv,                            // idiv
-    IfAcmCond(u8, u8),               // if_acmp<cond> branchbyte1 branchbyte2
-    IfIcmCond(u8, u8),               // if_icmp<cond> branchbyte1 branchbyte2
-    ICOND(u8, u8),                   // if<cond> branchbyte1 branchbyte2
+    IfAcmCond(Cond, u8, u8),         // if_acmp<cond> branchbyte1 branchbyte2
+    IfIcmCond(Cond, u8, u8),         // if_icmp<cond> branchbyte1 branchbyte2
+    ICOND(Cond, u8, u8),             // if<cond> branchbyte1 branchbyte2
     Ifnonnull(u8, u8),               // ifnonnull branchbyte1 branchbyte2
     Ifnull(u8, u8),                  // ifnull branchbyte1 branchbyte2
     Iinc(u8, u8),                    // iinc index const
@@ -294,7 +349,7 @@ pub enum Opcode {
     LloadN(u8),                      // lload_<n>
     Lmul,                            // lmul
     Lneg,                            // lneg
-    Lookupswitch(u8, u8, u8, u8, u8, u8, u8, u8, u8, u8), // lookupswitch <0-3 byte pad> defaultbyte1 defaultbyte2 defaultbyte3 defaultbyte4 npairs1 npairs2 npairs3 npairs4 match-offset pairs...
+    Lookupswitch(i32, Vec<(i32, i32)>),                  // lookupswitch <0-3 byte pad> default npairs match-offset pairs...
     Lor,                                                  // lor
     Lrem,                                                 // lrem
     Lreturn,                                              // lreturn
@@ -321,10 +376,27 @@ pub enum Opcode {
     Sastore,                    // sastore
     Sipush(u8, u8),             // sipush byte1 byte2
     Swap,                       // swap
-    Tableswitch(u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8, u8), // tableswitch <0-3 byte pad> defaultbyte1 defaultbyte2 defaultbyte3 defaultbyte4 lowbyte1 lowbyte2 lowbyte3 lowbyte4 highbyte1 highbyte2 highbyte3 highbyte4 jump offsets...
+    Tableswitch(i32, i32, i32, Vec<i32>), // tableswitch <0-3 byte pad> default low high jump offsets...
+    Wide(u8, u8, u8),                     // wide <opcode> indexbyte1 indexbyte2
+    WideIinc(u8, u8, u8, u8),             // wide iinc indexbyte1 indexbyte2 constbyte1 constbyte2
 }
 impl ToJvmBytecode for Opcode {
+    /// Encodes the instruction as if it were located at pc 0, which only
+    /// matters for the padding of `lookupswitch` and `tableswitch`.
     fn to_jvm_bytecode(&self) -> Vec<u8> {
+        self.to_jvm_bytecode_at(0)
+    }
+}
+
+/// Returns the number of padding bytes following a switch opcode at `pc`, so
+/// that its operands start at a multiple of four.
+fn switch_padding(pc: usize) -> usize {
+    (4 - (pc + 1) % 4) % 4
+}
+
+impl Opcode {
+    /// Encodes the instruction located at `pc` in the code array.
+    pub fn to_jvm_bytecode_at(&self, pc: usize) -> Vec<u8> {
         let mut bytecode = vec![];
 
         match self {
@@ -372,14 +444,15 @@ impl ToJvmBytecode for Opcode {
             Opcode::Dadd => bytecode.push(DADD),
             Opcode::Daload => bytecode.push(DALOAD),
             Opcode::Dastore => bytecode.push(DASTORE),
-            Opcode::DcmOP => bytecode.push(DCM_N),
-            Opcode::DconstD => bytecode.push(DCONST_N),
+            Opcode::Dcmpl => bytecode.push(DCMPL),
+            Opcode::Dcmpg => bytecode.push(DCMPG),
+            Opcode::DconstD(d) => bytecode.push(DCONST_N + d),
             Opcode::Ddiv => bytecode.push(DDIV),
             Opcode::Dload(index) => {
                 bytecode.push(DLOAD);
                 bytecode.push(*index);
             }
-            Opcode::DloadN => bytecode.push(DLOAD_N),
+            Opcode::DloadN(n) => bytecode.push(DLOAD_N + n),
             Opcode::Dmul => bytecode.push(DMUL),
             Opcode::Dneg => bytecode.push(DNEG),
             Opcode::Drem => bytecode.push(DREM),
@@ -388,7 +461,7 @@ impl ToJvmBytecode for Opcode {
                 bytecode.push(DSTORE);
                 bytecode.push(*index);
             }
-            Opcode::DstoreN => bytecode.push(DSTORE_N),
+            Opcode::DstoreN(n) => bytecode.push(DSTORE_N + n),
             Opcode::Dsub => bytecode.push(DSUB),
             Opcode::Dup => bytecode.push(DUP),
             Opcode::DupX1 => bytecode.push(DUP_X1),
@@ -402,14 +475,15 @@ impl ToJvmBytecode for Opcode {
             Opcode::Fadd => bytecode.push(FADD),
             Opcode::Faload => bytecode.push(FALOAD),
             Opcode::Fastore => bytecode.push(FASTORE),
-            Opcode::FcmOP => bytecode.push(FCM_N),
-            Opcode::FconstF => bytecode.push(FCONST_N),
+            Opcode::Fcmpl => bytecode.push(FCMPL),
+            Opcode::Fcmpg => bytecode.push(FCMPG),
+            Opcode::FconstF(f) => bytecode.push(FCONST_N + f),
             Opcode::Fdiv => bytecode.push(FDIV),
             Opcode::Fload(index) => {
                 bytecode.push(FLOAD);
                 bytecode.push(*index);
             }
-            Opcode::FloadN => bytecode.push(FLOAD_N),
+            Opcode::FloadN(n) => bytecode.push(FLOAD_N + n),
             Opcode::Fmul => bytecode.push(FMUL),
             Opcode::Fneg => bytecode.push(FNEG),
             Opcode::Frem => bytecode.push(FREM),
@@ -418,7 +492,7 @@ impl ToJvmBytecode for Opcode {
                 bytecode.push(FSTORE);
                 bytecode.push(*index);
             }
-            Opcode::FstoreN => bytecode.push(FSTORE_N),
+            Opcode::FstoreN(n) => bytecode.push(FSTORE_N + n),
             Opcode::Fsub => bytecode.push(FSUB),
             Opcode::Getfield(index1, index2) => {
                 bytecode.push(GETFIELD);
@@ -452,20 +526,20 @@ impl ToJvmBytecode for Opcode {
             Opcode::Iaload => bytecode.push(IALOAD),
             Opcode::Iand => bytecode.push(IAND),
             Opcode::Iastore => bytecode.push(IASTORE),
-            Opcode::IconstI => bytecode.push(ICONST_N),
+            Opcode::IconstI(i) => bytecode.push((ICONST_N as i8 + 1 + i) as u8),
             Opcode::Idiv => bytecode.push(IDIV),
-            Opcode::IfAcmCond(branch1, branch2) => {
-                bytecode.push(IF_ACM_N);
+            Opcode::IfAcmCond(cond, branch1, branch2) => {
+                bytecode.push(IF_ACM_N + *cond as u8);
                 bytecode.push(*branch1);
                 bytecode.push(*branch2);
             }
-            Opcode::IfIcmCond(branch1, branch2) => {
-                bytecode.push(IF_ICM_N);
+            Opcode::IfIcmCond(cond, branch1, branch2) => {
+                bytecode.push(IF_ICM_N + *cond as u8);
                 bytecode.push(*branch1);
                 bytecode.push(*branch2);
             }
-            Opcode::ICOND(branch1, branch2) => {
-                bytecode.push(I_N);
+            Opcode::ICOND(cond, branch1, branch2) => {
+                bytecode.push(I_N + *cond as u8);
                 bytecode.push(*branch1);
                 bytecode.push(*branch2);
             }
@@ -502,12 +576,15 @@ impl ToJvmBytecode for Opcode {
                 bytecode.push(INVOKEDYNAMIC);
                 bytecode.push(*index1);
                 bytecode.push(*index2);
+                bytecode.push(0);
+                bytecode.push(0);
             }
             Opcode::Invokeinterface(index1, index2, count, _) => {
                 bytecode.push(INVOKEINTERFACE);
                 bytecode.push(*index1);
                 bytecode.push(*index2);
                 bytecode.push(*count);
+                bytecode.push(0);
             }
             Opcode::Invokespecial(index1, index2) => {
                 bytecode.push(INVOKESPECIAL);
@@ -582,19 +659,15 @@ impl ToJvmBytecode for Opcode {
             Opcode::LloadN(n) => bytecode.push(LLOAD_N + n),
             Opcode::Lmul => bytecode.push(LMUL),
             Opcode::Lneg => bytecode.push(LNEG),
-            Opcode::Lookupswitch(
-                defaultbyte1,
-                defaultbyte2,
-                defaultbyte3,
-                defaultbyte4,
-                npairs1,
-                npairs2,
-                npairs3,
-                npairs4,
-                match_offset,
-                pairs,
-            ) => {
-                todo!()
+            Opcode::Lookupswitch(default, pairs) => {
+                bytecode.push(LOOKUPSWITCH);
+                bytecode.extend(std::iter::repeat_n(0, switch_padding(pc)));
+                bytecode.extend_from_slice(&default.to_be_bytes());
+                bytecode.extend_from_slice(&(pairs.len() as i32).to_be_bytes());
+                for (key, offset) in pairs {
+                    bytecode.extend_from_slice(&key.to_be_bytes());
+                    bytecode.extend_from_slice(&offset.to_be_bytes());
+                }
             }
             Opcode::Lor => bytecode.push(LOR),
             Opcode::Lrem => bytecode.push(LREM),
@@ -652,16 +725,41 @@ impl ToJvmBytecode for Opcode {
                 bytecode.push(*byte2);
             }
             Opcode::Swap => bytecode.push(SWAP),
-            Opcode::Tableswitch(..) => {
-                todo!()
+            Opcode::Tableswitch(default, low, high, offsets) => {
+                bytecode.push(TABLESWITCH);
+                bytecode.extend(std::iter::repeat_n(0, switch_padding(pc)));
+                bytecode.extend_from_slice(&default.to_be_bytes());
+                bytecode.extend_from_slice(&low.to_be_bytes());
+                bytecode.extend_from_slice(&high.to_be_bytes());
+                for offset in offsets {
+                    bytecode.extend_from_slice(&offset.to_be_bytes());
+                }
+            }
+            Opcode::Wide(opcode, index1, index2) => {
+                bytecode.push(WIDE);
+                bytecode.push(*opcode);
+                bytecode.push(*index1);
+                bytecode.push(*index2);
+            }
+            Opcode::WideIinc(index1, index2, const1, const2) => {
+                bytecode.push(WIDE);
+                bytecode.push(IINC);
+                bytecode.push(*index1);
+                bytecode.push(*index2);
+                bytecode.push(*const1);
+                bytecode.push(*const2);
             }
         }
         bytecode
     }
 }
 impl Opcode {
+    /// Decodes the next instruction. The reader's cursor must be relative to
+    /// the start of the code array for switch padding to be skipped correctly.
     pub fn from_reader(reader: &mut Reader) -> Option<Opcode> {
+        let pc = reader.cursor;
         let opcode = reader.read_int1();
+        let read_i32 = |reader: &mut Reader| reader.read_int4() as i32;
         match opcode {
             AALOAD => Some(Opcode::Aaload),
             AASTORE => Some(Opcode::Aastore),
@@ -669,6 +767,7 @@ impl Opcode {
             ALOAD => Some(Opcode::Aload(reader.read_int1())),
             n if n >= ALOAD_N && n - ALOAD_N <= 3 => Some(Opcode::AloadN(n - ALOAD_N)),
             ANEWARRAY => Some(Opcode::Anewarray(reader.read_int1(), reader.read_int1())),
+            ARETURN => Some(Opcode::Areturn),
             ARRAYLENGTH => Some(Opcode::Arraylength),
             ASTORE => Some(Opcode::Astore(reader.read_int1())),
             n if n >= ASTORE_N && n - ASTORE_N <= 3 => Some(Opcode::AstoreN(n - ASTORE_N)),
@@ -685,17 +784,18 @@ impl Opcode {
             DADD => Some(Opcode::Dadd),
             DALOAD => Some(Opcode::Daload),
             DASTORE => Some(Opcode::Dastore),
-            DCM_N => Some(Opcode::DcmOP),
-            n if n >= DCONST_N && n - DCONST_N <= 1 => Some(Opcode::DconstD),
+            DCMPL => Some(Opcode::Dcmpl),
+            DCMPG => Some(Opcode::Dcmpg),
+            n if n >= DCONST_N && n - DCONST_N <= 1 => Some(Opcode::DconstD(n - DCONST_N)),
             DDIV => Some(Opcode::Ddiv),
             DLOAD => Some(Opcode::Dload(reader.read_int1())),
-            n if n >= DLOAD_N && n - DLOAD_N <= 3 => Some(Opcode::DloadN),
+            n if n >= DLOAD_N && n - DLOAD_N <= 3 => Some(Opcode::DloadN(n - DLOAD_N)),
             DMUL => Some(Opcode::Dmul),
             DNEG => Some(Opcode::Dneg),
             DREM => Some(Opcode::Drem),
             DRETURN => Some(Opcode::Dreturn),
             DSTORE => Some(Opcode::Dstore(reader.read_int1())),
-            n if n >= DSTORE_N && n - DSTORE_N <= 3 => Some(Opcode::DstoreN),
+            n if n >= DSTORE_N && n - DSTORE_N <= 3 => Some(Opcode::DstoreN(n - DSTORE_N)),
             DSUB => Some(Opcode::Dsub),
             DUP => Some(Opcode::Dup),
             DUP_X1 => Some(Opcode::DupX1),
@@ -709,17 +809,18 @@ impl Opcode {
             FADD => Some(Opcode::Fadd),
             FALOAD => Some(Opcode::Faload),
             FASTORE => Some(Opcode::Fastore),
-            FCM_N => Some(Opcode::FcmOP),
-            n if n >= FCONST_N && n - FCONST_N <= 2 => Some(Opcode::FconstF),
+            FCMPL => Some(Opcode::Fcmpl),
+            FCMPG => Some(Opcode::Fcmpg),
+            n if n >= FCONST_N && n - FCONST_N <= 2 => Some(Opcode::FconstF(n - FCONST_N)),
             FDIV => Some(Opcode::Fdiv),
             FLOAD => Some(Opcode::Fload(reader.read_int1())),
-            n if n >= FLOAD_N && n - FLOAD_N <= 3 => Some(Opcode::FloadN),
+            n if n >= FLOAD_N && n - FLOAD_N <= 3 => Some(Opcode::FloadN(n - FLOAD_N)),
             FMUL => Some(Opcode::Fmul),
             FNEG => Some(Opcode::Fneg),
             FREM => Some(Opcode::Frem),
             FRETURN => Some(Opcode::Freturn),
             FSTORE => Some(Opcode::Fstore(reader.read_int1())),
-            n if n >= FSTORE_N && n - FSTORE_N <= 3 => Some(Opcode::FstoreN),
+            n if n >= FSTORE_N && n - FSTORE_N <= 3 => Some(Opcode::FstoreN(n - FSTORE_N)),
             FSUB => Some(Opcode::Fsub),
             GETFIELD => Some(Opcode::Getfield(reader.read_int1(), reader.read_int1())),
             GETSTATIC => Some(Opcode::Getstatic(reader.read_int1(), reader.read_int1())),
@@ -740,11 +841,23 @@ impl Opcode {
             IALOAD => Some(Opcode::Iaload),
             IAND => Some(Opcode::Iand),
             IASTORE => Some(Opcode::Iastore),
-            n if n >= ICONST_N && n - ICONST_N <= 5 => Some(Opcode::IconstI),
+            n if n >= ICONST_N && n - ICONST_N <= 6 => Some(Opcode::IconstI((n - ICONST_N) as i8 - 1)),
             IDIV => Some(Opcode::Idiv),
-            IF_ACM_N => Some(Opcode::IfAcmCond(reader.read_int1(), reader.read_int1())),
-            IF_ICM_N => Some(Opcode::IfIcmCond(reader.read_int1(), reader.read_int1())),
-            I_N => Some(Opcode::ICOND(reader.read_int1(), reader.read_int1())),
+            n if n >= IF_ACM_N && n - IF_ACM_N <= 1 => Some(Opcode::IfAcmCond(
+                Cond::from_offset(n - IF_ACM_N),
+                reader.read_int1(),
+                reader.read_int1(),
+            )),
+            n if n >= IF_ICM_N && n - IF_ICM_N <= 5 => Some(Opcode::IfIcmCond(
+                Cond::from_offset(n - IF_ICM_N),
+                reader.read_int1(),
+                reader.read_int1(),
+            )),
+            n if n >= I_N && n - I_N <= 5 => Some(Opcode::ICOND(
+                Cond::from_offset(n - I_N),
+                reader.read_int1(),
+                reader.read_int1(),
+            )),
             IFNONNULL => Some(Opcode::Ifnonnull(reader.read_int1(), reader.read_int1())),
             IFNULL => Some(Opcode::Ifnull(reader.read_int1(), reader.read_int1())),
             IINC => Some(Opcode::Iinc(reader.read_int1(), reader.read_int1())),
@@ -756,14 +869,14 @@ impl Opcode {
             INVOKEDYNAMIC => Some(Opcode::Invokedynamic(
                 reader.read_int1(),
                 reader.read_int1(),
-                0,
-                0,
+                reader.read_int1(),
+                reader.read_int1(),
             )),
             INVOKEINTERFACE => Some(Opcode::Invokeinterface(
                 reader.read_int1(),
                 reader.read_int1(),
                 reader.read_int1(),
-                0,
+                reader.read_int1(),
             )),
             INVOKESPECIAL => Some(Opcode::Invokespecial(
                 reader.read_int1(),
@@ -780,7 +893,7 @@ impl Opcode {
             ISHL => Some(Opcode::Ishl),
             ISHR => Some(Opcode::Ishr),
             ISTORE => Some(Opcode::Istore(reader.read_int1())),
-            n if n >= ISTORE_N && n - ISTORE_N <= 3 => Some(Opcode::IstoreN(n - ISTORE)),
+            n if n >= ISTORE_N && n - ISTORE_N <= 3 => Some(Opcode::IstoreN(n - ISTORE_N)),
             ISUB => Some(Opcode::Isub),
             IUSHR => Some(Opcode::Iushr),
             IXOR => Some(Opcode::Ixor),
@@ -799,7 +912,7 @@ impl Opcode {
             LAND => Some(Opcode::Land),
             LASTORE => Some(Opcode::Lastore),
             LCMP => Some(Opcode::Lcmp),
-            n if n >= LCONST_N && n - LCONST_N <= 3 => Some(Opcode::LconstL(n - LCONST_N)),
+            n if n >= LCONST_N && n - LCONST_N <= 1 => Some(Opcode::LconstL(n - LCONST_N)),
             LDC => Some(Opcode::Ldc(reader.read_int1())),
             LDC_W => Some(Opcode::LdcW(reader.read_int1(), reader.read_int1())),
             LDC2_W => Some(Opcode::Ldc2W(reader.read_int1(), reader.read_int1())),
@@ -808,7 +921,15 @@ impl Opcode {
             n if n >= LLOAD_N && n - LLOAD_N <= 3 => Some(Opcode::LloadN(n - LLOAD_N)),
             LMUL => Some(Opcode::Lmul),
             LNEG => Some(Opcode::Lneg),
-            LOOKUPSWITCH => todo!(),
+            LOOKUPSWITCH => {
+                reader.read_bytes(switch_padding(pc));
+                let default = read_i32(reader);
+                let npairs = read_i32(reader);
+                let pairs = (0..npairs)
+                    .map(|_| (read_i32(reader), read_i32(reader)))
+                    .collect();
+                Some(Opcode::Lookupswitch(default, pairs))
+            }
             LOR => Some(Opcode::Lor),
             LREM => Some(Opcode::Lrem),
             LRETURN => Some(Opcode::Lreturn),
@@ -839,8 +960,146 @@ impl Opcode {
             SASTORE => Some(Opcode::Sastore),
             SIPUSH => Some(Opcode::Sipush(reader.read_int1(), reader.read_int1())),
             SWAP => Some(Opcode::Swap),
-            TABLESWITCH => todo!(),
+            TABLESWITCH => {
+                reader.read_bytes(switch_padding(pc));
+                let default = read_i32(reader);
+                let low = read_i32(reader);
+                let high = read_i32(reader);
+                let offsets = (low..=high).map(|_| read_i32(reader)).collect();
+                Some(Opcode::Tableswitch(default, low, high, offsets))
+            }
+            WIDE => match reader.read_int1() {
+                IINC => Some(Opcode::WideIinc(
+                    reader.read_int1(),
+                    reader.read_int1(),
+                    reader.read_int1(),
+                    reader.read_int1(),
+                )),
+                opcode => Some(Opcode::Wide(opcode, reader.read_int1(), reader.read_int1())),
+            },
             _ => None,
         }
     }
 }
+
+impl Opcode {
+    /// Returns the number of bytes this instruction occupies in the code array
+    /// when located at `pc`.
+    pub fn size(&self, pc: usize) -> usize {
+        match self {
+            Opcode::Lookupswitch(_, pairs) => 1 + switch_padding(pc) + 8 + 8 * pairs.len(),
+            Opcode::Tableswitch(_, _, _, offsets) => {
+                1 + switch_padding(pc) + 12 + 4 * offsets.len()
+            }
+            _ => self.to_jvm_bytecode_at(pc).len(),
+        }
+    }
+
+    /// Returns the absolute targets of this instruction if it is a branch located at `pc`.
+    pub fn branch_targets(&self, pc: u16) -> Vec<i32> {
+        let offset = match *self {
+            Opcode::Goto(b1, b2)
+            | Opcode::IfAcmCond(_, b1, b2)
+            | Opcode::IfIcmCond(_, b1, b2)
+            | Opcode::ICOND(_, b1, b2)
+            | Opcode::Ifnonnull(b1, b2)
+            | Opcode::Ifnull(b1, b2)
+            | Opcode::Jsr(b1, b2) => i16::from_be_bytes([b1, b2]) as i32,
+            Opcode::GotoW(b1, b2, b3, b4) | Opcode::JsrW(b1, b2, b3, b4) => {
+                i32::from_be_bytes([b1, b2, b3, b4])
+            }
+            Opcode::Lookupswitch(default, ref pairs) => {
+                return std::iter::once(default)
+                    .chain(pairs.iter().map(|(_, offset)| *offset))
+                    .map(|offset| pc as i32 + offset)
+                    .collect();
+            }
+            Opcode::Tableswitch(default, _, _, ref offsets) => {
+                return std::iter::once(default)
+                    .chain(offsets.iter().copied())
+                    .map(|offset| pc as i32 + offset)
+                    .collect();
+            }
+            _ => return vec![],
+        };
+        vec![pc as i32 + offset]
+    }
+
+    /// Returns `true` if execution never continues with the next instruction.
+    pub fn is_unconditional(&self) -> bool {
+        matches!(
+            self,
+            Opcode::Goto(..)
+                | Opcode::GotoW(..)
+                | Opcode::Athrow
+                | Opcode::Ret(_)
+                | Opcode::Return_
+                | Opcode::Areturn
+                | Opcode::Dreturn
+                | Opcode::Freturn
+                | Opcode::Ireturn
+                | Opcode::Lreturn
+                | Opcode::Lookupswitch(..)
+                | Opcode::Tableswitch(..)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes the instruction at `pc` of `code`.
+    fn decode_at(code: &[u8], pc: usize) -> Opcode {
+        let mut reader = Reader::new(code.to_vec());
+        reader.cursor = pc;
+        Opcode::from_reader(&mut reader).unwrap()
+    }
+
+    #[test]
+    fn switch_operands_are_aligned_on_four_bytes() {
+        let switch = Opcode::Tableswitch(20, 1, 2, vec![12, 16]);
+        for (pc, padding) in [(0, 3), (1, 2), (2, 1), (3, 0), (4, 3)] {
+            let bytes = switch.to_jvm_bytecode_at(pc);
+            assert_eq!(bytes.len(), 1 + padding + 12 + 8);
+            assert_eq!(switch.size(pc), bytes.len());
+            assert_eq!((pc + 1 + padding) % 4, 0);
+            assert!(bytes[1..1 + padding].iter().all(|b| *b == 0));
+        }
+    }
+
+    #[test]
+    fn switches_round_trip_at_any_alignment() {
+        let switches = [
+            Opcode::Tableswitch(20, -1, 1, vec![12, 16, -4]),
+            Opcode::Lookupswitch(8, vec![(-5, 12), (1000, 16)]),
+        ];
+        for switch in switches {
+            for pc in 0..4 {
+                let mut code = vec![NOP; pc];
+                code.extend(switch.to_jvm_bytecode_at(pc));
+                assert_eq!(decode_at(&code, pc), switch);
+            }
+        }
+    }
+
+    #[test]
+    fn branch_targets_are_relative_to_the_branch() {
+        assert_eq!(Opcode::Goto(0, 5).branch_targets(10), [15]);
+        assert_eq!(Opcode::Goto(0xff, 0xfb).branch_targets(10), [5]);
+        assert_eq!(
+            Opcode::ICOND(Cond::Eq, 0x80, 0x00).branch_targets(40000),
+            [40000 - 32768]
+        );
+        assert_eq!(Opcode::GotoW(0, 1, 0, 0).branch_targets(3), [65539]);
+        assert_eq!(
+            Opcode::Tableswitch(20, 1, 2, vec![12, -16]).branch_targets(100),
+            [120, 112, 84]
+        );
+        assert_eq!(
+            Opcode::Lookupswitch(8, vec![(7, 12)]).branch_targets(1),
+            [9, 13]
+        );
+        assert!(Opcode::Ireturn.branch_targets(0).is_empty());
+    }
+}
@@ -2,14 +2,19 @@ pub mod instr;
 
 use instr::Opcode;
 
-pub const PUBLIC: u16 = 0x0001;
-pub const FINAL: u16 = 0x0010;
-pub const SUPER: u16 = 0x0020;
-pub const INTERFACE: u16 = 0x0200;
-pub const ABSTRACT: u16 = 0x0400;
-pub const SYNTHETIC: u16 = 0x1000;
-pub const ANNOTATION: u16 = 0x2000;
-pub const ENUM: u16 = 0x4000;
+use crate::constant_pool::ConstantPoolError;
+use crate::flags::{ClassAccess, FieldAccess, InnerClassAccess, MethodAccess};
+use crate::mutf8;
+
+pub const PUBLIC: ClassAccess = ClassAccess::from_bits(0x0001);
+pub const FINAL: ClassAccess = ClassAccess::from_bits(0x0010);
+pub const SUPER: ClassAccess = ClassAccess::from_bits(0x0020);
+pub const INTERFACE: ClassAccess = ClassAccess::from_bits(0x0200);
+pub const ABSTRACT: ClassAccess = ClassAccess::from_bits(0x0400);
+pub const SYNTHETIC: ClassAccess = ClassAccess::from_bits(0x1000);
+pub const ANNOTATION: ClassAccess = ClassAccess::from_bits(0x2000);
+pub const ENUM: ClassAccess = ClassAccess::from_bits(0x4000);
+pub const MODULE: ClassAccess = ClassAccess::from_bits(0x8000);
 
 pub const JVM_MAGIC: u32 = 0xCAFEBABE;
 
@@ -24,7 +29,7 @@ pub struct ClassFile {
     pub major_version: u16,
     pub constant_pool_count: u16,
     pub constant_pool: Vec<CpInfo>,
-    pub access_flags: u16,
+    pub access_flags: ClassAccess,
     pub this_class: u16,
     pub super_class: u16,
     pub interfaces_count: u16,
@@ -52,7 +57,7 @@ impl ToJvmBytecode for ClassFile {
                 .flat_map(|cp| cp.to_jvm_bytecode())
                 .collect::<Vec<u8>>(),
         );
-        bytes.extend_from_slice(&self.access_flags.to_be_bytes());
+        bytes.extend_from_slice(&self.access_flags.bits().to_be_bytes());
         bytes.extend_from_slice(&self.this_class.to_be_bytes());
         bytes.extend_from_slice(&self.super_class.to_be_bytes());
         bytes.extend_from_slice(&self.interfaces_count.to_be_bytes());
@@ -93,24 +98,117 @@ impl ToJvmBytecode for ClassFile {
 
 impl ClassFile {
     pub fn write(&self, path: &str) -> std::io::Result<()> {
-        std::fs::write(path, self.to_jvm_bytecode())?;
+        let bytes = self
+            .to_bytes()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, bytes)?;
         Ok(())
     }
+
+    /// Serializes the class file like `to_jvm_bytecode`, but fails instead of
+    /// panicking if a `Utf8` constant is longer than the 65535 bytes its
+    /// length can count.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ConstantPoolError> {
+        for cp in &self.constant_pool {
+            if let CpInfoType::Utf8 { bytes, .. } = &cp.info {
+                let length = mutf8::encoded_len(bytes);
+                if length > u16::MAX as usize {
+                    return Err(ConstantPoolError::StringTooLong(length));
+                }
+            }
+        }
+        Ok(self.to_jvm_bytecode())
+    }
+
+    /// Returns the constant pool entry at `index`, taking the two slots used by
+    /// `Long` and `Double` entries into account.
+    pub fn get_constant(&self, index: u16) -> Option<&CpInfo> {
+        let mut slot = 1;
+        for cp in &self.constant_pool {
+            if slot == index {
+                return Some(cp);
+            }
+            slot += match cp.info {
+                CpInfoType::Long { .. } | CpInfoType::Double { .. } => 2,
+                _ => 1,
+            };
+        }
+        None
+    }
+
+    pub fn get_utf8(&self, index: u16) -> Option<&str> {
+        match &self.get_constant(index)?.info {
+            CpInfoType::Utf8 { bytes, .. } => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn get_class_name(&self, index: u16) -> Option<&str> {
+        match self.get_constant(index)?.info {
+            CpInfoType::Class { name_index } => self.get_utf8(name_index),
+            _ => None,
+        }
+    }
+
+    /// Returns the `Signature` attribute among `attributes`, which belong to
+    /// this class or one of its fields or methods. It is parsed with
+    /// `ClassSignature::parse`, `TypeSignature::parse` or
+    /// `MethodSignature::parse` respectively.
+    pub fn get_signature(&self, attributes: &[AttributeInfo]) -> Option<&str> {
+        attributes.iter().find_map(|a| match a.info {
+            AttributeInfoKind::Signature { signature_index } => self.get_utf8(signature_index),
+            _ => None,
+        })
+    }
+
+    /// Returns the name and descriptor referenced by a `NameAndType` entry.
+    pub fn get_name_and_type(&self, index: u16) -> Option<(&str, &str)> {
+        match self.get_constant(index)?.info {
+            CpInfoType::NameAndType {
+                name_index,
+                descriptor_index,
+            } => Some((self.get_utf8(name_index)?, self.get_utf8(descriptor_index)?)),
+            _ => None,
+        }
+    }
+
+    /// Returns the class name, member name and descriptor of a `Fieldref`,
+    /// `Methodref` or `InterfaceMethodref` entry.
+    pub fn get_member_ref(&self, index: u16) -> Option<(&str, &str, &str)> {
+        match self.get_constant(index)?.info {
+            CpInfoType::Fieldref {
+                class_index,
+                name_and_type_index,
+            }
+            | CpInfoType::Methodref {
+                class_index,
+                name_and_type_index,
+            }
+            | CpInfoType::InterfaceMethodref {
+                class_index,
+                name_and_type_index,
+            } => {
+                let (name, descriptor) = self.get_name_and_type(name_and_type_index)?;
+                Some((self.get_class_name(class_index)?, name, descriptor))
+            }
+            _ => None,
+        }
+    }
 }
 
-pub const FIELD_ACC_PUBLIC: u16 = 0x0001;
-pub const FIELD_ACC_PRIVATE: u16 = 0x0002;
-pub const FIELD_ACC_PROTECTED: u16 = 0x0004;
-pub const FIELD_ACC_STATIC: u16 = 0x0008;
-pub const FIELD_ACC_FINAL: u16 = 0x0010;
-pub const FIELD_ACC_VOLATILE: u16 = 0x0040;
-pub const FIELD_ACC_TRANSIENT: u16 = 0x0080;
-pub const FIELD_ACC_SYNTHETIC: u16 = 0x1000;
-pub const FIELD_ACC_ENUM: u16 = 0x4000;
+pub const FIELD_ACC_PUBLIC: FieldAccess = FieldAccess::from_bits(0x0001);
+pub const FIELD_ACC_PRIVATE: FieldAccess = FieldAccess::from_bits(0x0002);
+pub const FIELD_ACC_PROTECTED: FieldAccess = FieldAccess::from_bits(0x0004);
+pub const FIELD_ACC_STATIC: FieldAccess = FieldAccess::from_bits(0x0008);
+pub const FIELD_ACC_FINAL: FieldAccess = FieldAccess::from_bits(0x0010);
+pub const FIELD_ACC_VOLATILE: FieldAccess = FieldAccess::from_bits(0x0040);
+pub const FIELD_ACC_TRANSIENT: FieldAccess = FieldAccess::from_bits(0x0080);
+pub const FIELD_ACC_SYNTHETIC: FieldAccess = FieldAccess::from_bits(0x1000);
+pub const FIELD_ACC_ENUM: FieldAccess = FieldAccess::from_bits(0x4000);
 
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct FieldInfo {
-    pub access_flags: u16,
+    pub access_flags: FieldAccess,
     pub name_index: u16,
     pub descriptor_index: u16,
     pub attributes_count: u16,
@@ -120,7 +218,7 @@ pub struct FieldInfo {
 impl ToJvmBytecode for FieldInfo {
     fn to_jvm_bytecode(&self) -> Vec<u8> {
         let mut bytes = vec![];
-        bytes.extend_from_slice(&self.access_flags.to_be_bytes());
+        bytes.extend_from_slice(&self.access_flags.bits().to_be_bytes());
         bytes.extend_from_slice(&self.name_index.to_be_bytes());
         bytes.extend_from_slice(&self.descriptor_index.to_be_bytes());
         bytes.extend_from_slice(&self.attributes_count.to_be_bytes());
@@ -234,6 +332,14 @@ pub enum AttributeInfoKind {
         num_bootstrap_methods: u16,
         bootstrap_methods: Vec<BootstrapMethod>,
     },
+    Record {
+        components_count: u16,
+        components: Vec<RecordComponent>,
+    },
+    /// An attribute this crate doesn't model, kept as its raw bytes.
+    Custom {
+        info: Vec<u8>,
+    },
 }
 
 impl ToJvmBytecode for AttributeInfoKind {
@@ -255,10 +361,11 @@ impl ToJvmBytecode for AttributeInfoKind {
                 attributes,
                 ..
             } => {
-                let c = code
-                    .iter()
-                    .flat_map(|c| c.to_jvm_bytecode())
-                    .collect::<Vec<u8>>();
+                let mut c = vec![];
+                for op in code {
+                    let bytecode = op.to_jvm_bytecode_at(c.len());
+                    c.extend(bytecode);
+                }
                 bytes.extend_from_slice(&max_stack.to_be_bytes());
                 bytes.extend_from_slice(&max_locals.to_be_bytes());
                 bytes.extend_from_slice(&(c.len() as u32).to_be_bytes());
@@ -437,11 +544,51 @@ impl ToJvmBytecode for AttributeInfoKind {
                         .collect::<Vec<u8>>(),
                 );
             }
+            AttributeInfoKind::Record {
+                components_count,
+                components,
+            } => {
+                bytes.extend_from_slice(&components_count.to_be_bytes());
+                bytes.extend_from_slice(
+                    &components
+                        .iter()
+                        .flat_map(|c| c.to_jvm_bytecode())
+                        .collect::<Vec<u8>>(),
+                );
+            }
+            AttributeInfoKind::Custom { info } => {
+                bytes.extend_from_slice(info);
+            }
         }
         bytes
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordComponent {
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attributes_count: u16,
+    pub attributes: Vec<AttributeInfo>,
+}
+
+impl ToJvmBytecode for RecordComponent {
+    fn to_jvm_bytecode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&self.name_index.to_be_bytes());
+        bytes.extend_from_slice(&self.descriptor_index.to_be_bytes());
+        bytes.extend_from_slice(&self.attributes_count.to_be_bytes());
+        bytes.extend_from_slice(
+            &self
+                .attributes
+                .iter()
+                .flat_map(|a| a.to_jvm_bytecode())
+                .collect::<Vec<u8>>(),
+        );
+        bytes
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct BootstrapMethod {
     pub bootstrap_method_ref: u16,
@@ -643,7 +790,7 @@ pub struct InnerClass {
     pub inner_class_info_index: u16,
     pub outer_class_info_index: u16,
     pub inner_name_index: u16,
-    pub inner_class_access_flags: u16,
+    pub inner_class_access_flags: InnerClassAccess,
 }
 
 impl ToJvmBytecode for InnerClass {
@@ -652,7 +799,7 @@ impl ToJvmBytecode for InnerClass {
         bytes.extend_from_slice(&self.inner_class_info_index.to_be_bytes());
         bytes.extend_from_slice(&self.outer_class_info_index.to_be_bytes());
         bytes.extend_from_slice(&self.inner_name_index.to_be_bytes());
-        bytes.extend_from_slice(&self.inner_class_access_flags.to_be_bytes());
+        bytes.extend_from_slice(&self.inner_class_access_flags.bits().to_be_bytes());
         bytes
     }
 }
@@ -746,12 +893,14 @@ impl ToJvmBytecode for StackMapFrame {
             } => {
                 bytes.push(*frame_type);
                 bytes.extend_from_slice(&offset_delta.to_be_bytes());
+                bytes.extend_from_slice(&(locals.len() as u16).to_be_bytes());
                 bytes.extend_from_slice(
                     &locals
                         .iter()
                         .flat_map(|l| l.to_jvm_bytecode())
                         .collect::<Vec<u8>>(),
                 );
+                bytes.extend_from_slice(&(stack.len() as u16).to_be_bytes());
                 bytes.extend_from_slice(
                     &stack
                         .iter()
@@ -820,22 +969,22 @@ impl ToJvmBytecode for ExceptionTable {
     }
 }
 
-pub const METHOD_ACC_PUBLIC: u16 = 0x0001;
-pub const METHOD_ACC_PRIVATE: u16 = 0x0002;
-pub const METHOD_ACC_PROTECTED: u16 = 0x0004;
-pub const METHOD_ACC_STATIC: u16 = 0x0008;
-pub const METHOD_ACC_FINAL: u16 = 0x0010;
-pub const METHOD_ACC_SYNCHRONIZED: u16 = 0x0020;
-pub const METHOD_ACC_BRIDGE: u16 = 0x0040;
-pub const METHOD_ACC_VARARGS: u16 = 0x0080;
-pub const METHOD_ACC_NATIVE: u16 = 0x0100;
-pub const METHOD_ACC_ABSTRACT: u16 = 0x0400;
-pub const METHOD_ACC_STRICT: u16 = 0x0800;
-pub const METHOD_ACC_SYNTHETIC: u16 = 0x1000;
+pub const METHOD_ACC_PUBLIC: MethodAccess = MethodAccess::from_bits(0x0001);
+pub const METHOD_ACC_PRIVATE: MethodAccess = MethodAccess::from_bits(0x0002);
+pub const METHOD_ACC_PROTECTED: MethodAccess = MethodAccess::from_bits(0x0004);
+pub const METHOD_ACC_STATIC: MethodAccess = MethodAccess::from_bits(0x0008);
+pub const METHOD_ACC_FINAL: MethodAccess = MethodAccess::from_bits(0x0010);
+pub const METHOD_ACC_SYNCHRONIZED: MethodAccess = MethodAccess::from_bits(0x0020);
+pub const METHOD_ACC_BRIDGE: MethodAccess = MethodAccess::from_bits(0x0040);
+pub const METHOD_ACC_VARARGS: MethodAccess = MethodAccess::from_bits(0x0080);
+pub const METHOD_ACC_NATIVE: MethodAccess = MethodAccess::from_bits(0x0100);
+pub const METHOD_ACC_ABSTRACT: MethodAccess = MethodAccess::from_bits(0x0400);
+pub const METHOD_ACC_STRICT: MethodAccess = MethodAccess::from_bits(0x0800);
+pub const METHOD_ACC_SYNTHETIC: MethodAccess = MethodAccess::from_bits(0x1000);
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct MethodInfo {
-    pub access_flags: u16,
+    pub access_flags: MethodAccess,
     pub name_index: u16,
     pub descriptor_index: u16,
     pub attributes_count: u16,
@@ -845,7 +994,7 @@ pub struct MethodInfo {
 impl ToJvmBytecode for MethodInfo {
     fn to_jvm_bytecode(&self) -> Vec<u8> {
         let mut bytes = vec![];
-        bytes.extend_from_slice(&self.access_flags.to_be_bytes());
+        bytes.extend_from_slice(&self.access_flags.bits().to_be_bytes());
         bytes.extend_from_slice(&self.name_index.to_be_bytes());
         bytes.extend_from_slice(&self.descriptor_index.to_be_bytes());
         bytes.extend_from_slice(&self.attributes_count.to_be_bytes());
@@ -873,7 +1022,10 @@ pub const CP_TAG_INTERFACEMETHODREF: u8 = 11;
 pub const CP_TAG_NAMEANDTYPE: u8 = 12;
 pub const CP_TAG_METHODHANDLE: u8 = 15;
 pub const CP_TAG_METHODTYPE: u8 = 16;
+pub const CP_TAG_DYNAMIC: u8 = 17;
 pub const CP_TAG_INVOKEDYNAMIC: u8 = 18;
+pub const CP_TAG_MODULE: u8 = 19;
+pub const CP_TAG_PACKAGE: u8 = 20;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct CpInfo {
@@ -934,9 +1086,14 @@ impl ToJvmBytecode for CpInfo {
                 bytes.extend_from_slice(&name_index.to_be_bytes());
                 bytes.extend_from_slice(&descriptor_index.to_be_bytes());
             }
-            CpInfoType::Utf8 { length, bytes: str } => {
+            // The stored length may be stale or counted in UTF-8 bytes, so it
+            // is recomputed from the encoding.
+            CpInfoType::Utf8 { bytes: str, .. } => {
+                let encoded = mutf8::encode(str);
+                let length = u16::try_from(encoded.len())
+                    .expect("Utf8 constant longer than 65535 bytes, see ClassFile::to_bytes");
                 bytes.extend_from_slice(&length.to_be_bytes());
-                bytes.extend_from_slice(str.as_bytes());
+                bytes.extend_from_slice(&encoded);
             }
             CpInfoType::MethodHandle {
                 reference_kind,
@@ -948,18 +1105,42 @@ impl ToJvmBytecode for CpInfo {
             CpInfoType::MethodType { descriptor_index } => {
                 bytes.extend_from_slice(&descriptor_index.to_be_bytes());
             }
-            CpInfoType::InvokeDynamic {
+            CpInfoType::Dynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            }
+            | CpInfoType::InvokeDynamic {
                 bootstrap_method_attr_index,
                 name_and_type_index,
             } => {
                 bytes.extend_from_slice(&bootstrap_method_attr_index.to_be_bytes());
                 bytes.extend_from_slice(&name_and_type_index.to_be_bytes());
             }
+            CpInfoType::Module { name_index } | CpInfoType::Package { name_index } => {
+                bytes.extend_from_slice(&name_index.to_be_bytes());
+            }
         }
 
         bytes
     }
 }
+/// The kind of a `MethodHandle` constant, which tells how its reference is
+/// accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum ReferenceKind {
+    GetField = 1,
+    GetStatic = 2,
+    PutField = 3,
+    PutStatic = 4,
+    InvokeVirtual = 5,
+    InvokeStatic = 6,
+    InvokeSpecial = 7,
+    /// Calls a constructor on a new object, as `new`, `dup` and `invokespecial`.
+    NewInvokeSpecial = 8,
+    InvokeInterface = 9,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum CpInfoType {
     Class {
@@ -1009,8 +1190,19 @@ pub enum CpInfoType {
     MethodType {
         descriptor_index: u16,
     },
+    /// A dynamically computed constant, loaded with `ldc`.
+    Dynamic {
+        bootstrap_method_attr_index: u16,
+        name_and_type_index: u16,
+    },
     InvokeDynamic {
         bootstrap_method_attr_index: u16,
         name_and_type_index: u16,
     },
+    Module {
+        name_index: u16,
+    },
+    Package {
+        name_index: u16,
+    },
 }
@@ -0,0 +1,410 @@
+use std::{collections::HashMap, fmt, ops::Range};
+
+use crate::bytecode::{instr::Opcode, AttributeInfoKind, ExceptionTable, MethodInfo};
+
+/// The index of a block in `ControlFlowGraph::blocks`.
+pub type BlockId = usize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CfgError {
+    MissingCode,
+    EmptyCode,
+    InvalidBranchTarget {
+        pc: u16,
+        target: i32,
+    },
+    /// An exception table entry whose range doesn't start and end on
+    /// instruction boundaries.
+    InvalidExceptionRange {
+        start_pc: u16,
+        end_pc: u16,
+    },
+    FallsOffEnd {
+        pc: u16,
+    },
+    /// `jsr` and `ret`, whose successors depend on the subroutine caller.
+    Unsupported {
+        pc: u16,
+        opcode: Opcode,
+    },
+}
+
+impl fmt::Display for CfgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CfgError::MissingCode => write!(f, "method has no Code attribute"),
+            CfgError::EmptyCode => write!(f, "method code is empty"),
+            CfgError::InvalidBranchTarget { pc, target } => {
+                write!(f, "invalid branch target {} at pc {}", target, pc)
+            }
+            CfgError::InvalidExceptionRange { start_pc, end_pc } => {
+                write!(f, "invalid exception range {}..{}", start_pc, end_pc)
+            }
+            CfgError::FallsOffEnd { pc } => {
+                write!(f, "execution falls off the end of the code at pc {}", pc)
+            }
+            CfgError::Unsupported { pc, opcode } => {
+                write!(f, "unsupported instruction {:?} at pc {}", opcode, pc)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CfgError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /// Execution continues with the next instruction.
+    FallThrough,
+    /// A branch, `goto` or switch jumps to the block.
+    Jump,
+    /// An exception thrown in the block is caught by the handler starting the
+    /// target block. `catch_type` is the index of the caught class in the
+    /// constant pool, or 0 for a handler catching every exception.
+    Exception { catch_type: u16 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Edge {
+    pub from: BlockId,
+    pub to: BlockId,
+    pub kind: EdgeKind,
+}
+
+/// A run of instructions that is only entered at its first instruction and
+/// only left after its last one, or by an exception.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub start_pc: u16,
+    /// The pc following the last instruction of the block.
+    pub end_pc: u16,
+    /// The indices of the instructions of the block in the code.
+    pub instructions: Range<usize>,
+    pub successors: Vec<Edge>,
+    pub predecessors: Vec<Edge>,
+}
+
+/// The control flow graph of a method body. The first block is the entry of
+/// the method.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+    /// The pc of each instruction.
+    pub pcs: Vec<u16>,
+    exits: Vec<BlockId>,
+}
+
+impl ControlFlowGraph {
+    /// Builds the graph of the `Code` attribute of `method`.
+    pub fn from_method(method: &MethodInfo) -> Result<ControlFlowGraph, CfgError> {
+        method
+            .attributes
+            .iter()
+            .find_map(|a| match &a.info {
+                AttributeInfoKind::Code {
+                    code,
+                    exception_table,
+                    ..
+                } => Some(ControlFlowGraph::new(code, exception_table)),
+                _ => None,
+            })
+            .unwrap_or(Err(CfgError::MissingCode))
+    }
+
+    /// Splits `code` into basic blocks at branch targets, after branches,
+    /// switches, returns and `athrow`, and at the bounds of exception
+    /// ranges and their handlers.
+    pub fn new(
+        code: &[Opcode],
+        exception_table: &[ExceptionTable],
+    ) -> Result<ControlFlowGraph, CfgError> {
+        if code.is_empty() {
+            return Err(CfgError::EmptyCode);
+        }
+        let mut pcs = Vec::with_capacity(code.len());
+        let mut pc = 0usize;
+        for op in code {
+            pcs.push(pc as u16);
+            pc += op.size(pc);
+        }
+        let code_length = pc as u16;
+        let index_of: HashMap<u16, usize> =
+            pcs.iter().enumerate().map(|(i, pc)| (*pc, i)).collect();
+        let instruction = |pc: u16, target: i32| {
+            u16::try_from(target)
+                .ok()
+                .and_then(|t| index_of.get(&t).copied())
+                .ok_or(CfgError::InvalidBranchTarget { pc, target })
+        };
+
+        let mut leaders = vec![false; code.len()];
+        leaders[0] = true;
+        for (index, op) in code.iter().enumerate() {
+            let pc = pcs[index];
+            if matches!(op, Opcode::Jsr(..) | Opcode::JsrW(..) | Opcode::Ret(_)) {
+                return Err(CfgError::Unsupported {
+                    pc,
+                    opcode: op.clone(),
+                });
+            }
+            let targets = op.branch_targets(pc);
+            for target in &targets {
+                leaders[instruction(pc, *target)?] = true;
+            }
+            if !targets.is_empty() || op.is_unconditional() {
+                if let Some(next) = leaders.get_mut(index + 1) {
+                    *next = true;
+                }
+            }
+        }
+        for e in exception_table {
+            let invalid = CfgError::InvalidExceptionRange {
+                start_pc: e.start_pc,
+                end_pc: e.end_pc,
+            };
+            if e.start_pc >= e.end_pc {
+                return Err(invalid);
+            }
+            leaders[*index_of.get(&e.start_pc).ok_or(invalid.clone())?] = true;
+            if e.end_pc != code_length {
+                leaders[*index_of.get(&e.end_pc).ok_or(invalid)?] = true;
+            }
+            leaders[instruction(e.start_pc, e.handler_pc as i32)?] = true;
+        }
+
+        let mut blocks = vec![];
+        let mut block_of = vec![0; code.len()];
+        for index in 0..code.len() {
+            if leaders[index] {
+                blocks.push(BasicBlock {
+                    start_pc: pcs[index],
+                    end_pc: 0,
+                    instructions: index..index,
+                    successors: vec![],
+                    predecessors: vec![],
+                });
+            }
+            let block = blocks.last_mut().unwrap();
+            block.instructions.end = index + 1;
+            block.end_pc = pcs.get(index + 1).copied().unwrap_or(code_length);
+            block_of[index] = blocks.len() - 1;
+        }
+
+        let mut edges = vec![];
+        let mut exits = vec![];
+        for (id, block) in blocks.iter().enumerate() {
+            let last = block.instructions.end - 1;
+            let op = &code[last];
+            let pc = pcs[last];
+            let mut successors: Vec<Edge> = vec![];
+            let mut add = |to: BlockId, kind: EdgeKind| {
+                let edge = Edge { from: id, to, kind };
+                if !successors.contains(&edge) {
+                    successors.push(edge);
+                }
+            };
+            for target in op.branch_targets(pc) {
+                add(block_of[instruction(pc, target)?], EdgeKind::Jump);
+            }
+            if !op.is_unconditional() {
+                if last + 1 >= code.len() {
+                    return Err(CfgError::FallsOffEnd { pc });
+                }
+                add(id + 1, EdgeKind::FallThrough);
+            }
+            for e in exception_table {
+                if e.start_pc <= block.start_pc && block.start_pc < e.end_pc {
+                    let handler = instruction(e.start_pc, e.handler_pc as i32)?;
+                    add(
+                        block_of[handler],
+                        EdgeKind::Exception {
+                            catch_type: e.catch_type,
+                        },
+                    );
+                }
+            }
+            if matches!(
+                op,
+                Opcode::Athrow
+                    | Opcode::Return_
+                    | Opcode::Areturn
+                    | Opcode::Dreturn
+                    | Opcode::Freturn
+                    | Opcode::Ireturn
+                    | Opcode::Lreturn
+            ) {
+                exits.push(id);
+            }
+            edges.push(successors);
+        }
+        for (id, successors) in edges.into_iter().enumerate() {
+            for edge in &successors {
+                blocks[edge.to].predecessors.push(*edge);
+            }
+            blocks[id].successors = successors;
+        }
+
+        Ok(ControlFlowGraph { blocks, pcs, exits })
+    }
+
+    pub fn entry(&self) -> BlockId {
+        0
+    }
+
+    /// Returns the blocks ending with a return or `athrow` instruction.
+    pub fn exits(&self) -> &[BlockId] {
+        &self.exits
+    }
+
+    pub fn block(&self, id: BlockId) -> &BasicBlock {
+        &self.blocks[id]
+    }
+
+    /// Returns the block holding the instruction at `pc`.
+    pub fn block_at(&self, pc: u16) -> Option<BlockId> {
+        let index = self.pcs.binary_search(&pc).ok()?;
+        Some(self.blocks.partition_point(|b| b.instructions.end <= index))
+    }
+
+    pub fn successors(&self, id: BlockId) -> &[Edge] {
+        &self.blocks[id].successors
+    }
+
+    pub fn predecessors(&self, id: BlockId) -> &[Edge] {
+        &self.blocks[id].predecessors
+    }
+
+    /// Returns the blocks reachable from the entry, through normal and
+    /// exception edges, in reverse postorder: every block comes before its
+    /// successors, except along loop back edges.
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut postorder = vec![];
+        let mut stack = vec![(self.entry(), 0)];
+        visited[self.entry()] = true;
+        while let Some((id, next)) = stack.last_mut() {
+            match self.blocks[*id].successors.get(*next) {
+                Some(edge) => {
+                    *next += 1;
+                    if !visited[edge.to] {
+                        visited[edge.to] = true;
+                        stack.push((edge.to, 0));
+                    }
+                }
+                None => {
+                    postorder.push(*id);
+                    stack.pop();
+                }
+            }
+        }
+        postorder.reverse();
+        postorder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::instr::Cond;
+
+    /// `if (x == 0) { x = 1; } return x;` with `x` in local 0.
+    fn if_then() -> Vec<Opcode> {
+        vec![
+            Opcode::IloadN(0),             // 0
+            Opcode::ICOND(Cond::Ne, 0, 5), // 1: to 6
+            Opcode::IconstI(1),            // 4
+            Opcode::IstoreN(0),            // 5
+            Opcode::IloadN(0),             // 6
+            Opcode::Ireturn,               // 7
+        ]
+    }
+
+    #[test]
+    fn splits_blocks_at_branches_and_targets() {
+        let cfg = ControlFlowGraph::new(&if_then(), &[]).unwrap();
+        let bounds: Vec<(u16, u16)> = cfg.blocks.iter().map(|b| (b.start_pc, b.end_pc)).collect();
+        assert_eq!(bounds, [(0, 4), (4, 6), (6, 8)]);
+        let successors = |id| {
+            cfg.successors(id)
+                .iter()
+                .map(|e| (e.to, e.kind))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            successors(0),
+            [(2, EdgeKind::Jump), (1, EdgeKind::FallThrough)]
+        );
+        assert_eq!(successors(1), [(2, EdgeKind::FallThrough)]);
+        assert_eq!(cfg.predecessors(2).len(), 2);
+        assert_eq!(cfg.exits(), [2]);
+        assert_eq!(cfg.block_at(5), Some(1));
+        assert_eq!(cfg.block_at(2), None);
+    }
+
+    #[test]
+    fn switch_targets_account_for_padding() {
+        let code = |switch: Opcode| {
+            vec![
+                Opcode::IloadN(0),  // 0
+                switch,             // 1: operands start at 4
+                Opcode::IconstI(0), // 24
+                Opcode::Ireturn,    // 25
+                Opcode::Ireturn,    // 26
+                Opcode::Ireturn,    // 27
+            ]
+        };
+        // Offsets computed as if the switch had no padding.
+        let unpadded = Opcode::Tableswitch(23, 0, 1, vec![21, 22]);
+        assert_eq!(
+            ControlFlowGraph::new(&code(unpadded), &[]),
+            Err(CfgError::InvalidBranchTarget { pc: 1, target: 22 })
+        );
+        let switch = Opcode::Tableswitch(26, 0, 1, vec![24, 25]);
+        let cfg = ControlFlowGraph::new(&code(switch), &[]).unwrap();
+        let starts: Vec<u16> = cfg.blocks.iter().map(|b| b.start_pc).collect();
+        assert_eq!(starts, [0, 24, 25, 26, 27]);
+        assert_eq!(cfg.successors(0).len(), 3);
+    }
+
+    #[test]
+    fn handlers_start_blocks_with_exception_edges() {
+        let code = vec![
+            Opcode::AloadN(0),  // 0
+            Opcode::Athrow,     // 1
+            Opcode::AstoreN(1), // 2
+            Opcode::Return_,    // 3
+        ];
+        let exception_table = [ExceptionTable {
+            start_pc: 0,
+            end_pc: 2,
+            handler_pc: 2,
+            catch_type: 7,
+        }];
+        let cfg = ControlFlowGraph::new(&code, &exception_table).unwrap();
+        assert_eq!(cfg.blocks.len(), 2);
+        assert_eq!(
+            cfg.successors(0),
+            [Edge {
+                from: 0,
+                to: 1,
+                kind: EdgeKind::Exception { catch_type: 7 }
+            }]
+        );
+        assert_eq!(cfg.exits(), [0, 1]);
+    }
+
+    #[test]
+    fn orders_blocks_in_reverse_postorder() {
+        let cfg = ControlFlowGraph::new(&if_then(), &[]).unwrap();
+        assert_eq!(cfg.reverse_postorder(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn rejects_code_falling_off_the_end() {
+        let code = vec![Opcode::IconstI(0), Opcode::Pop];
+        assert_eq!(
+            ControlFlowGraph::new(&code, &[]),
+            Err(CfgError::FallsOffEnd { pc: 1 })
+        );
+    }
+}
@@ -0,0 +1,494 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    bytecode::{
+        CpInfo, CpInfoType, ReferenceKind, CP_TAG_CLASS, CP_TAG_DOUBLE, CP_TAG_DYNAMIC,
+        CP_TAG_FIELDREF, CP_TAG_FLOAT, CP_TAG_INTEGER, CP_TAG_INTERFACEMETHODREF,
+        CP_TAG_INVOKEDYNAMIC, CP_TAG_LONG, CP_TAG_METHODHANDLE, CP_TAG_METHODREF,
+        CP_TAG_METHODTYPE, CP_TAG_MODULE, CP_TAG_NAMEANDTYPE, CP_TAG_PACKAGE, CP_TAG_STRING,
+        CP_TAG_UTF8,
+    },
+    mutf8,
+};
+
+/// The largest index a constant pool entry may have, as `constant_pool_count`
+/// is a `u16` holding the number of entries plus one.
+pub const MAX_CONSTANT_POOL_INDEX: u16 = u16::MAX - 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstantPoolError {
+    /// The constant pool has no room for another entry.
+    Overflow,
+    /// A string is longer than the 65535 bytes a `Utf8` entry can hold.
+    StringTooLong(usize),
+}
+
+impl fmt::Display for ConstantPoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstantPoolError::Overflow => write!(
+                f,
+                "constant pool exceeds {} entries",
+                MAX_CONSTANT_POOL_INDEX
+            ),
+            ConstantPoolError::StringTooLong(length) => {
+                write!(
+                    f,
+                    "string of {} bytes is too long for the constant pool",
+                    length
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConstantPoolError {}
+
+/// The value identity of an entry, used to find existing entries.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Utf8(String),
+    Integer(u32),
+    Float(u32),
+    Long(u32, u32),
+    Double(u32, u32),
+    Class(u16),
+    String(u16),
+    Fieldref(u16, u16),
+    Methodref(u16, u16),
+    InterfaceMethodref(u16, u16),
+    NameAndType(u16, u16),
+    MethodHandle(u8, u16),
+    MethodType(u16),
+    Dynamic(u16, u16),
+    InvokeDynamic(u16, u16),
+    Module(u16),
+    Package(u16),
+}
+
+impl Key {
+    fn new(info: &CpInfoType) -> Key {
+        match info {
+            CpInfoType::Utf8 { bytes, .. } => Key::Utf8(bytes.clone()),
+            CpInfoType::Integer { bytes } => Key::Integer(*bytes),
+            CpInfoType::Float { bytes } => Key::Float(bytes.to_bits()),
+            CpInfoType::Long {
+                high_bytes,
+                low_bytes,
+            } => Key::Long(*high_bytes, *low_bytes),
+            CpInfoType::Double {
+                high_bytes,
+                low_bytes,
+            } => Key::Double(*high_bytes, *low_bytes),
+            CpInfoType::Class { name_index } => Key::Class(*name_index),
+            CpInfoType::String { string_index } => Key::String(*string_index),
+            CpInfoType::Fieldref {
+                class_index,
+                name_and_type_index,
+            } => Key::Fieldref(*class_index, *name_and_type_index),
+            CpInfoType::Methodref {
+                class_index,
+                name_and_type_index,
+            } => Key::Methodref(*class_index, *name_and_type_index),
+            CpInfoType::InterfaceMethodref {
+                class_index,
+                name_and_type_index,
+            } => Key::InterfaceMethodref(*class_index, *name_and_type_index),
+            CpInfoType::NameAndType {
+                name_index,
+                descriptor_index,
+            } => Key::NameAndType(*name_index, *descriptor_index),
+            CpInfoType::MethodHandle {
+                reference_kind,
+                reference_index,
+            } => Key::MethodHandle(*reference_kind, *reference_index),
+            CpInfoType::MethodType { descriptor_index } => Key::MethodType(*descriptor_index),
+            CpInfoType::Dynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => Key::Dynamic(*bootstrap_method_attr_index, *name_and_type_index),
+            CpInfoType::InvokeDynamic {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            } => Key::InvokeDynamic(*bootstrap_method_attr_index, *name_and_type_index),
+            CpInfoType::Module { name_index } => Key::Module(*name_index),
+            CpInfoType::Package { name_index } => Key::Package(*name_index),
+        }
+    }
+}
+
+fn tag(info: &CpInfoType) -> u8 {
+    match info {
+        CpInfoType::Utf8 { .. } => CP_TAG_UTF8,
+        CpInfoType::Integer { .. } => CP_TAG_INTEGER,
+        CpInfoType::Float { .. } => CP_TAG_FLOAT,
+        CpInfoType::Long { .. } => CP_TAG_LONG,
+        CpInfoType::Double { .. } => CP_TAG_DOUBLE,
+        CpInfoType::Class { .. } => CP_TAG_CLASS,
+        CpInfoType::String { .. } => CP_TAG_STRING,
+        CpInfoType::Fieldref { .. } => CP_TAG_FIELDREF,
+        CpInfoType::Methodref { .. } => CP_TAG_METHODREF,
+        CpInfoType::InterfaceMethodref { .. } => CP_TAG_INTERFACEMETHODREF,
+        CpInfoType::NameAndType { .. } => CP_TAG_NAMEANDTYPE,
+        CpInfoType::MethodHandle { .. } => CP_TAG_METHODHANDLE,
+        CpInfoType::MethodType { .. } => CP_TAG_METHODTYPE,
+        CpInfoType::Dynamic { .. } => CP_TAG_DYNAMIC,
+        CpInfoType::InvokeDynamic { .. } => CP_TAG_INVOKEDYNAMIC,
+        CpInfoType::Module { .. } => CP_TAG_MODULE,
+        CpInfoType::Package { .. } => CP_TAG_PACKAGE,
+    }
+}
+
+/// A constant pool that interns its entries: adding a value that is already
+/// present returns the index of the existing entry.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConstantPool {
+    entries: Vec<CpInfo>,
+    /// The position in `entries` of each index, `None` for index 0 and for the
+    /// slot following a `Long` or `Double`.
+    positions: Vec<Option<usize>>,
+    indices: HashMap<Key, u16>,
+}
+
+impl ConstantPool {
+    pub fn new() -> Self {
+        ConstantPool {
+            positions: vec![None],
+            ..Default::default()
+        }
+    }
+
+    /// Creates a pool holding `entries`, such as the constant pool of a parsed
+    /// class, so that they are reused by later additions.
+    pub fn from_entries(entries: Vec<CpInfo>) -> Result<Self, ConstantPoolError> {
+        let mut pool = ConstantPool::new();
+        for entry in entries {
+            let key = Key::new(&entry.info);
+            let index = pool.push(entry)?;
+            pool.indices.entry(key).or_insert(index);
+        }
+        Ok(pool)
+    }
+
+    /// Returns the number of slots used, which is the `constant_pool_count`
+    /// of the class file minus one.
+    pub fn count(&self) -> u16 {
+        (self.positions.len() - 1) as u16
+    }
+
+    pub fn entries(&self) -> &[CpInfo] {
+        &self.entries
+    }
+
+    pub fn into_entries(self) -> Vec<CpInfo> {
+        self.entries
+    }
+
+    pub fn get(&self, index: u16) -> Option<&CpInfo> {
+        let position = (*self.positions.get(index as usize)?)?;
+        Some(&self.entries[position])
+    }
+
+    pub fn get_utf8(&self, index: u16) -> Option<&str> {
+        match &self.get(index)?.info {
+            CpInfoType::Utf8 { bytes, .. } => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn get_class_name(&self, index: u16) -> Option<&str> {
+        match self.get(index)?.info {
+            CpInfoType::Class { name_index } => self.get_utf8(name_index),
+            _ => None,
+        }
+    }
+
+    /// Returns the name and descriptor referenced by a `NameAndType` entry.
+    pub fn get_name_and_type(&self, index: u16) -> Option<(&str, &str)> {
+        match self.get(index)?.info {
+            CpInfoType::NameAndType {
+                name_index,
+                descriptor_index,
+            } => Some((self.get_utf8(name_index)?, self.get_utf8(descriptor_index)?)),
+            _ => None,
+        }
+    }
+
+    /// Returns the class name, member name and descriptor of a `Fieldref`,
+    /// `Methodref` or `InterfaceMethodref` entry.
+    pub fn get_member_ref(&self, index: u16) -> Option<(&str, &str, &str)> {
+        match self.get(index)?.info {
+            CpInfoType::Fieldref {
+                class_index,
+                name_and_type_index,
+            }
+            | CpInfoType::Methodref {
+                class_index,
+                name_and_type_index,
+            }
+            | CpInfoType::InterfaceMethodref {
+                class_index,
+                name_and_type_index,
+            } => {
+                let (name, descriptor) = self.get_name_and_type(name_and_type_index)?;
+                Some((self.get_class_name(class_index)?, name, descriptor))
+            }
+            _ => None,
+        }
+    }
+
+    fn push(&mut self, entry: CpInfo) -> Result<u16, ConstantPoolError> {
+        let index = self.positions.len();
+        let wide = matches!(
+            entry.info,
+            CpInfoType::Long { .. } | CpInfoType::Double { .. }
+        );
+        let last = if wide { index + 1 } else { index };
+        if last > MAX_CONSTANT_POOL_INDEX as usize {
+            return Err(ConstantPoolError::Overflow);
+        }
+        self.positions.push(Some(self.entries.len()));
+        if wide {
+            self.positions.push(None);
+        }
+        self.entries.push(entry);
+        Ok(index as u16)
+    }
+
+    /// Returns the index of the entry equal to `info`, adding it if needed.
+    fn add(&mut self, info: CpInfoType) -> Result<u16, ConstantPoolError> {
+        let key = Key::new(&info);
+        if let Some(index) = self.indices.get(&key) {
+            return Ok(*index);
+        }
+        let index = self.push(CpInfo {
+            tag: tag(&info),
+            info,
+        })?;
+        self.indices.insert(key, index);
+        Ok(index)
+    }
+
+    pub fn utf8(&mut self, string: &str) -> Result<u16, ConstantPoolError> {
+        let encoded_len = mutf8::encoded_len(string);
+        let length = u16::try_from(encoded_len)
+            .map_err(|_| ConstantPoolError::StringTooLong(encoded_len))?;
+        self.add(CpInfoType::Utf8 {
+            length,
+            bytes: string.to_string(),
+        })
+    }
+
+    pub fn class(&mut self, name: &str) -> Result<u16, ConstantPoolError> {
+        let name_index = self.utf8(name)?;
+        self.add(CpInfoType::Class { name_index })
+    }
+
+    pub fn string(&mut self, string: &str) -> Result<u16, ConstantPoolError> {
+        let string_index = self.utf8(string)?;
+        self.add(CpInfoType::String { string_index })
+    }
+
+    pub fn integer(&mut self, value: i32) -> Result<u16, ConstantPoolError> {
+        self.add(CpInfoType::Integer {
+            bytes: value as u32,
+        })
+    }
+
+    pub fn float(&mut self, value: f32) -> Result<u16, ConstantPoolError> {
+        self.add(CpInfoType::Float { bytes: value })
+    }
+
+    pub fn long(&mut self, value: i64) -> Result<u16, ConstantPoolError> {
+        let bits = value as u64;
+        self.add(CpInfoType::Long {
+            high_bytes: (bits >> 32) as u32,
+            low_bytes: bits as u32,
+        })
+    }
+
+    pub fn double(&mut self, value: f64) -> Result<u16, ConstantPoolError> {
+        let bits = value.to_bits();
+        self.add(CpInfoType::Double {
+            high_bytes: (bits >> 32) as u32,
+            low_bytes: bits as u32,
+        })
+    }
+
+    pub fn name_and_type(
+        &mut self,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<u16, ConstantPoolError> {
+        let name_index = self.utf8(name)?;
+        let descriptor_index = self.utf8(descriptor)?;
+        self.add(CpInfoType::NameAndType {
+            name_index,
+            descriptor_index,
+        })
+    }
+
+    pub fn field_ref(
+        &mut self,
+        class_index: u16,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<u16, ConstantPoolError> {
+        let name_and_type_index = self.name_and_type(name, descriptor)?;
+        self.add(CpInfoType::Fieldref {
+            class_index,
+            name_and_type_index,
+        })
+    }
+
+    pub fn method_ref(
+        &mut self,
+        class_index: u16,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<u16, ConstantPoolError> {
+        let name_and_type_index = self.name_and_type(name, descriptor)?;
+        self.add(CpInfoType::Methodref {
+            class_index,
+            name_and_type_index,
+        })
+    }
+
+    pub fn interface_method_ref(
+        &mut self,
+        class_index: u16,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<u16, ConstantPoolError> {
+        let name_and_type_index = self.name_and_type(name, descriptor)?;
+        self.add(CpInfoType::InterfaceMethodref {
+            class_index,
+            name_and_type_index,
+        })
+    }
+
+    pub fn method_handle(
+        &mut self,
+        reference_kind: ReferenceKind,
+        reference_index: u16,
+    ) -> Result<u16, ConstantPoolError> {
+        self.add(CpInfoType::MethodHandle {
+            reference_kind: reference_kind as u8,
+            reference_index,
+        })
+    }
+
+    pub fn method_type(&mut self, descriptor: &str) -> Result<u16, ConstantPoolError> {
+        let descriptor_index = self.utf8(descriptor)?;
+        self.add(CpInfoType::MethodType { descriptor_index })
+    }
+
+    /// Adds a dynamically computed constant whose bootstrap method is entry
+    /// `bootstrap_method_attr_index` of the `BootstrapMethods` attribute.
+    pub fn dynamic(
+        &mut self,
+        bootstrap_method_attr_index: u16,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<u16, ConstantPoolError> {
+        let name_and_type_index = self.name_and_type(name, descriptor)?;
+        self.add(CpInfoType::Dynamic {
+            bootstrap_method_attr_index,
+            name_and_type_index,
+        })
+    }
+
+    /// Adds a call site whose bootstrap method is entry
+    /// `bootstrap_method_attr_index` of the `BootstrapMethods` attribute.
+    pub fn invoke_dynamic(
+        &mut self,
+        bootstrap_method_attr_index: u16,
+        name: &str,
+        descriptor: &str,
+    ) -> Result<u16, ConstantPoolError> {
+        let name_and_type_index = self.name_and_type(name, descriptor)?;
+        self.add(CpInfoType::InvokeDynamic {
+            bootstrap_method_attr_index,
+            name_and_type_index,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interns_entries_by_value() {
+        let mut pool = ConstantPool::new();
+        let class = pool.class("java/lang/String").unwrap();
+        assert_eq!(pool.class("java/lang/String"), Ok(class));
+        assert_eq!(pool.utf8("java/lang/String"), Ok(class - 1));
+        let string = pool.string("java/lang/String").unwrap();
+        assert_ne!(string, class);
+        let field = pool.field_ref(class, "CASE", "I").unwrap();
+        let method = pool.method_ref(class, "CASE", "I").unwrap();
+        assert_ne!(field, method);
+        assert_eq!(pool.field_ref(class, "CASE", "I"), Ok(field));
+        assert_eq!(pool.integer(1), Ok(pool.integer(1).unwrap()));
+        assert_ne!(pool.integer(1), pool.float(f32::from_bits(1)));
+        assert_eq!(pool.count(), 10);
+    }
+
+    #[test]
+    fn wide_entries_take_two_slots() {
+        let mut pool = ConstantPool::new();
+        assert_eq!(pool.long(1), Ok(1));
+        assert_eq!(pool.double(1.0), Ok(3));
+        assert_eq!(pool.integer(1), Ok(5));
+        assert_eq!(pool.get(2), None);
+        assert_eq!(pool.count(), 5);
+        assert_eq!(pool.entries().len(), 3);
+    }
+
+    #[test]
+    fn reports_overflow() {
+        let mut pool = ConstantPool::new();
+        for value in 1..MAX_CONSTANT_POOL_INDEX as i32 {
+            assert_eq!(pool.integer(value), Ok(value as u16));
+        }
+        // One slot is left, which is not enough for a long.
+        assert_eq!(pool.long(0), Err(ConstantPoolError::Overflow));
+        assert_eq!(pool.integer(0), Ok(MAX_CONSTANT_POOL_INDEX));
+        assert_eq!(pool.integer(-1), Err(ConstantPoolError::Overflow));
+        // Existing entries are still found.
+        assert_eq!(pool.integer(1), Ok(1));
+    }
+
+    #[test]
+    fn rejects_strings_that_are_too_long() {
+        let mut pool = ConstantPool::new();
+        let string = "\0".repeat(40000);
+        assert_eq!(
+            pool.utf8(&string),
+            Err(ConstantPoolError::StringTooLong(80000))
+        );
+        assert_eq!(pool.count(), 0);
+    }
+
+    #[test]
+    fn reuses_entries_of_a_parsed_pool() {
+        let mut original = ConstantPool::new();
+        let class = original.class("Example").unwrap();
+        let long = original.long(7).unwrap();
+        let mut pool = ConstantPool::from_entries(original.into_entries()).unwrap();
+        assert_eq!(pool.class("Example"), Ok(class));
+        assert_eq!(pool.long(7), Ok(long));
+        assert_eq!(pool.count(), 4);
+    }
+
+    #[test]
+    fn utf8_entries_store_their_encoded_length() {
+        let mut pool = ConstantPool::new();
+        let index = pool.utf8("a\0é").unwrap();
+        assert!(matches!(
+            pool.get(index).unwrap().info,
+            CpInfoType::Utf8 { length: 5, .. }
+        ));
+    }
+}
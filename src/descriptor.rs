@@ -1,3 +1,7 @@
+use std::fmt;
+
+use crate::{bytecode::instr::*, name::ClassName};
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Descriptor {
     Byte,
@@ -9,61 +13,433 @@ pub enum Descriptor {
     Short,
     Boolean,
     Array(Box<Descriptor>),
-    Object(String),
+    /// A class or interface. Array classes are described by `Array`.
+    Object(ClassName),
     Function(Vec<Descriptor>, Box<Descriptor>),
     Void,
 }
 
+/// An error found while parsing a descriptor.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DescriptorError {
+    /// The descriptor ends in the middle of a type.
+    UnexpectedEnd,
+    /// A character that doesn't start a type, at the given byte offset.
+    InvalidCharacter { character: char, position: usize },
+    /// Characters following a complete descriptor.
+    TrailingCharacters(String),
+    /// `V` used at the given byte offset, other than as a return type.
+    InvalidVoid { position: usize },
+    /// An array type with more than 255 dimensions.
+    TooManyDimensions,
+    /// A class name that is empty or has an empty, `.` or `[` component.
+    InvalidClassName(String),
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DescriptorError::UnexpectedEnd => write!(f, "unexpected end of descriptor"),
+            DescriptorError::InvalidCharacter {
+                character,
+                position,
+            } => write!(
+                f,
+                "invalid character {:?} at offset {}",
+                character, position
+            ),
+            DescriptorError::TrailingCharacters(rest) => {
+                write!(f, "unexpected {:?} after descriptor", rest)
+            }
+            DescriptorError::InvalidVoid { position } => {
+                write!(f, "void used as a value type at offset {}", position)
+            }
+            DescriptorError::TooManyDimensions => {
+                write!(f, "array type has more than 255 dimensions")
+            }
+            DescriptorError::InvalidClassName(name) => write!(f, "invalid class name {:?}", name),
+        }
+    }
+}
+
+impl std::error::Error for DescriptorError {}
+
+/// Returns the class named `name`, an internal name which can't be that of
+/// an array class.
+fn object_class_name(name: &str) -> Result<ClassName, DescriptorError> {
+    match ClassName::new(name) {
+        Ok(class) if !class.is_array() => Ok(class),
+        _ => Err(DescriptorError::InvalidClassName(name.to_string())),
+    }
+}
+
+/// Converts a binary name such as `java.util.Map$Entry` to the internal name
+/// `java/util/Map$Entry`.
+pub fn binary_to_internal_name(name: &str) -> String {
+    name.replace('.', "/")
+}
+
+/// Converts an internal name such as `java/util/Map$Entry` to the binary name
+/// `java.util.Map$Entry`.
+pub fn internal_to_binary_name(name: &str) -> String {
+    name.replace('/', ".")
+}
+
+/// Describes the class `class`, as an `Array` for an array class.
+impl From<ClassName> for Descriptor {
+    fn from(class: ClassName) -> Self {
+        match class.is_array() {
+            true => Descriptor::parse_field(class.as_str()).unwrap(),
+            false => Descriptor::Object(class),
+        }
+    }
+}
+
+/// A cursor over the characters of a descriptor.
+struct DescriptorParser<'a> {
+    descriptor: &'a str,
+    position: usize,
+}
+
+impl DescriptorParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.descriptor[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Result<char, DescriptorError> {
+        let c = self.peek().ok_or(DescriptorError::UnexpectedEnd)?;
+        self.position += c.len_utf8();
+        Ok(c)
+    }
+
+    /// Parses a field type, which excludes `void`.
+    fn field_type(&mut self) -> Result<Descriptor, DescriptorError> {
+        let mut dimensions = 0;
+        while self.peek() == Some('[') {
+            self.position += 1;
+            dimensions += 1;
+        }
+        if dimensions > 255 {
+            return Err(DescriptorError::TooManyDimensions);
+        }
+        let position = self.position;
+        let mut descriptor = match self.next()? {
+            'B' => Descriptor::Byte,
+            'C' => Descriptor::Char,
+            'D' => Descriptor::Double,
+            'F' => Descriptor::Float,
+            'I' => Descriptor::Int,
+            'J' => Descriptor::Long,
+            'S' => Descriptor::Short,
+            'Z' => Descriptor::Boolean,
+            'L' => {
+                let rest = &self.descriptor[self.position..];
+                let end = rest.find(';').ok_or(DescriptorError::UnexpectedEnd)?;
+                let class = object_class_name(&rest[..end])?;
+                self.position += end + 1;
+                Descriptor::Object(class)
+            }
+            'V' => return Err(DescriptorError::InvalidVoid { position }),
+            character => {
+                return Err(DescriptorError::InvalidCharacter {
+                    character,
+                    position,
+                })
+            }
+        };
+        for _ in 0..dimensions {
+            descriptor = Descriptor::Array(Box::new(descriptor));
+        }
+        Ok(descriptor)
+    }
+
+    /// Fails if characters are left after the descriptor.
+    fn finish(&self) -> Result<(), DescriptorError> {
+        match &self.descriptor[self.position..] {
+            "" => Ok(()),
+            rest => Err(DescriptorError::TrailingCharacters(rest.to_string())),
+        }
+    }
+}
+
 impl Descriptor {
-    pub fn parse(descriptor: &str) -> (Descriptor, String) {
-        let mut chars = descriptor.chars();
-        (
-            match chars.next().unwrap() {
-                'B' => Descriptor::Byte,
-                'C' => Descriptor::Char,
-                'D' => Descriptor::Double,
-                'F' => Descriptor::Float,
-                'I' => Descriptor::Int,
-                'J' => Descriptor::Long,
-                'S' => Descriptor::Short,
-                'Z' => Descriptor::Boolean,
-                'L' => {
-                    let mut object = String::new();
-                    loop {
-                        match chars.next().unwrap() {
-                            ';' => break,
-                            c => object.push(c),
-                        }
-                    }
-                    Descriptor::Object(object)
-                }
-                '(' => {
-                    let mut parameters = Vec::new();
-                    loop {
-                        let c = chars.next().unwrap();
-                        if c == ')' {
-                            break;
-                        }
-                        let mut s = String::new();
-                        s.push(c);
-                        s.extend(chars.clone());
-                        let d = Descriptor::parse(&s);
-                        parameters.push(d.0);
-                        let var_name = Box::leak(Box::new(d.1.clone()));
-                        chars = var_name.chars();
-                    }
-                    Descriptor::Function(parameters, Box::new(Descriptor::parse(chars.as_str()).0))
-                }
-                '[' => {
-                    let res = Descriptor::parse(chars.as_str());
-                    let arr = Descriptor::Array(Box::new(res.0));
-                    return (arr, res.1);
-                }
-                'V' => Descriptor::Void,
-                _ => panic!("Invalid descriptor"),
-            },
-            chars.as_str().to_string(),
-        )
+    /// Parses a field descriptor such as `[Ljava/lang/String;`, or a method
+    /// descriptor such as `(IJ)V`.
+    pub fn parse(descriptor: &str) -> Result<Descriptor, DescriptorError> {
+        if descriptor.starts_with('(') {
+            Descriptor::parse_method(descriptor)
+        } else {
+            Descriptor::parse_field(descriptor)
+        }
+    }
+
+    /// Parses a field descriptor, the type of a field or local variable.
+    pub fn parse_field(descriptor: &str) -> Result<Descriptor, DescriptorError> {
+        let mut parser = DescriptorParser {
+            descriptor,
+            position: 0,
+        };
+        let field = parser.field_type()?;
+        parser.finish()?;
+        Ok(field)
+    }
+
+    /// Parses a method descriptor into a `Descriptor::Function`.
+    pub fn parse_method(descriptor: &str) -> Result<Descriptor, DescriptorError> {
+        let mut parser = DescriptorParser {
+            descriptor,
+            position: 0,
+        };
+        match parser.next()? {
+            '(' => {}
+            character => {
+                return Err(DescriptorError::InvalidCharacter {
+                    character,
+                    position: 0,
+                })
+            }
+        }
+        let mut parameters = vec![];
+        while parser.peek() != Some(')') {
+            parameters.push(parser.field_type()?);
+        }
+        parser.position += 1;
+        let return_type = match parser.peek() {
+            Some('V') => {
+                parser.position += 1;
+                Descriptor::Void
+            }
+            _ => parser.field_type()?,
+        };
+        parser.finish()?;
+        Ok(Descriptor::Function(parameters, Box::new(return_type)))
+    }
+
+    /// Parses a type written as in Java source, such as `int`,
+    /// `java.util.Map$Entry` or `java.lang.String[][]`. Classes are written
+    /// with their binary name.
+    pub fn from_java_type(java_type: &str) -> Result<Descriptor, DescriptorError> {
+        let mut base = java_type.trim();
+        let mut dimensions = 0;
+        while let Some(rest) = base.strip_suffix("[]") {
+            base = rest.trim_end();
+            dimensions += 1;
+        }
+        if dimensions > 255 {
+            return Err(DescriptorError::TooManyDimensions);
+        }
+        let mut descriptor = match base {
+            "byte" => Descriptor::Byte,
+            "char" => Descriptor::Char,
+            "double" => Descriptor::Double,
+            "float" => Descriptor::Float,
+            "int" => Descriptor::Int,
+            "long" => Descriptor::Long,
+            "short" => Descriptor::Short,
+            "boolean" => Descriptor::Boolean,
+            "void" if dimensions == 0 => Descriptor::Void,
+            "void" => {
+                return Err(DescriptorError::InvalidVoid {
+                    position: java_type.find("void").unwrap_or(0),
+                })
+            }
+            name if !name.contains(['/', '[']) && !name.contains(char::is_whitespace) => {
+                Descriptor::Object(object_class_name(&binary_to_internal_name(name))?)
+            }
+            name => return Err(DescriptorError::InvalidClassName(name.to_string())),
+        };
+        for _ in 0..dimensions {
+            descriptor = Descriptor::Array(Box::new(descriptor));
+        }
+        Ok(descriptor)
+    }
+
+    /// Returns the type as written in Java source, such as
+    /// `java.lang.String[]`, or `int(long, java.lang.Object)` for a method.
+    /// Classes are written with their binary name, such as
+    /// `java.util.Map$Entry`.
+    pub fn to_java_string(&self) -> String {
+        match self {
+            Descriptor::Byte => "byte".to_string(),
+            Descriptor::Char => "char".to_string(),
+            Descriptor::Double => "double".to_string(),
+            Descriptor::Float => "float".to_string(),
+            Descriptor::Int => "int".to_string(),
+            Descriptor::Long => "long".to_string(),
+            Descriptor::Short => "short".to_string(),
+            Descriptor::Boolean => "boolean".to_string(),
+            Descriptor::Object(object) => object.binary_name(),
+            Descriptor::Function(parameters, return_type) => {
+                let parameters: Vec<String> =
+                    parameters.iter().map(Descriptor::to_java_string).collect();
+                format!(
+                    "{}({})",
+                    return_type.to_java_string(),
+                    parameters.join(", ")
+                )
+            }
+            Descriptor::Array(inner) => format!("{}[]", inner.to_java_string()),
+            Descriptor::Void => "void".to_string(),
+        }
+    }
+
+    /// Returns the type named by a `CONSTANT_Class` entry, which holds an
+    /// internal name such as `java/lang/String`, or a descriptor for arrays
+    /// such as `[I`.
+    pub fn from_internal_name(name: &str) -> Result<Descriptor, DescriptorError> {
+        if name.starts_with('[') {
+            return Descriptor::parse_field(name);
+        }
+        Ok(Descriptor::Object(object_class_name(name)?))
+    }
+
+    /// Returns the type named by a binary name, as returned by
+    /// `Class.getName()`, such as `java.lang.String` or `[Ljava.lang.String;`.
+    pub fn from_binary_name(name: &str) -> Result<Descriptor, DescriptorError> {
+        Descriptor::from_internal_name(&binary_to_internal_name(name))
+    }
+
+    /// Returns the name a `CONSTANT_Class` entry uses for this type: the
+    /// internal name of a class, or the descriptor of an array. Other types
+    /// have none.
+    pub fn internal_name(&self) -> Option<String> {
+        match self {
+            Descriptor::Object(object) => Some(object.to_string()),
+            Descriptor::Array(_) => Some(self.serialize()),
+            _ => None,
+        }
+    }
+
+    /// Returns the name `Class.getName()` gives this type, such as
+    /// `java.util.Map$Entry` or `[Ljava.lang.String;`. Types other than
+    /// classes and arrays have none.
+    pub fn binary_name(&self) -> Option<String> {
+        self.internal_name()
+            .map(|name| internal_to_binary_name(&name))
+    }
+
+    /// Returns the number of local variable slots, or words of operand stack,
+    /// taken by a value of this type: 2 for `long` and `double`, 0 for `void`
+    /// and 1 otherwise.
+    pub fn slot_size(&self) -> u16 {
+        match self {
+            Descriptor::Long | Descriptor::Double => 2,
+            Descriptor::Void | Descriptor::Function(..) => 0,
+            _ => 1,
+        }
+    }
+
+    /// Returns the number of local slots taken by the arguments of a method,
+    /// including `this` unless the method is static. Only `Function`
+    /// descriptors have arguments.
+    pub fn argument_slots(&self, is_static: bool) -> Option<u16> {
+        match self {
+            Descriptor::Function(parameters, _) => Some(
+                parameters
+                    .iter()
+                    .map(Descriptor::slot_size)
+                    .fold(u16::from(!is_static), u16::saturating_add),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Returns the instruction loading a local of this type from `slot`, in
+    /// its shortest form, such as `iload_1`, `lload 7` or `wide aload`.
+    pub fn load_opcode(&self, slot: u16) -> Option<Opcode> {
+        let (short, long, opcode): (LocalOpcode, LocalOpcode, u8) = match self {
+            Descriptor::Long => (Opcode::LloadN, Opcode::Lload, LLOAD),
+            Descriptor::Float => (Opcode::FloadN, Opcode::Fload, FLOAD),
+            Descriptor::Double => (Opcode::DloadN, Opcode::Dload, DLOAD),
+            Descriptor::Array(_) | Descriptor::Object(_) => (Opcode::AloadN, Opcode::Aload, ALOAD),
+            Descriptor::Void | Descriptor::Function(..) => return None,
+            _ => (Opcode::IloadN, Opcode::Iload, ILOAD),
+        };
+        Some(local_opcode(short, long, opcode, slot))
+    }
+
+    /// Returns the instruction storing a value of this type to the local
+    /// `slot`, in its shortest form.
+    pub fn store_opcode(&self, slot: u16) -> Option<Opcode> {
+        let (short, long, opcode): (LocalOpcode, LocalOpcode, u8) = match self {
+            Descriptor::Long => (Opcode::LstoreN, Opcode::Lstore, LSTORE),
+            Descriptor::Float => (Opcode::FstoreN, Opcode::Fstore, FSTORE),
+            Descriptor::Double => (Opcode::DstoreN, Opcode::Dstore, DSTORE),
+            Descriptor::Array(_) | Descriptor::Object(_) => {
+                (Opcode::AstoreN, Opcode::Astore, ASTORE)
+            }
+            Descriptor::Void | Descriptor::Function(..) => return None,
+            _ => (Opcode::IstoreN, Opcode::Istore, ISTORE),
+        };
+        Some(local_opcode(short, long, opcode, slot))
+    }
+
+    /// Returns the instruction returning a value of this type, `return` for
+    /// `void`.
+    pub fn return_opcode(&self) -> Option<Opcode> {
+        Some(match self {
+            Descriptor::Long => Opcode::Lreturn,
+            Descriptor::Float => Opcode::Freturn,
+            Descriptor::Double => Opcode::Dreturn,
+            Descriptor::Array(_) | Descriptor::Object(_) => Opcode::Areturn,
+            Descriptor::Void => Opcode::Return_,
+            Descriptor::Function(..) => return None,
+            _ => Opcode::Ireturn,
+        })
+    }
+
+    /// Returns the instruction loading an element of an array of this type,
+    /// such as `baload` for `[Z`. Only arrays have elements.
+    pub fn array_load_opcode(&self) -> Option<Opcode> {
+        let Descriptor::Array(element) = self else {
+            return None;
+        };
+        Some(match **element {
+            Descriptor::Byte | Descriptor::Boolean => Opcode::Baload,
+            Descriptor::Char => Opcode::Caload,
+            Descriptor::Double => Opcode::Daload,
+            Descriptor::Float => Opcode::Faload,
+            Descriptor::Int => Opcode::Iaload,
+            Descriptor::Long => Opcode::Laload,
+            Descriptor::Short => Opcode::Saload,
+            Descriptor::Array(_) | Descriptor::Object(_) => Opcode::Aaload,
+            Descriptor::Void | Descriptor::Function(..) => return None,
+        })
+    }
+
+    /// Returns the instruction storing an element into an array of this
+    /// type, such as `bastore` for `[Z`.
+    pub fn array_store_opcode(&self) -> Option<Opcode> {
+        let Descriptor::Array(element) = self else {
+            return None;
+        };
+        Some(match **element {
+            Descriptor::Byte | Descriptor::Boolean => Opcode::Bastore,
+            Descriptor::Char => Opcode::Castore,
+            Descriptor::Double => Opcode::Dastore,
+            Descriptor::Float => Opcode::Fastore,
+            Descriptor::Int => Opcode::Iastore,
+            Descriptor::Long => Opcode::Lastore,
+            Descriptor::Short => Opcode::Sastore,
+            Descriptor::Array(_) | Descriptor::Object(_) => Opcode::Aastore,
+            Descriptor::Void | Descriptor::Function(..) => return None,
+        })
+    }
+
+    /// Returns the `newarray` operand creating an array of this primitive
+    /// type. Arrays of references are created with `anewarray`.
+    pub fn array_type(&self) -> Option<ArrayType> {
+        Some(match self {
+            Descriptor::Boolean => ArrayType::Boolean,
+            Descriptor::Char => ArrayType::Char,
+            Descriptor::Float => ArrayType::Float,
+            Descriptor::Double => ArrayType::Double,
+            Descriptor::Byte => ArrayType::Byte,
+            Descriptor::Short => ArrayType::Short,
+            Descriptor::Int => ArrayType::Int,
+            Descriptor::Long => ArrayType::Long,
+            _ => return None,
+        })
     }
 
     pub fn serialize(&self) -> String {
@@ -91,3 +467,104 @@ impl Descriptor {
         }
     }
 }
+
+/// The constructor of an instruction accessing a local, such as `Opcode::Iload`.
+type LocalOpcode = fn(u8) -> Opcode;
+
+/// Returns the `<x>_<n>` form of a local access for slots 0 to 3, the
+/// one-byte form up to 255 and the `wide` form above.
+fn local_opcode(short: LocalOpcode, long: LocalOpcode, opcode: u8, slot: u16) -> Opcode {
+    match u8::try_from(slot) {
+        Ok(slot) if slot <= 3 => short(slot),
+        Ok(slot) => long(slot),
+        Err(_) => {
+            let [high, low] = slot.to_be_bytes();
+            Opcode::Wide(opcode, high, low)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptors_round_trip() {
+        for descriptor in [
+            "I",
+            "Z",
+            "[[D",
+            "Ljava/lang/String;",
+            "[Ljava/util/Map$Entry;",
+            "()V",
+            "(IJ[Ljava/lang/Object;)Ljava/util/List;",
+            "(BCSFZ)[I",
+        ] {
+            assert_eq!(
+                Descriptor::parse(descriptor).unwrap().serialize(),
+                descriptor
+            );
+        }
+    }
+
+    #[test]
+    fn parses_method_descriptors() {
+        let string = Descriptor::Object(ClassName::new("java/lang/String").unwrap());
+        assert_eq!(
+            Descriptor::parse("(J[Ljava/lang/String;)I"),
+            Ok(Descriptor::Function(
+                vec![Descriptor::Long, Descriptor::Array(Box::new(string))],
+                Box::new(Descriptor::Int)
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_descriptors() {
+        assert_eq!(
+            Descriptor::parse("Ljava/lang/String"),
+            Err(DescriptorError::UnexpectedEnd)
+        );
+        assert_eq!(
+            Descriptor::parse("II"),
+            Err(DescriptorError::TrailingCharacters("I".to_string()))
+        );
+        assert_eq!(
+            Descriptor::parse("(V)V"),
+            Err(DescriptorError::InvalidVoid { position: 1 })
+        );
+        assert_eq!(
+            Descriptor::parse("Q"),
+            Err(DescriptorError::InvalidCharacter {
+                character: 'Q',
+                position: 0
+            })
+        );
+        let deep = format!("{}I", "[".repeat(256));
+        assert_eq!(
+            Descriptor::parse(&deep),
+            Err(DescriptorError::TooManyDimensions)
+        );
+    }
+
+    #[test]
+    fn java_types_round_trip() {
+        for java_type in ["int", "java.util.Map$Entry", "java.lang.String[][]", "void"] {
+            let descriptor = Descriptor::from_java_type(java_type).unwrap();
+            assert_eq!(descriptor.to_java_string(), java_type);
+        }
+        assert_eq!(
+            Descriptor::from_java_type("java.util.List[][]")
+                .unwrap()
+                .serialize(),
+            "[[Ljava/util/List;"
+        );
+    }
+
+    #[test]
+    fn counts_argument_slots() {
+        let method = Descriptor::parse("(IJLjava/lang/Object;D)V").unwrap();
+        assert_eq!(method.argument_slots(true), Some(6));
+        assert_eq!(method.argument_slots(false), Some(7));
+    }
+}
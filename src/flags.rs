@@ -1,30 +1,656 @@
-#[derive(Debug, Clone, PartialEq, Default)]
-pub struct Flags<T>
-where
-    T: std::ops::BitAnd<Output = T> + std::ops::BitOr<Output = T> + PartialEq + Clone + Default,
-{
-    pub flags: T,
+use std::{fmt, marker::PhantomData, ops, str::FromStr};
+
+/// A single access flag of one kind, such as `MethodFlag::Static`.
+pub trait AccessFlag: Copy + PartialEq + 'static {
+    /// Every flag of the kind, in the order Java writes modifiers.
+    const ALL: &'static [Self];
+
+    fn bits(self) -> u16;
+
+    /// Returns the Java keyword of the flag, such as `static`, or its name in
+    /// lower case for flags without one, such as `synthetic`.
+    fn keyword(self) -> &'static str;
+}
+
+/// A set of access flags of one kind. Bits that aren't a flag of the kind are
+/// kept, so that parsed class files are written back unchanged.
+pub struct Flags<F> {
+    bits: u16,
+    kind: PhantomData<F>,
+}
+
+pub type ClassAccess = Flags<ClassFlag>;
+pub type FieldAccess = Flags<FieldFlag>;
+pub type MethodAccess = Flags<MethodFlag>;
+pub type InnerClassAccess = Flags<InnerClassFlag>;
+pub type ModuleAccess = Flags<ModuleFlag>;
+
+/// An error found while parsing flags from Java keywords.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlagsError {
+    /// A word that isn't the keyword of a flag of the kind.
+    UnknownKeyword(String),
+}
+
+impl fmt::Display for FlagsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlagsError::UnknownKeyword(keyword) => write!(f, "unknown modifier {:?}", keyword),
+        }
+    }
+}
+
+impl std::error::Error for FlagsError {}
+
+/// A combination of access flags that the JVM rejects with a
+/// `ClassFormatError`, as found by the `check` methods.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessError {
+    /// Two flags that can't be set together, such as `abstract` and `final`.
+    Conflict(&'static str, &'static str),
+    /// A flag, or a choice of flags, that `on` must have, such as `abstract`
+    /// on an interface.
+    Missing {
+        flag: &'static str,
+        on: &'static str,
+    },
+    /// A flag that `on` can't have, such as `protected` on an interface
+    /// method.
+    NotAllowed {
+        flag: &'static str,
+        on: &'static str,
+    },
+    /// An `<init>` method in an interface, which only classes can have.
+    ConstructorInInterface,
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessError::Conflict(first, second) => {
+                write!(f, "{} and {} can't be combined", first, second)
+            }
+            AccessError::Missing { flag, on } => write!(f, "{} must be {}", on, flag),
+            AccessError::NotAllowed { flag, on } => write!(f, "{} can't be {}", on, flag),
+            AccessError::ConstructorInInterface => write!(f, "interfaces can't have constructors"),
+        }
+    }
+}
+
+impl std::error::Error for AccessError {}
+
+fn conflict<F: AccessFlag>(flags: Flags<F>, first: F, second: F) -> Result<(), AccessError> {
+    if flags.is_set(first) && flags.is_set(second) {
+        return Err(AccessError::Conflict(first.keyword(), second.keyword()));
+    }
+    Ok(())
+}
+
+fn require<F: AccessFlag>(flags: Flags<F>, flag: F, on: &'static str) -> Result<(), AccessError> {
+    if !flags.is_set(flag) {
+        return Err(AccessError::Missing {
+            flag: flag.keyword(),
+            on,
+        });
+    }
+    Ok(())
 }
 
-impl<T> Flags<T>
-where
-    T: std::ops::BitAnd<Output = T> + std::ops::BitOr<Output = T> + PartialEq + Clone + Default,
-{
-    pub fn new(flags: T) -> Flags<T> {
-        Flags { flags }
+fn forbid<F: AccessFlag>(
+    flags: Flags<F>,
+    forbidden: &[F],
+    on: &'static str,
+) -> Result<(), AccessError> {
+    match forbidden.iter().find(|flag| flags.is_set(**flag)) {
+        Some(flag) => Err(AccessError::NotAllowed {
+            flag: flag.keyword(),
+            on,
+        }),
+        None => Ok(()),
     }
-    pub fn is_set(&self, flag: T) -> bool {
-        self.flags.clone() & flag.clone() == flag.clone()
+}
+
+/// Checks that at most one of `public`, `protected` and `private` is set.
+fn visibility<F: AccessFlag>(
+    flags: Flags<F>,
+    public: F,
+    protected: F,
+    private: F,
+) -> Result<(), AccessError> {
+    conflict(flags, public, protected)?;
+    conflict(flags, public, private)?;
+    conflict(flags, protected, private)
+}
+
+impl<F: AccessFlag> Flags<F> {
+    pub const fn empty() -> Self {
+        Flags::from_bits(0)
+    }
+
+    pub const fn from_bits(bits: u16) -> Self {
+        Flags {
+            bits,
+            kind: PhantomData,
+        }
+    }
+
+    pub fn bits(self) -> u16 {
+        self.bits
     }
 
-    pub fn set(&mut self, flag: T) {
-        self.flags = self.flags.clone() | flag;
+    pub fn is_empty(self) -> bool {
+        self.bits == 0
     }
 
-    pub fn get_all_flags(&self, possible_flags: Vec<T>) -> Vec<T> {
+    /// Returns `true` if every flag of `flags` is set.
+    pub fn is_set(self, flags: impl Into<Flags<F>>) -> bool {
+        let flags = flags.into();
+        self.bits & flags.bits == flags.bits
+    }
+
+    pub fn set(&mut self, flags: impl Into<Flags<F>>) {
+        self.bits |= flags.into().bits;
+    }
+
+    pub fn unset(&mut self, flags: impl Into<Flags<F>>) {
+        self.bits &= !flags.into().bits;
+    }
+
+    /// Returns the flags that are set, in the order Java writes modifiers.
+    pub fn iter(self) -> impl Iterator<Item = F> {
+        F::ALL
+            .iter()
+            .copied()
+            .filter(move |flag| self.is_set(*flag))
+    }
+
+    #[deprecated(note = "use `Flags::from_bits`")]
+    pub fn new(bits: u16) -> Self {
+        Flags::from_bits(bits)
+    }
+
+    #[deprecated(note = "use `Flags::iter`")]
+    pub fn get_all_flags(&self, possible_flags: Vec<F>) -> Vec<F> {
         possible_flags
             .into_iter()
-            .filter(|flag| self.is_set(flag.clone()))
+            .filter(|flag| self.is_set(*flag))
             .collect()
     }
 }
+
+impl<F> Clone for Flags<F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<F> Copy for Flags<F> {}
+
+impl<F> PartialEq for Flags<F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits == other.bits
+    }
+}
+
+impl<F> Eq for Flags<F> {}
+
+impl<F> std::hash::Hash for Flags<F> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.bits.hash(state);
+    }
+}
+
+impl<F> Default for Flags<F> {
+    fn default() -> Self {
+        Flags {
+            bits: 0,
+            kind: PhantomData,
+        }
+    }
+}
+
+impl<F: AccessFlag> From<F> for Flags<F> {
+    fn from(flag: F) -> Self {
+        Flags::from_bits(flag.bits())
+    }
+}
+
+impl<F: AccessFlag, T: Into<Flags<F>>> ops::BitOr<T> for Flags<F> {
+    type Output = Flags<F>;
+
+    fn bitor(self, rhs: T) -> Flags<F> {
+        Flags::from_bits(self.bits | rhs.into().bits)
+    }
+}
+
+impl<F: AccessFlag, T: Into<Flags<F>>> ops::BitOrAssign<T> for Flags<F> {
+    fn bitor_assign(&mut self, rhs: T) {
+        self.set(rhs);
+    }
+}
+
+impl<F: AccessFlag, T: Into<Flags<F>>> ops::BitAnd<T> for Flags<F> {
+    type Output = Flags<F>;
+
+    fn bitand(self, rhs: T) -> Flags<F> {
+        Flags::from_bits(self.bits & rhs.into().bits)
+    }
+}
+
+/// Shows the flags as Java modifiers, such as `public static final`.
+impl<F: AccessFlag> fmt::Display for Flags<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, flag) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            f.write_str(flag.keyword())?;
+        }
+        Ok(())
+    }
+}
+
+impl<F: AccessFlag> fmt::Debug for Flags<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Flags({:#06x} {})", self.bits, self)
+    }
+}
+
+/// Parses modifiers separated by whitespace, such as `public static final`.
+impl<F: AccessFlag> FromStr for Flags<F> {
+    type Err = FlagsError;
+
+    fn from_str(s: &str) -> Result<Self, FlagsError> {
+        let mut flags = Flags::empty();
+        for word in s.split_whitespace() {
+            let flag = F::ALL
+                .iter()
+                .find(|flag| flag.keyword() == word)
+                .ok_or_else(|| FlagsError::UnknownKeyword(word.to_string()))?;
+            flags.set(*flag);
+        }
+        Ok(flags)
+    }
+}
+
+impl Flags<ClassFlag> {
+    /// Checks the flags of a class file of version `major_version` against
+    /// JVMS 4.1.
+    pub fn check(self, major_version: u16) -> Result<(), AccessError> {
+        use ClassFlag::*;
+        if major_version >= 53 && self.is_set(Module) {
+            return match self.iter().find(|flag| *flag != Module) {
+                Some(flag) => Err(AccessError::NotAllowed {
+                    flag: flag.keyword(),
+                    on: "module",
+                }),
+                None => Ok(()),
+            };
+        }
+        if self.is_set(Interface) {
+            forbid(self, &[Final], "interface")?;
+            if major_version >= 49 {
+                forbid(self, &[Super, Enum], "interface")?;
+            }
+            // Before Java 6 the JVM treats every interface as abstract.
+            if major_version >= 50 {
+                require(self, Abstract, "interface")?;
+            }
+        } else if major_version >= 49 {
+            forbid(self, &[Annotation], "class")?;
+        }
+        conflict(self, Abstract, Final)
+    }
+}
+
+impl Flags<FieldFlag> {
+    /// Checks the flags of a field of a class file of version
+    /// `major_version` against JVMS 4.5. `in_interface` tells whether the
+    /// field belongs to an interface.
+    pub fn check(self, major_version: u16, in_interface: bool) -> Result<(), AccessError> {
+        use FieldFlag::*;
+        if !in_interface {
+            visibility(self, Public, Protected, Private)?;
+            return conflict(self, Final, Volatile);
+        }
+        forbid(
+            self,
+            &[Protected, Private, Volatile, Transient],
+            "interface field",
+        )?;
+        if major_version >= 49 {
+            forbid(self, &[Enum], "interface field")?;
+        }
+        for flag in [Public, Static, Final] {
+            require(self, flag, "interface field")?;
+        }
+        Ok(())
+    }
+}
+
+impl Flags<MethodFlag> {
+    /// Checks the flags of the method `name` of a class file of version
+    /// `major_version` against JVMS 4.6. `in_interface` tells whether the
+    /// method belongs to an interface.
+    pub fn check(
+        self,
+        name: &str,
+        major_version: u16,
+        in_interface: bool,
+    ) -> Result<(), AccessError> {
+        use MethodFlag::*;
+        // Other flags of a static initializer are ignored.
+        if name == "<clinit>" {
+            if major_version >= 51 {
+                require(self, Static, "static initializer")?;
+            }
+            return Ok(());
+        }
+        // `strictfp` has no meaning since Java 17.
+        let strict = if (49..61).contains(&major_version) {
+            &[Strict][..]
+        } else {
+            &[]
+        };
+        if in_interface {
+            if name == "<init>" {
+                return Err(AccessError::ConstructorInInterface);
+            }
+            if major_version < 52 {
+                forbid(self, &[Static, Final, Native], "interface method")?;
+                if major_version >= 49 {
+                    forbid(
+                        self,
+                        &[Protected, Private, Synchronized, Strict],
+                        "interface method",
+                    )?;
+                }
+                require(self, Public, "interface method")?;
+                return require(self, Abstract, "interface method");
+            }
+            forbid(
+                self,
+                &[Protected, Final, Synchronized, Native],
+                "interface method",
+            )?;
+            conflict(self, Public, Private)?;
+            if !self.is_set(Public) && !self.is_set(Private) {
+                return Err(AccessError::Missing {
+                    flag: "public or private",
+                    on: "interface method",
+                });
+            }
+            if self.is_set(Abstract) {
+                forbid(self, &[Private, Static], "abstract method")?;
+                forbid(self, strict, "abstract method")?;
+            }
+            return Ok(());
+        }
+        visibility(self, Public, Protected, Private)?;
+        if name == "<init>" {
+            forbid(
+                self,
+                &[Static, Final, Synchronized, Native, Abstract],
+                "constructor",
+            )?;
+            if major_version >= 49 {
+                forbid(self, &[Bridge], "constructor")?;
+            }
+        } else if self.is_set(Abstract) {
+            forbid(self, &[Final, Native, Private, Static], "abstract method")?;
+            if major_version >= 49 {
+                forbid(self, &[Synchronized], "abstract method")?;
+                forbid(self, strict, "abstract method")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+macro_rules! access_flags {
+    ($(#[$doc:meta])* $name:ident { $($flag:ident = $bits:literal, $keyword:literal;)* }) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($flag,)*
+        }
+
+        impl AccessFlag for $name {
+            const ALL: &'static [Self] = &[$($name::$flag,)*];
+
+            fn bits(self) -> u16 {
+                match self {
+                    $($name::$flag => $bits,)*
+                }
+            }
+
+            fn keyword(self) -> &'static str {
+                match self {
+                    $($name::$flag => $keyword,)*
+                }
+            }
+        }
+
+        impl ops::BitOr for $name {
+            type Output = Flags<$name>;
+
+            fn bitor(self, rhs: $name) -> Flags<$name> {
+                Flags::from(self) | rhs
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.keyword())
+            }
+        }
+    };
+}
+
+access_flags! {
+    /// A flag of `ClassFile.access_flags`.
+    ClassFlag {
+        Public = 0x0001, "public";
+        Abstract = 0x0400, "abstract";
+        Final = 0x0010, "final";
+        Synthetic = 0x1000, "synthetic";
+        Super = 0x0020, "super";
+        Annotation = 0x2000, "@interface";
+        Interface = 0x0200, "interface";
+        Enum = 0x4000, "enum";
+        Module = 0x8000, "module";
+    }
+}
+
+access_flags! {
+    /// A flag of `FieldInfo.access_flags`.
+    FieldFlag {
+        Public = 0x0001, "public";
+        Protected = 0x0004, "protected";
+        Private = 0x0002, "private";
+        Static = 0x0008, "static";
+        Final = 0x0010, "final";
+        Transient = 0x0080, "transient";
+        Volatile = 0x0040, "volatile";
+        Synthetic = 0x1000, "synthetic";
+        Enum = 0x4000, "enum";
+    }
+}
+
+access_flags! {
+    /// A flag of `MethodInfo.access_flags`.
+    MethodFlag {
+        Public = 0x0001, "public";
+        Protected = 0x0004, "protected";
+        Private = 0x0002, "private";
+        Abstract = 0x0400, "abstract";
+        Static = 0x0008, "static";
+        Final = 0x0010, "final";
+        Synchronized = 0x0020, "synchronized";
+        Native = 0x0100, "native";
+        Strict = 0x0800, "strictfp";
+        Synthetic = 0x1000, "synthetic";
+        Bridge = 0x0040, "bridge";
+        Varargs = 0x0080, "varargs";
+    }
+}
+
+access_flags! {
+    /// A flag of `InnerClass.inner_class_access_flags`.
+    InnerClassFlag {
+        Public = 0x0001, "public";
+        Protected = 0x0004, "protected";
+        Private = 0x0002, "private";
+        Abstract = 0x0400, "abstract";
+        Static = 0x0008, "static";
+        Final = 0x0010, "final";
+        Synthetic = 0x1000, "synthetic";
+        Annotation = 0x2000, "@interface";
+        Interface = 0x0200, "interface";
+        Enum = 0x4000, "enum";
+    }
+}
+
+access_flags! {
+    /// A flag of the `module_flags` of a `Module` attribute.
+    ModuleFlag {
+        Open = 0x0020, "open";
+        Synthetic = 0x1000, "synthetic";
+        Mandated = 0x8000, "mandated";
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::*;
+
+    #[test]
+    fn checks_class_flags() {
+        assert_eq!((PUBLIC | SUPER).check(61), Ok(()));
+        assert_eq!(
+            (PUBLIC | ABSTRACT | FINAL).check(61),
+            Err(AccessError::Conflict("abstract", "final"))
+        );
+        assert_eq!(
+            (PUBLIC | INTERFACE).check(61),
+            Err(AccessError::Missing {
+                flag: "abstract",
+                on: "interface"
+            })
+        );
+        // Interfaces were implicitly abstract before Java 6.
+        assert_eq!((PUBLIC | INTERFACE).check(49), Ok(()));
+        assert_eq!(
+            (INTERFACE | ABSTRACT | SUPER).check(61),
+            Err(AccessError::NotAllowed {
+                flag: "super",
+                on: "interface"
+            })
+        );
+        assert_eq!(
+            (PUBLIC | ANNOTATION).check(61),
+            Err(AccessError::NotAllowed {
+                flag: "@interface",
+                on: "class"
+            })
+        );
+        assert_eq!(MODULE.check(53), Ok(()));
+        assert!((MODULE | PUBLIC).check(53).is_err());
+    }
+
+    #[test]
+    fn checks_field_flags() {
+        assert_eq!(
+            (FIELD_ACC_PRIVATE | FIELD_ACC_FINAL).check(61, false),
+            Ok(())
+        );
+        assert_eq!(
+            (FIELD_ACC_PUBLIC | FIELD_ACC_PRIVATE).check(61, false),
+            Err(AccessError::Conflict("public", "private"))
+        );
+        assert_eq!(
+            (FIELD_ACC_FINAL | FIELD_ACC_VOLATILE).check(61, false),
+            Err(AccessError::Conflict("final", "volatile"))
+        );
+        let constant = FIELD_ACC_PUBLIC | FIELD_ACC_STATIC | FIELD_ACC_FINAL;
+        assert_eq!(constant.check(61, true), Ok(()));
+        assert_eq!(
+            (FIELD_ACC_PUBLIC | FIELD_ACC_STATIC).check(61, true),
+            Err(AccessError::Missing {
+                flag: "final",
+                on: "interface field"
+            })
+        );
+    }
+
+    #[test]
+    fn checks_method_flags() {
+        let abstract_method = METHOD_ACC_PUBLIC | METHOD_ACC_ABSTRACT;
+        assert_eq!(abstract_method.check("run", 61, false), Ok(()));
+        assert_eq!(
+            (abstract_method | METHOD_ACC_FINAL).check("run", 61, false),
+            Err(AccessError::NotAllowed {
+                flag: "final",
+                on: "abstract method"
+            })
+        );
+        assert_eq!(
+            (METHOD_ACC_PUBLIC | METHOD_ACC_STATIC).check("<init>", 61, false),
+            Err(AccessError::NotAllowed {
+                flag: "static",
+                on: "constructor"
+            })
+        );
+        // Other flags of a static initializer are ignored.
+        assert_eq!(METHOD_ACC_STATIC.check("<clinit>", 61, false), Ok(()));
+        assert!(METHOD_ACC_PUBLIC.check("<clinit>", 61, false).is_err());
+        // Interfaces have private and static methods since Java 8.
+        let private = METHOD_ACC_PRIVATE | METHOD_ACC_STATIC;
+        assert_eq!(private.check("helper", 52, true), Ok(()));
+        assert!(private.check("helper", 51, true).is_err());
+        assert_eq!(
+            METHOD_ACC_STATIC.check("helper", 61, true),
+            Err(AccessError::Missing {
+                flag: "public or private",
+                on: "interface method"
+            })
+        );
+    }
+
+    #[test]
+    fn flags_display_and_parse_as_modifiers() {
+        let flags = FIELD_ACC_PUBLIC | FIELD_ACC_STATIC | FIELD_ACC_FINAL;
+        assert_eq!(flags.to_string(), "public static final");
+        assert_eq!("public static final".parse(), Ok(flags));
+        assert!("public sealed".parse::<FieldAccess>().is_err());
+    }
+
+    #[test]
+    fn interfaces_reject_constructors() {
+        assert_eq!(
+            METHOD_ACC_PUBLIC.check("<init>", 61, true),
+            Err(AccessError::ConstructorInInterface)
+        );
+        assert_eq!(METHOD_ACC_PUBLIC.check("<init>", 61, false), Ok(()));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_methods_match_their_replacements() {
+        let flags = MethodAccess::new(0x0009);
+        assert_eq!(flags, MethodAccess::from_bits(0x0009));
+        assert_eq!(
+            flags.get_all_flags(vec![
+                MethodFlag::Static,
+                MethodFlag::Final,
+                MethodFlag::Public
+            ]),
+            [MethodFlag::Static, MethodFlag::Public]
+        );
+        assert_eq!(
+            flags.iter().collect::<Vec<_>>(),
+            [MethodFlag::Public, MethodFlag::Static]
+        );
+    }
+}
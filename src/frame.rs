@@ -0,0 +1,1121 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    bytecode::{
+        instr::{
+            Opcode, ALOAD, ASTORE, DLOAD, DSTORE, FLOAD, FSTORE, ILOAD, ISTORE, LLOAD, LSTORE,
+        },
+        AttributeInfoKind, ClassFile, CpInfoType, MethodInfo, METHOD_ACC_STATIC,
+    },
+    constant_pool::{ConstantPool, ConstantPoolError},
+    descriptor::Descriptor,
+    flags::MethodAccess,
+    hierarchy::{ClassHierarchy, OBJECT_CLASS},
+};
+
+pub const THROWABLE_CLASS: &str = "java/lang/Throwable";
+
+/// The symbolic form of a `VerificationTypeInfo`. Object types hold an internal
+/// class name, or a descriptor for array types (`[I`, `[Ljava/lang/String;`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Long,
+    Double,
+    Null,
+    UninitializedThis,
+    Object(String),
+    /// An object created by the `new` instruction at the given pc.
+    Uninitialized(u16),
+}
+
+impl VerificationType {
+    pub fn from_descriptor(descriptor: &Descriptor) -> Option<VerificationType> {
+        Some(match descriptor {
+            Descriptor::Byte
+            | Descriptor::Char
+            | Descriptor::Short
+            | Descriptor::Boolean
+            | Descriptor::Int => VerificationType::Integer,
+            Descriptor::Float => VerificationType::Float,
+            Descriptor::Long => VerificationType::Long,
+            Descriptor::Double => VerificationType::Double,
+            Descriptor::Object(name) => VerificationType::Object(name.to_string()),
+            Descriptor::Array(_) => VerificationType::Object(descriptor.serialize()),
+            Descriptor::Void | Descriptor::Function(..) => return None,
+        })
+    }
+
+    /// Returns `true` for `Long` and `Double`, which take two local slots and
+    /// two words of operand stack.
+    pub fn is_wide(&self) -> bool {
+        matches!(self, VerificationType::Long | VerificationType::Double)
+    }
+
+    pub fn is_reference(&self) -> bool {
+        matches!(
+            self,
+            VerificationType::Null
+                | VerificationType::UninitializedThis
+                | VerificationType::Object(_)
+                | VerificationType::Uninitialized(_)
+        )
+    }
+}
+
+/// The types held in the local variables and on the operand stack before an
+/// instruction executes. A wide local at slot `n` is followed by `Top` at `n + 1`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Frame {
+    pub locals: Vec<VerificationType>,
+    pub stack: Vec<VerificationType>,
+}
+
+impl Frame {
+    fn set_local(&mut self, index: usize, value: VerificationType) {
+        let end = index + if value.is_wide() { 2 } else { 1 };
+        if self.locals.len() < end {
+            self.locals.resize(end, VerificationType::Top);
+        }
+        if index > 0 && self.locals[index - 1].is_wide() {
+            self.locals[index - 1] = VerificationType::Top;
+        }
+        if value.is_wide() {
+            self.locals[index + 1] = VerificationType::Top;
+        }
+        self.locals[index] = value;
+    }
+
+    /// Returns the number of operand stack words used, counting `Long` and
+    /// `Double` as two.
+    pub fn stack_size(&self) -> usize {
+        self.stack
+            .iter()
+            .map(|t| if t.is_wide() { 2 } else { 1 })
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameError {
+    MissingCode,
+    InvalidDescriptor(String),
+    InvalidConstant { pc: u16, index: u16 },
+    StackUnderflow { pc: u16 },
+    StackMismatch { pc: u16 },
+    InvalidBranchTarget { pc: u16, target: i32 },
+    FallsOffEnd { pc: u16 },
+    Unsupported { pc: u16, opcode: Opcode },
+    ConstantPool(ConstantPoolError),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::MissingCode => write!(f, "method has no Code attribute"),
+            FrameError::InvalidDescriptor(d) => write!(f, "invalid descriptor {}", d),
+            FrameError::InvalidConstant { pc, index } => {
+                write!(f, "invalid constant pool index {} at pc {}", index, pc)
+            }
+            FrameError::StackUnderflow { pc } => write!(f, "operand stack underflow at pc {}", pc),
+            FrameError::StackMismatch { pc } => {
+                write!(f, "inconsistent operand stack at join point pc {}", pc)
+            }
+            FrameError::InvalidBranchTarget { pc, target } => {
+                write!(f, "invalid branch target {} at pc {}", target, pc)
+            }
+            FrameError::FallsOffEnd { pc } => {
+                write!(f, "execution falls off the end of the code at pc {}", pc)
+            }
+            FrameError::Unsupported { pc, opcode } => {
+                write!(f, "unsupported instruction {:?} at pc {}", opcode, pc)
+            }
+            FrameError::ConstantPool(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl FrameError {
+    /// Returns the pc of the instruction where the analysis failed, if the
+    /// error comes from one.
+    pub fn pc(&self) -> Option<u16> {
+        match self {
+            FrameError::InvalidConstant { pc, .. }
+            | FrameError::StackUnderflow { pc }
+            | FrameError::StackMismatch { pc }
+            | FrameError::InvalidBranchTarget { pc, .. }
+            | FrameError::FallsOffEnd { pc }
+            | FrameError::Unsupported { pc, .. } => Some(*pc),
+            FrameError::MissingCode
+            | FrameError::InvalidDescriptor(_)
+            | FrameError::ConstantPool(_) => None,
+        }
+    }
+}
+
+impl From<ConstantPoolError> for FrameError {
+    fn from(e: ConstantPoolError) -> Self {
+        FrameError::ConstantPool(e)
+    }
+}
+
+/// The result of running the frame analysis over a method body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    /// The frame on entry to the method.
+    pub initial: Frame,
+    /// The pc of each instruction.
+    pub pcs: Vec<u16>,
+    /// The incoming frame of each instruction, `None` if it is unreachable.
+    pub frames: Vec<Option<Frame>>,
+    pub max_stack: u16,
+    pub max_locals: u16,
+}
+
+impl Analysis {
+    /// Returns the frame before the instruction at `pc`, or `None` if no
+    /// instruction starts at `pc` or it is unreachable.
+    pub fn frame_at(&self, pc: u16) -> Option<&Frame> {
+        let index = self.pcs.binary_search(&pc).ok()?;
+        self.frames[index].as_ref()
+    }
+}
+
+/// Computes the initial frame of a method from its descriptor and flags.
+pub(crate) fn initial_frame(
+    this_class: &str,
+    name: &str,
+    descriptor: &str,
+    access_flags: MethodAccess,
+) -> Result<Frame, FrameError> {
+    let parameters = match Descriptor::parse_method(descriptor) {
+        Ok(Descriptor::Function(parameters, _)) => parameters,
+        _ => return Err(FrameError::InvalidDescriptor(descriptor.to_string())),
+    };
+    let mut frame = Frame::default();
+    if !access_flags.is_set(METHOD_ACC_STATIC) {
+        if name == "<init>" && this_class != OBJECT_CLASS {
+            frame.locals.push(VerificationType::UninitializedThis);
+        } else {
+            frame
+                .locals
+                .push(VerificationType::Object(this_class.to_string()));
+        }
+    }
+    for parameter in &parameters {
+        let t = VerificationType::from_descriptor(parameter)
+            .ok_or_else(|| FrameError::InvalidDescriptor(descriptor.to_string()))?;
+        let index = frame.locals.len();
+        frame.set_local(index, t);
+    }
+    Ok(frame)
+}
+
+/// Runs a dataflow analysis over the code of `method` and returns the frame
+/// before every reachable instruction.
+///
+/// Objects created by `new` are `Uninitialized` until their constructor is
+/// called, as is `this` in a constructor. Where control flow joins, object
+/// types are merged to the common super class found by `hierarchy`, and
+/// locals that disagree become `Top`. The error tells where inference
+/// failed, such as an operand stack of a different height at a join point.
+///
+/// This indexes the constant pool of `class` on every call, so methods of the
+/// same class are better analyzed with `analyze_with_pool`.
+pub fn analyze(
+    class: &ClassFile,
+    method: &MethodInfo,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<Analysis, FrameError> {
+    let pool = ConstantPool::from_entries(class.constant_pool.clone())?;
+    analyze_with_pool(class, &pool, method, hierarchy)
+}
+
+/// Like `analyze`, looking up constants in `pool`, which must hold the
+/// entries of `class`.
+pub fn analyze_with_pool(
+    class: &ClassFile,
+    pool: &ConstantPool,
+    method: &MethodInfo,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<Analysis, FrameError> {
+    let (code, exception_table) = method
+        .attributes
+        .iter()
+        .find_map(|a| match &a.info {
+            AttributeInfoKind::Code {
+                code,
+                exception_table,
+                ..
+            } => Some((code, exception_table)),
+            _ => None,
+        })
+        .ok_or(FrameError::MissingCode)?;
+    let invalid = |index| FrameError::InvalidConstant { pc: 0, index };
+    let this_class = pool
+        .get_class_name(class.this_class)
+        .ok_or(invalid(class.this_class))?
+        .to_string();
+    let name = pool
+        .get_utf8(method.name_index)
+        .ok_or(invalid(method.name_index))?;
+    let descriptor = pool
+        .get_utf8(method.descriptor_index)
+        .ok_or(invalid(method.descriptor_index))?;
+    let initial = initial_frame(&this_class, name, descriptor, method.access_flags)?;
+
+    let mut pcs = Vec::with_capacity(code.len());
+    let mut pc = 0usize;
+    for op in code {
+        pcs.push(pc as u16);
+        pc += op.size(pc);
+    }
+    let index_of: HashMap<u16, usize> = pcs.iter().enumerate().map(|(i, pc)| (*pc, i)).collect();
+    let analyzer = Analyzer {
+        pool,
+        hierarchy,
+        this_class,
+        code,
+        pcs: &pcs,
+    };
+
+    let mut handlers = vec![];
+    for e in exception_table {
+        let handler = *index_of
+            .get(&e.handler_pc)
+            .ok_or(FrameError::InvalidBranchTarget {
+                pc: e.start_pc,
+                target: e.handler_pc as i32,
+            })?;
+        let catch_type = if e.catch_type == 0 {
+            THROWABLE_CLASS.to_string()
+        } else {
+            analyzer
+                .pool
+                .get_class_name(e.catch_type)
+                .ok_or(FrameError::InvalidConstant {
+                    pc: e.handler_pc,
+                    index: e.catch_type,
+                })?
+                .to_string()
+        };
+        handlers.push((e.start_pc, e.end_pc, handler, catch_type));
+    }
+
+    let mut max_stack = 0;
+    let mut max_locals = initial.locals.len();
+    let mut frames: Vec<Option<Frame>> = vec![None; code.len()];
+    let mut worklist = vec![];
+    if !code.is_empty() {
+        frames[0] = Some(initial.clone());
+        worklist.push(0);
+    }
+    while let Some(index) = worklist.pop() {
+        let pc = pcs[index];
+        let mut frame = frames[index].clone().unwrap();
+        for (start, end, handler, catch_type) in &handlers {
+            if *start <= pc && pc < *end {
+                let handler_frame = Frame {
+                    locals: frame.locals.clone(),
+                    stack: vec![VerificationType::Object(catch_type.clone())],
+                };
+                if analyzer.merge_into(&mut frames, *handler, handler_frame)? {
+                    worklist.push(*handler);
+                }
+            }
+        }
+        max_stack = max_stack.max(frame.stack_size());
+        analyzer.execute(index, &mut frame)?;
+        max_stack = max_stack.max(frame.stack_size());
+        max_locals = max_locals.max(frame.locals.len());
+
+        let op = &code[index];
+        let mut successors = vec![];
+        for target in op.branch_targets(pc) {
+            let successor = u16::try_from(target)
+                .ok()
+                .and_then(|t| index_of.get(&t))
+                .ok_or(FrameError::InvalidBranchTarget { pc, target })?;
+            successors.push(*successor);
+        }
+        if !op.is_unconditional() {
+            if index + 1 >= code.len() {
+                return Err(FrameError::FallsOffEnd { pc });
+            }
+            successors.push(index + 1);
+        }
+        for successor in successors {
+            if analyzer.merge_into(&mut frames, successor, frame.clone())? {
+                worklist.push(successor);
+            }
+        }
+    }
+
+    Ok(Analysis {
+        initial,
+        pcs,
+        frames,
+        max_stack: max_stack as u16,
+        max_locals: max_locals as u16,
+    })
+}
+
+struct Analyzer<'a> {
+    pool: &'a ConstantPool,
+    hierarchy: &'a dyn ClassHierarchy,
+    this_class: String,
+    code: &'a [Opcode],
+    pcs: &'a [u16],
+}
+
+impl Analyzer<'_> {
+    /// Merges `frame` into the incoming frame of `index` and returns whether it changed.
+    fn merge_into(
+        &self,
+        frames: &mut [Option<Frame>],
+        index: usize,
+        frame: Frame,
+    ) -> Result<bool, FrameError> {
+        let old = match &frames[index] {
+            None => {
+                frames[index] = Some(frame);
+                return Ok(true);
+            }
+            Some(old) => old,
+        };
+        let pc = self.pcs[index];
+        if old.stack.len() != frame.stack.len() {
+            return Err(FrameError::StackMismatch { pc });
+        }
+        let mut stack = vec![];
+        for (a, b) in old.stack.iter().zip(&frame.stack) {
+            match self.merge_type(a, b) {
+                VerificationType::Top => return Err(FrameError::StackMismatch { pc }),
+                t => stack.push(t),
+            }
+        }
+        let len = old.locals.len().max(frame.locals.len());
+        let mut locals = vec![];
+        for i in 0..len {
+            let a = old.locals.get(i).unwrap_or(&VerificationType::Top);
+            let b = frame.locals.get(i).unwrap_or(&VerificationType::Top);
+            locals.push(self.merge_type(a, b));
+        }
+        for i in 0..len.saturating_sub(1) {
+            if locals[i].is_wide() && locals[i + 1] != VerificationType::Top {
+                locals[i] = VerificationType::Top;
+            }
+        }
+        while locals.last() == Some(&VerificationType::Top) {
+            locals.pop();
+        }
+        let merged = Frame { locals, stack };
+        if &merged == old {
+            return Ok(false);
+        }
+        frames[index] = Some(merged);
+        Ok(true)
+    }
+
+    fn merge_type(&self, a: &VerificationType, b: &VerificationType) -> VerificationType {
+        match (a, b) {
+            _ if a == b => a.clone(),
+            (VerificationType::Null, VerificationType::Object(_)) => b.clone(),
+            (VerificationType::Object(_), VerificationType::Null) => a.clone(),
+            (VerificationType::Object(x), VerificationType::Object(y)) => {
+                VerificationType::Object(self.common_super_class(x, y))
+            }
+            _ => VerificationType::Top,
+        }
+    }
+
+    fn common_super_class(&self, a: &str, b: &str) -> String {
+        match (a.strip_prefix('['), b.strip_prefix('[')) {
+            (Some(x), Some(y)) => {
+                let element = |d: &str| {
+                    d.strip_prefix('L')
+                        .and_then(|d| d.strip_suffix(';'))
+                        .map(str::to_string)
+                        .or_else(|| d.starts_with('[').then(|| d.to_string()))
+                };
+                match (element(x), element(y)) {
+                    (Some(x), Some(y)) => {
+                        let common = self.common_super_class(&x, &y);
+                        if common.starts_with('[') {
+                            format!("[{}", common)
+                        } else {
+                            format!("[L{};", common)
+                        }
+                    }
+                    _ => OBJECT_CLASS.to_string(),
+                }
+            }
+            (None, None) => self.hierarchy.common_super_class(a, b),
+            _ => OBJECT_CLASS.to_string(),
+        }
+    }
+
+    fn class_name(&self, pc: u16, index: u16) -> Result<String, FrameError> {
+        self.pool
+            .get_class_name(index)
+            .map(str::to_string)
+            .ok_or(FrameError::InvalidConstant { pc, index })
+    }
+
+    fn member_descriptor(&self, pc: u16, index: u16) -> Result<(String, Descriptor), FrameError> {
+        let (name, descriptor) = match self.pool.get(index).map(|cp| &cp.info) {
+            Some(CpInfoType::InvokeDynamic {
+                name_and_type_index,
+                ..
+            }) => self.pool.get_name_and_type(*name_and_type_index),
+            _ => self
+                .pool
+                .get_member_ref(index)
+                .map(|(_, name, descriptor)| (name, descriptor)),
+        }
+        .ok_or(FrameError::InvalidConstant { pc, index })?;
+        let descriptor = Descriptor::parse(descriptor)
+            .map_err(|_| FrameError::InvalidDescriptor(descriptor.to_string()))?;
+        Ok((name.to_string(), descriptor))
+    }
+
+    fn constant_type(&self, pc: u16, index: u16) -> Result<VerificationType, FrameError> {
+        let object = |name: &str| VerificationType::Object(name.to_string());
+        Ok(match self.pool.get(index).map(|cp| &cp.info) {
+            Some(CpInfoType::Integer { .. }) => VerificationType::Integer,
+            Some(CpInfoType::Float { .. }) => VerificationType::Float,
+            Some(CpInfoType::Long { .. }) => VerificationType::Long,
+            Some(CpInfoType::Double { .. }) => VerificationType::Double,
+            Some(CpInfoType::String { .. }) => object("java/lang/String"),
+            Some(CpInfoType::Class { .. }) => object("java/lang/Class"),
+            Some(CpInfoType::MethodType { .. }) => object("java/lang/invoke/MethodType"),
+            Some(CpInfoType::MethodHandle { .. }) => object("java/lang/invoke/MethodHandle"),
+            Some(CpInfoType::Dynamic {
+                name_and_type_index,
+                ..
+            }) => self
+                .pool
+                .get_name_and_type(*name_and_type_index)
+                .and_then(|(_, descriptor)| Descriptor::parse_field(descriptor).ok())
+                .and_then(|descriptor| VerificationType::from_descriptor(&descriptor))
+                .ok_or(FrameError::InvalidConstant { pc, index })?,
+            _ => return Err(FrameError::InvalidConstant { pc, index }),
+        })
+    }
+
+    /// Applies the effect of the instruction at `index` to `frame`.
+    fn execute(&self, index: usize, frame: &mut Frame) -> Result<(), FrameError> {
+        use VerificationType as T;
+
+        let pc = self.pcs[index];
+        let op = &self.code[index];
+        let pop = |frame: &mut Frame| frame.stack.pop().ok_or(FrameError::StackUnderflow { pc });
+        let pop_n = |frame: &mut Frame, n: usize| -> Result<(), FrameError> {
+            for _ in 0..n {
+                pop(frame)?;
+            }
+            Ok(())
+        };
+        let load = |frame: &mut Frame, i: usize| {
+            let t = frame.locals.get(i).cloned().unwrap_or(T::Top);
+            frame.stack.push(t);
+        };
+        let store = |frame: &mut Frame, i: usize| -> Result<(), FrameError> {
+            let t = pop(frame)?;
+            frame.set_local(i, t);
+            Ok(())
+        };
+        let index_of = |b1: u8, b2: u8| u16::from_be_bytes([b1, b2]);
+
+        match *op {
+            Opcode::Nop | Opcode::Goto(..) | Opcode::GotoW(..) | Opcode::Return_ => {}
+            Opcode::Iinc(i, _) => frame.set_local(i as usize, T::Integer),
+            Opcode::WideIinc(b1, b2, _, _) => {
+                frame.set_local(index_of(b1, b2) as usize, T::Integer)
+            }
+            Opcode::Wide(opcode, b1, b2) => {
+                let i = index_of(b1, b2) as usize;
+                match opcode {
+                    ALOAD => load(frame, i),
+                    ILOAD => frame.stack.push(T::Integer),
+                    LLOAD => frame.stack.push(T::Long),
+                    FLOAD => frame.stack.push(T::Float),
+                    DLOAD => frame.stack.push(T::Double),
+                    ISTORE | LSTORE | FSTORE | DSTORE | ASTORE => store(frame, i)?,
+                    _ => {
+                        return Err(FrameError::Unsupported {
+                            pc,
+                            opcode: op.clone(),
+                        })
+                    }
+                }
+            }
+            Opcode::AconstNull => frame.stack.push(T::Null),
+            Opcode::IconstI(_) | Opcode::Bipush(_) | Opcode::Sipush(..) => {
+                frame.stack.push(T::Integer)
+            }
+            Opcode::LconstL(_) => frame.stack.push(T::Long),
+            Opcode::FconstF(_) => frame.stack.push(T::Float),
+            Opcode::DconstD(_) => frame.stack.push(T::Double),
+            Opcode::Ldc(i) => frame.stack.push(self.constant_type(pc, i as u16)?),
+            Opcode::LdcW(b1, b2) | Opcode::Ldc2W(b1, b2) => {
+                frame.stack.push(self.constant_type(pc, index_of(b1, b2))?)
+            }
+            Opcode::Aload(i) | Opcode::AloadN(i) => load(frame, i as usize),
+            Opcode::Iload(_) | Opcode::IloadN(_) => frame.stack.push(T::Integer),
+            Opcode::Lload(_) | Opcode::LloadN(_) => frame.stack.push(T::Long),
+            Opcode::Fload(_) | Opcode::FloadN(_) => frame.stack.push(T::Float),
+            Opcode::Dload(_) | Opcode::DloadN(_) => frame.stack.push(T::Double),
+            Opcode::Astore(i)
+            | Opcode::AstoreN(i)
+            | Opcode::Istore(i)
+            | Opcode::IstoreN(i)
+            | Opcode::Lstore(i)
+            | Opcode::LstoreN(i)
+            | Opcode::Fstore(i)
+            | Opcode::FstoreN(i)
+            | Opcode::Dstore(i)
+            | Opcode::DstoreN(i) => store(frame, i as usize)?,
+            Opcode::Aaload => {
+                pop(frame)?;
+                let component = match pop(frame)? {
+                    T::Object(array) => match array.strip_prefix('[') {
+                        Some(c) if c.starts_with('[') => T::Object(c.to_string()),
+                        Some(c) => match c.strip_prefix('L').and_then(|c| c.strip_suffix(';')) {
+                            Some(c) => T::Object(c.to_string()),
+                            None => T::Object(OBJECT_CLASS.to_string()),
+                        },
+                        None => T::Object(OBJECT_CLASS.to_string()),
+                    },
+                    _ => T::Null,
+                };
+                frame.stack.push(component);
+            }
+            Opcode::Baload | Opcode::Caload | Opcode::Saload | Opcode::Iaload => {
+                pop_n(frame, 2)?;
+                frame.stack.push(T::Integer);
+            }
+            Opcode::Laload => {
+                pop_n(frame, 2)?;
+                frame.stack.push(T::Long);
+            }
+            Opcode::Faload => {
+                pop_n(frame, 2)?;
+                frame.stack.push(T::Float);
+            }
+            Opcode::Daload => {
+                pop_n(frame, 2)?;
+                frame.stack.push(T::Double);
+            }
+            Opcode::Aastore
+            | Opcode::Bastore
+            | Opcode::Castore
+            | Opcode::Sastore
+            | Opcode::Iastore
+            | Opcode::Lastore
+            | Opcode::Fastore
+            | Opcode::Dastore => pop_n(frame, 3)?,
+            Opcode::Pop => pop_n(frame, 1)?,
+            Opcode::Pop2 => {
+                if !pop(frame)?.is_wide() {
+                    pop(frame)?;
+                }
+            }
+            Opcode::Dup => {
+                let v = pop(frame)?;
+                frame.stack.extend([v.clone(), v]);
+            }
+            Opcode::DupX1 => {
+                let v1 = pop(frame)?;
+                let v2 = pop(frame)?;
+                frame.stack.extend([v1.clone(), v2, v1]);
+            }
+            Opcode::DupX2 => {
+                let v1 = pop(frame)?;
+                let v2 = pop(frame)?;
+                if v2.is_wide() {
+                    frame.stack.extend([v1.clone(), v2, v1]);
+                } else {
+                    let v3 = pop(frame)?;
+                    frame.stack.extend([v1.clone(), v3, v2, v1]);
+                }
+            }
+            Opcode::Dup2 => {
+                let v1 = pop(frame)?;
+                if v1.is_wide() {
+                    frame.stack.extend([v1.clone(), v1]);
+                } else {
+                    let v2 = pop(frame)?;
+                    frame.stack.extend([v2.clone(), v1.clone(), v2, v1]);
+                }
+            }
+            Opcode::Dup2X1 => {
+                let v1 = pop(frame)?;
+                if v1.is_wide() {
+                    let v2 = pop(frame)?;
+                    frame.stack.extend([v1.clone(), v2, v1]);
+                } else {
+                    let v2 = pop(frame)?;
+                    let v3 = pop(frame)?;
+                    frame.stack.extend([v2.clone(), v1.clone(), v3, v2, v1]);
+                }
+            }
+            Opcode::Dup2X2 => {
+                let v1 = pop(frame)?;
+                let v2 = pop(frame)?;
+                match (v1.is_wide(), v2.is_wide()) {
+                    (true, true) => frame.stack.extend([v1.clone(), v2, v1]),
+                    (true, false) => {
+                        let v3 = pop(frame)?;
+                        frame.stack.extend([v1.clone(), v3, v2, v1]);
+                    }
+                    (false, _) => {
+                        let v3 = pop(frame)?;
+                        if v3.is_wide() {
+                            frame.stack.extend([v2.clone(), v1.clone(), v3, v2, v1]);
+                        } else {
+                            let v4 = pop(frame)?;
+                            frame.stack.extend([v2.clone(), v1.clone(), v4, v3, v2, v1]);
+                        }
+                    }
+                }
+            }
+            Opcode::Swap => {
+                let v1 = pop(frame)?;
+                let v2 = pop(frame)?;
+                frame.stack.extend([v1, v2]);
+            }
+            Opcode::Iadd
+            | Opcode::Isub
+            | Opcode::Imul
+            | Opcode::Idiv
+            | Opcode::Irem
+            | Opcode::Ishl
+            | Opcode::Ishr
+            | Opcode::Iushr
+            | Opcode::Iand
+            | Opcode::Ior
+            | Opcode::Ixor
+            | Opcode::Lcmp
+            | Opcode::Fcmpl
+            | Opcode::Fcmpg
+            | Opcode::Dcmpl
+            | Opcode::Dcmpg => {
+                pop_n(frame, 2)?;
+                frame.stack.push(T::Integer);
+            }
+            Opcode::Ladd
+            | Opcode::Lsub
+            | Opcode::Lmul
+            | Opcode::Ldiv
+            | Opcode::Lrem
+            | Opcode::Lshl
+            | Opcode::Lshr
+            | Opcode::Lushr
+            | Opcode::Land
+            | Opcode::Lor
+            | Opcode::Lxor => {
+                pop_n(frame, 2)?;
+                frame.stack.push(T::Long);
+            }
+            Opcode::Fadd | Opcode::Fsub | Opcode::Fmul | Opcode::Fdiv | Opcode::Frem => {
+                pop_n(frame, 2)?;
+                frame.stack.push(T::Float);
+            }
+            Opcode::Dadd | Opcode::Dsub | Opcode::Dmul | Opcode::Ddiv | Opcode::Drem => {
+                pop_n(frame, 2)?;
+                frame.stack.push(T::Double);
+            }
+            Opcode::Ineg
+            | Opcode::L2i
+            | Opcode::F2i
+            | Opcode::D2i
+            | Opcode::I2b
+            | Opcode::I2c
+            | Opcode::I2s
+            | Opcode::Arraylength
+            | Opcode::Instanceof(..) => {
+                pop(frame)?;
+                frame.stack.push(T::Integer);
+            }
+            Opcode::Lneg | Opcode::I2l | Opcode::F2l | Opcode::D2l => {
+                pop(frame)?;
+                frame.stack.push(T::Long);
+            }
+            Opcode::Fneg | Opcode::I2f | Opcode::L2f | Opcode::D2f => {
+                pop(frame)?;
+                frame.stack.push(T::Float);
+            }
+            Opcode::Dneg | Opcode::I2d | Opcode::L2d | Opcode::F2d => {
+                pop(frame)?;
+                frame.stack.push(T::Double);
+            }
+            Opcode::ICOND(..)
+            | Opcode::Lookupswitch(..)
+            | Opcode::Tableswitch(..)
+            | Opcode::Ifnull(..)
+            | Opcode::Ifnonnull(..)
+            | Opcode::Monitorenter
+            | Opcode::Monitorexit
+            | Opcode::Athrow
+            | Opcode::Ireturn
+            | Opcode::Lreturn
+            | Opcode::Freturn
+            | Opcode::Dreturn
+            | Opcode::Areturn => pop_n(frame, 1)?,
+            Opcode::IfIcmCond(..) | Opcode::IfAcmCond(..) => pop_n(frame, 2)?,
+            Opcode::Getstatic(b1, b2) | Opcode::Getfield(b1, b2) => {
+                if matches!(op, Opcode::Getfield(..)) {
+                    pop(frame)?;
+                }
+                let (_, descriptor) = self.member_descriptor(pc, index_of(b1, b2))?;
+                frame.stack.push(T::from_descriptor(&descriptor).ok_or(
+                    FrameError::InvalidConstant {
+                        pc,
+                        index: index_of(b1, b2),
+                    },
+                )?);
+            }
+            Opcode::Putstatic(..) => pop_n(frame, 1)?,
+            Opcode::Putfield(..) => pop_n(frame, 2)?,
+            Opcode::Invokevirtual(b1, b2)
+            | Opcode::Invokespecial(b1, b2)
+            | Opcode::Invokestatic(b1, b2)
+            | Opcode::Invokeinterface(b1, b2, _, _)
+            | Opcode::Invokedynamic(b1, b2, _, _) => {
+                let (name, descriptor) = self.member_descriptor(pc, index_of(b1, b2))?;
+                let (parameters, ret) = match descriptor {
+                    Descriptor::Function(parameters, ret) => (parameters, ret),
+                    _ => {
+                        return Err(FrameError::InvalidConstant {
+                            pc,
+                            index: index_of(b1, b2),
+                        })
+                    }
+                };
+                pop_n(frame, parameters.len())?;
+                if !matches!(op, Opcode::Invokestatic(..) | Opcode::Invokedynamic(..)) {
+                    let receiver = pop(frame)?;
+                    if matches!(op, Opcode::Invokespecial(..)) && name == "<init>" {
+                        let initialized = match &receiver {
+                            T::UninitializedThis => T::Object(self.this_class.clone()),
+                            T::Uninitialized(new_pc) => {
+                                match self
+                                    .pcs
+                                    .iter()
+                                    .position(|p| p == new_pc)
+                                    .map(|i| &self.code[i])
+                                {
+                                    Some(&Opcode::New(n1, n2)) => {
+                                        T::Object(self.class_name(pc, index_of(n1, n2))?)
+                                    }
+                                    _ => return Err(FrameError::StackMismatch { pc }),
+                                }
+                            }
+                            _ => receiver.clone(),
+                        };
+                        for t in frame.locals.iter_mut().chain(frame.stack.iter_mut()) {
+                            if *t == receiver {
+                                *t = initialized.clone();
+                            }
+                        }
+                    }
+                }
+                if let Some(t) = T::from_descriptor(&ret) {
+                    frame.stack.push(t);
+                }
+            }
+            Opcode::New(..) => frame.stack.push(T::Uninitialized(pc)),
+            Opcode::Newarray(atype) => {
+                pop(frame)?;
+                let component = match atype {
+                    4 => "Z",
+                    5 => "C",
+                    6 => "F",
+                    7 => "D",
+                    8 => "B",
+                    9 => "S",
+                    10 => "I",
+                    11 => "J",
+                    _ => {
+                        return Err(FrameError::Unsupported {
+                            pc,
+                            opcode: op.clone(),
+                        })
+                    }
+                };
+                frame.stack.push(T::Object(format!("[{}", component)));
+            }
+            Opcode::Anewarray(b1, b2) => {
+                pop(frame)?;
+                let class = self.class_name(pc, index_of(b1, b2))?;
+                if class.starts_with('[') {
+                    frame.stack.push(T::Object(format!("[{}", class)));
+                } else {
+                    frame.stack.push(T::Object(format!("[L{};", class)));
+                }
+            }
+            Opcode::Multianewarray(b1, b2, dimensions) => {
+                pop_n(frame, dimensions as usize)?;
+                frame
+                    .stack
+                    .push(T::Object(self.class_name(pc, index_of(b1, b2))?));
+            }
+            Opcode::Checkcast(b1, b2) => {
+                pop(frame)?;
+                frame
+                    .stack
+                    .push(T::Object(self.class_name(pc, index_of(b1, b2))?));
+            }
+            Opcode::Jsr(..) | Opcode::JsrW(..) | Opcode::Ret(_) => {
+                return Err(FrameError::Unsupported {
+                    pc,
+                    opcode: op.clone(),
+                })
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{builder::ClassBuilder, hierarchy::SimpleHierarchy};
+
+    fn object(name: &str) -> VerificationType {
+        VerificationType::Object(name.to_string())
+    }
+
+    fn hierarchy() -> SimpleHierarchy {
+        let mut h = SimpleHierarchy::new();
+        h.add_class("java/util/AbstractList", Some(OBJECT_CLASS));
+        h.add_class("java/util/ArrayList", Some("java/util/AbstractList"));
+        h.add_class("java/util/LinkedList", Some("java/util/AbstractList"));
+        h.add_interface("java/util/List");
+        h
+    }
+
+    fn analyzer<'a>(
+        pool: &'a ConstantPool,
+        hierarchy: &'a SimpleHierarchy,
+        pcs: &'a [u16],
+    ) -> Analyzer<'a> {
+        Analyzer {
+            pool,
+            hierarchy,
+            this_class: "T".to_string(),
+            code: &[],
+            pcs,
+        }
+    }
+
+    #[test]
+    fn merges_objects_to_their_common_super_class() {
+        let h = hierarchy();
+        let pool = ConstantPool::new();
+        let a = analyzer(&pool, &h, &[]);
+        let merge = |x: &str, y: &str| a.merge_type(&object(x), &object(y));
+        assert_eq!(
+            merge("java/util/ArrayList", "java/util/LinkedList"),
+            object("java/util/AbstractList")
+        );
+        assert_eq!(
+            merge("[Ljava/util/ArrayList;", "[Ljava/util/LinkedList;"),
+            object("[Ljava/util/AbstractList;")
+        );
+        assert_eq!(
+            merge("[[Ljava/util/ArrayList;", "[[Ljava/util/LinkedList;"),
+            object("[[Ljava/util/AbstractList;")
+        );
+        assert_eq!(merge("[I", "[F"), object(OBJECT_CLASS));
+        assert_eq!(merge("[I", "java/util/ArrayList"), object(OBJECT_CLASS));
+        assert_eq!(
+            merge("java/util/List", "java/util/ArrayList"),
+            object(OBJECT_CLASS)
+        );
+    }
+
+    #[test]
+    fn merges_null_and_primitives() {
+        let h = hierarchy();
+        let pool = ConstantPool::new();
+        let a = analyzer(&pool, &h, &[]);
+        let list = object("java/util/List");
+        assert_eq!(a.merge_type(&VerificationType::Null, &list), list);
+        assert_eq!(a.merge_type(&list, &VerificationType::Null), list);
+        assert_eq!(
+            a.merge_type(&VerificationType::Integer, &VerificationType::Integer),
+            VerificationType::Integer
+        );
+        assert_eq!(
+            a.merge_type(&VerificationType::Integer, &VerificationType::Float),
+            VerificationType::Top
+        );
+        assert_eq!(
+            a.merge_type(&VerificationType::Integer, &list),
+            VerificationType::Top
+        );
+    }
+
+    #[test]
+    fn merging_frames_drops_disagreeing_locals() {
+        let h = hierarchy();
+        let pool = ConstantPool::new();
+        let a = analyzer(&pool, &h, &[0, 5]);
+        let mut frames = vec![None, None];
+        let first = Frame {
+            locals: vec![
+                object("java/util/ArrayList"),
+                VerificationType::Integer,
+                VerificationType::Float,
+            ],
+            stack: vec![],
+        };
+        assert_eq!(a.merge_into(&mut frames, 1, first.clone()), Ok(true));
+        assert_eq!(frames[1], Some(first.clone()));
+        assert_eq!(a.merge_into(&mut frames, 1, first), Ok(false));
+
+        let second = Frame {
+            locals: vec![object("java/util/LinkedList"), VerificationType::Integer],
+            stack: vec![],
+        };
+        assert_eq!(a.merge_into(&mut frames, 1, second), Ok(true));
+        let expected = Frame {
+            locals: vec![object("java/util/AbstractList"), VerificationType::Integer],
+            stack: vec![],
+        };
+        assert_eq!(frames[1], Some(expected));
+    }
+
+    #[test]
+    fn merging_drops_wide_locals_of_different_types() {
+        let h = hierarchy();
+        let pool = ConstantPool::new();
+        let a = analyzer(&pool, &h, &[0]);
+        let mut frames = vec![Some(Frame {
+            locals: vec![
+                VerificationType::Integer,
+                VerificationType::Long,
+                VerificationType::Top,
+            ],
+            stack: vec![],
+        })];
+        let frame = Frame {
+            locals: vec![
+                VerificationType::Integer,
+                VerificationType::Double,
+                VerificationType::Top,
+            ],
+            stack: vec![],
+        };
+        assert_eq!(a.merge_into(&mut frames, 0, frame), Ok(true));
+        let expected = Frame {
+            locals: vec![VerificationType::Integer],
+            stack: vec![],
+        };
+        assert_eq!(frames[0], Some(expected));
+    }
+
+    #[test]
+    fn merging_rejects_incompatible_stacks() {
+        let h = hierarchy();
+        let pool = ConstantPool::new();
+        let a = analyzer(&pool, &h, &[0, 3]);
+        let int_on_stack = Frame {
+            locals: vec![],
+            stack: vec![VerificationType::Integer],
+        };
+        let mut frames = vec![None, Some(int_on_stack)];
+        assert_eq!(
+            a.merge_into(&mut frames, 1, Frame::default()),
+            Err(FrameError::StackMismatch { pc: 3 })
+        );
+        let float_on_stack = Frame {
+            locals: vec![],
+            stack: vec![VerificationType::Float],
+        };
+        assert_eq!(
+            a.merge_into(&mut frames, 1, float_on_stack),
+            Err(FrameError::StackMismatch { pc: 3 })
+        );
+    }
+
+    #[test]
+    fn wide_locals_are_followed_by_top() {
+        let mut frame = Frame::default();
+        frame.set_local(2, VerificationType::Double);
+        assert_eq!(
+            frame.locals,
+            [
+                VerificationType::Top,
+                VerificationType::Top,
+                VerificationType::Double,
+                VerificationType::Top
+            ]
+        );
+        frame.set_local(3, VerificationType::Integer);
+        assert_eq!(frame.locals[2], VerificationType::Top);
+        frame.set_local(0, VerificationType::Long);
+        frame.stack.push(VerificationType::Long);
+        frame.stack.push(VerificationType::Null);
+        assert_eq!(frame.stack_size(), 3);
+    }
+
+    /// Builds `static void run(int)`, which increments its argument if it
+    /// isn't zero.
+    fn class_with_branch() -> ClassFile {
+        let mut builder = ClassBuilder::new();
+        builder.set_class_name("T").unwrap();
+        let object = builder.load_class(OBJECT_CLASS).unwrap();
+        builder.set_super_class(object);
+        let descriptor = Descriptor::Function(vec![Descriptor::Int], Box::new(Descriptor::Void));
+        let mut method = builder
+            .method("run", descriptor, METHOD_ACC_STATIC)
+            .unwrap();
+        let end = method.new_label();
+        method.build_iload_n(0).unwrap(); // 0
+        method.build_ifeq(end); // 1
+        method.build_iinc(0, 1); // 4
+        method.place_label(end).unwrap();
+        method.build_return().unwrap(); // 7
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn analysis_gives_the_frame_of_each_instruction() {
+        let class = class_with_branch();
+        let method = &class.method_info[0];
+        let h = hierarchy();
+        let analysis = analyze(&class, method, &h).unwrap();
+        assert_eq!(analysis.pcs, [0, 1, 4, 7]);
+        assert_eq!((analysis.max_stack, analysis.max_locals), (1, 1));
+        let frame = analysis.frame_at(1).unwrap();
+        assert_eq!(frame.locals, [VerificationType::Integer]);
+        assert_eq!(frame.stack, [VerificationType::Integer]);
+        assert!(analysis.frame_at(7).unwrap().stack.is_empty());
+        assert_eq!(analysis.frame_at(2), None);
+
+        let pool = ConstantPool::from_entries(class.constant_pool.clone()).unwrap();
+        assert_eq!(analyze_with_pool(&class, &pool, method, &h), Ok(analysis));
+    }
+
+    #[test]
+    fn errors_tell_where_inference_failed() {
+        let mut class = class_with_branch();
+        for attribute in &mut class.method_info[0].attributes {
+            if let AttributeInfoKind::Code { code, .. } = &mut attribute.info {
+                code.pop();
+            }
+        }
+        let error = analyze(&class, &class.method_info[0], &hierarchy()).unwrap_err();
+        // The `ifeq` now jumps past the end of the code.
+        assert_eq!(error, FrameError::InvalidBranchTarget { pc: 1, target: 7 });
+        assert_eq!(error.pc(), Some(1));
+        assert_eq!(FrameError::MissingCode.pc(), None);
+    }
+}
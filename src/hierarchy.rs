@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+pub const OBJECT_CLASS: &str = "java/lang/Object";
+
+/// Provides the subtyping information needed to merge object types when
+/// computing stack map frames. Class names are internal names such as
+/// `java/lang/String`.
+pub trait ClassHierarchy {
+    /// Returns the super class of `class`, or `None` if it is unknown or has none.
+    fn super_class(&self, class: &str) -> Option<String>;
+
+    fn is_interface(&self, class: &str) -> bool;
+
+    /// Returns the closest class both `a` and `b` are assignable to. Interfaces
+    /// and unknown classes merge to `java/lang/Object`, as the verifier treats
+    /// interface types like `Object`.
+    fn common_super_class(&self, a: &str, b: &str) -> String {
+        if a == b {
+            return a.to_string();
+        }
+        if self.is_interface(a) || self.is_interface(b) {
+            return OBJECT_CLASS.to_string();
+        }
+        let mut ancestors = vec![a.to_string()];
+        let mut current = a.to_string();
+        while let Some(parent) = self.super_class(&current) {
+            if ancestors.contains(&parent) {
+                break;
+            }
+            ancestors.push(parent.clone());
+            current = parent;
+        }
+        let mut current = b.to_string();
+        let mut seen = vec![];
+        loop {
+            if ancestors.contains(&current) {
+                return current;
+            }
+            seen.push(current.clone());
+            match self.super_class(&current) {
+                Some(parent) if !seen.contains(&parent) => current = parent,
+                _ => return OBJECT_CLASS.to_string(),
+            }
+        }
+    }
+}
+
+/// A `ClassHierarchy` built from explicitly registered classes.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SimpleHierarchy {
+    classes: HashMap<String, (Option<String>, bool)>,
+}
+
+impl SimpleHierarchy {
+    pub fn new() -> Self {
+        SimpleHierarchy::default()
+    }
+
+    pub fn add_class(&mut self, class: &str, super_class: Option<&str>) {
+        self.classes
+            .insert(class.to_string(), (super_class.map(str::to_string), false));
+    }
+
+    pub fn add_interface(&mut self, interface: &str) {
+        self.classes.insert(
+            interface.to_string(),
+            (Some(OBJECT_CLASS.to_string()), true),
+        );
+    }
+}
+
+impl ClassHierarchy for SimpleHierarchy {
+    fn super_class(&self, class: &str) -> Option<String> {
+        self.classes.get(class).and_then(|(s, _)| s.clone())
+    }
+
+    fn is_interface(&self, class: &str) -> bool {
+        self.classes.get(class).is_some_and(|(_, i)| *i)
+    }
+}
@@ -1,5 +1,14 @@
 pub mod builder;
+pub mod constant_pool;
 pub mod bytecode;
+pub mod cfg;
 pub mod descriptor;
+pub mod flags;
+pub mod frame;
+pub mod hierarchy;
+pub mod mutf8;
+pub mod name;
 pub mod parser;
 pub mod reader;
+pub mod signature;
+pub mod stackmap;
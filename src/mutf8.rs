@@ -0,0 +1,101 @@
+/// Encodes `string` in the modified UTF-8 of `CONSTANT_Utf8` entries (JVMS
+/// 4.4.7), where `\0` takes two bytes so that no byte is zero, and characters
+/// outside the Basic Multilingual Plane are encoded as their two UTF-16
+/// surrogates, on three bytes each.
+pub fn encode(string: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(string.len());
+    for unit in string.encode_utf16() {
+        match unit {
+            0x0001..=0x007f => bytes.push(unit as u8),
+            0x0000 | 0x0080..=0x07ff => {
+                bytes.push(0xc0 | (unit >> 6) as u8);
+                bytes.push(0x80 | (unit & 0x3f) as u8);
+            }
+            _ => {
+                bytes.push(0xe0 | (unit >> 12) as u8);
+                bytes.push(0x80 | ((unit >> 6) & 0x3f) as u8);
+                bytes.push(0x80 | (unit & 0x3f) as u8);
+            }
+        }
+    }
+    bytes
+}
+
+/// Returns the length of `string` encoded in modified UTF-8.
+pub fn encoded_len(string: &str) -> usize {
+    string
+        .encode_utf16()
+        .map(|unit| match unit {
+            0x0001..=0x007f => 1,
+            0x0000 | 0x0080..=0x07ff => 2,
+            _ => 3,
+        })
+        .sum()
+}
+
+/// Decodes modified UTF-8 `bytes`. Malformed bytes and unpaired surrogates,
+/// which a Rust string can't hold, are replaced by U+FFFD.
+pub fn decode(bytes: &[u8]) -> String {
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let continuation = |offset: usize| {
+            bytes
+                .get(i + offset)
+                .filter(|byte| *byte & 0xc0 == 0x80)
+                .map(|byte| (byte & 0x3f) as u16)
+        };
+        let byte = bytes[i];
+        let (unit, size) = match byte {
+            0x01..=0x7f => (Some(byte as u16), 1),
+            0xc0..=0xdf => match continuation(1) {
+                Some(low) => (Some(((byte & 0x1f) as u16) << 6 | low), 2),
+                None => (None, 1),
+            },
+            0xe0..=0xef => match (continuation(1), continuation(2)) {
+                (Some(middle), Some(low)) => {
+                    (Some(((byte & 0x0f) as u16) << 12 | middle << 6 | low), 3)
+                }
+                _ => (None, 1),
+            },
+            _ => (None, 1),
+        };
+        units.push(unit.unwrap_or(char::REPLACEMENT_CHARACTER as u16));
+        i += size;
+    }
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_nul_on_two_bytes() {
+        assert_eq!(encode("a\0b"), [b'a', 0xc0, 0x80, b'b']);
+        assert_eq!(decode(&[b'a', 0xc0, 0x80, b'b']), "a\0b");
+    }
+
+    #[test]
+    fn encodes_supplementary_characters_as_surrogate_pairs() {
+        // U+1F600 is the surrogate pair D83D DE00.
+        let bytes = [0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80];
+        assert_eq!(encode("\u{1f600}"), bytes);
+        assert_eq!(decode(&bytes), "\u{1f600}");
+        assert_eq!(encoded_len("\u{1f600}"), 6);
+    }
+
+    #[test]
+    fn round_trips_like_utf8_elsewhere() {
+        let string = "Ljava/lang/String; é € 中";
+        assert_eq!(encode(string), string.as_bytes());
+        assert_eq!(decode(string.as_bytes()), string);
+        assert_eq!(encoded_len(string), string.len());
+    }
+
+    #[test]
+    fn replaces_malformed_bytes() {
+        assert_eq!(decode(&[b'a', 0x00, 0xc3]), "a\u{fffd}\u{fffd}");
+        assert_eq!(decode(&[0xed, 0xa0, 0xbd]), "\u{fffd}");
+    }
+}
@@ -0,0 +1,344 @@
+use std::fmt;
+
+use crate::descriptor::{binary_to_internal_name, internal_to_binary_name, Descriptor};
+
+/// An error found while validating a class, package or member name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NameError {
+    Empty,
+    /// `character` isn't allowed in `name`, such as `.` in a class name.
+    InvalidCharacter {
+        name: String,
+        character: char,
+    },
+    /// A class or package name with an empty component, such as `java//Object`.
+    EmptyComponent(String),
+    /// A name starting with `[` that isn't an array descriptor.
+    InvalidArray(String),
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameError::Empty => write!(f, "empty name"),
+            NameError::InvalidCharacter { name, character } => {
+                write!(f, "invalid character {:?} in name {:?}", character, name)
+            }
+            NameError::EmptyComponent(name) => {
+                write!(f, "empty package or class name in {:?}", name)
+            }
+            NameError::InvalidArray(name) => write!(f, "invalid array class name {:?}", name),
+        }
+    }
+}
+
+impl std::error::Error for NameError {}
+
+/// Checks an unqualified name, which can't contain `.`, `;`, `[` or `/`.
+fn check_unqualified(name: &str) -> Result<(), NameError> {
+    if name.is_empty() {
+        return Err(NameError::Empty);
+    }
+    match name.chars().find(|c| matches!(c, '.' | ';' | '[' | '/')) {
+        Some(character) => Err(NameError::InvalidCharacter {
+            name: name.to_string(),
+            character,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Checks unqualified names separated by `/`, such as `java/lang/Object`.
+fn check_qualified(name: &str) -> Result<(), NameError> {
+    if name.is_empty() {
+        return Err(NameError::Empty);
+    }
+    for part in name.split('/') {
+        match check_unqualified(part) {
+            Err(NameError::Empty) => return Err(NameError::EmptyComponent(name.to_string())),
+            Err(NameError::InvalidCharacter { character, .. }) => {
+                return Err(NameError::InvalidCharacter {
+                    name: name.to_string(),
+                    character,
+                })
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+macro_rules! name_type {
+    ($name:ident) => {
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl PartialEq<str> for $name {
+            fn eq(&self, other: &str) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<&str> for $name {
+            fn eq(&self, other: &&str) -> bool {
+                self.0 == *other
+            }
+        }
+
+        impl TryFrom<&str> for $name {
+            type Error = NameError;
+
+            fn try_from(name: &str) -> Result<Self, NameError> {
+                $name::new(name)
+            }
+        }
+
+        impl TryFrom<&String> for $name {
+            type Error = NameError;
+
+            fn try_from(name: &String) -> Result<Self, NameError> {
+                $name::new(name)
+            }
+        }
+
+        impl TryFrom<String> for $name {
+            type Error = NameError;
+
+            fn try_from(name: String) -> Result<Self, NameError> {
+                $name::new(&name)
+            }
+        }
+
+        impl From<&$name> for $name {
+            fn from(name: &$name) -> Self {
+                name.clone()
+            }
+        }
+    };
+}
+
+/// The name of a class or interface in internal form, such as
+/// `java/util/Map$Entry`, or of an array class, such as `[Ljava/lang/String;`,
+/// as found in `CONSTANT_Class` entries.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClassName(String);
+
+name_type!(ClassName);
+
+impl ClassName {
+    pub fn new(name: &str) -> Result<ClassName, NameError> {
+        if name.starts_with('[') {
+            return match Descriptor::parse_field(name) {
+                Ok(Descriptor::Array(_)) => Ok(ClassName(name.to_string())),
+                _ => Err(NameError::InvalidArray(name.to_string())),
+            };
+        }
+        check_qualified(name)?;
+        Ok(ClassName(name.to_string()))
+    }
+
+    /// Validates a binary name as returned by `Class.getName()`, such as
+    /// `java.util.Map$Entry`.
+    pub fn from_binary_name(name: &str) -> Result<ClassName, NameError> {
+        ClassName::new(&binary_to_internal_name(name))
+    }
+
+    /// Returns the binary name of the class, such as `java.util.Map$Entry`.
+    pub fn binary_name(&self) -> String {
+        internal_to_binary_name(&self.0)
+    }
+
+    pub fn is_array(&self) -> bool {
+        self.0.starts_with('[')
+    }
+
+    /// Returns the package of the class, or `None` for the unnamed package
+    /// and array classes.
+    pub fn package(&self) -> Option<PackageName> {
+        if self.is_array() {
+            return None;
+        }
+        let (package, _) = self.0.rsplit_once('/')?;
+        Some(PackageName(package.to_string()))
+    }
+
+    /// Returns the name of the class without its package, such as
+    /// `Map$Entry`.
+    pub fn simple_name(&self) -> &str {
+        match self.0.rsplit_once('/') {
+            Some((_, name)) if !self.is_array() => name,
+            _ => &self.0,
+        }
+    }
+}
+
+/// The name of a package in internal form, such as `java/util`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PackageName(String);
+
+name_type!(PackageName);
+
+impl PackageName {
+    pub fn new(name: &str) -> Result<PackageName, NameError> {
+        check_qualified(name)?;
+        Ok(PackageName(name.to_string()))
+    }
+}
+
+/// The name of a method. Besides unqualified names, which can't contain `.`,
+/// `;`, `[`, `/`, `<` or `>`, it can be `<init>` or `<clinit>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MethodName(String);
+
+name_type!(MethodName);
+
+impl MethodName {
+    pub fn new(name: &str) -> Result<MethodName, NameError> {
+        if name == "<init>" || name == "<clinit>" {
+            return Ok(MethodName(name.to_string()));
+        }
+        check_unqualified(name)?;
+        match name.chars().find(|c| matches!(c, '<' | '>')) {
+            Some(character) => Err(NameError::InvalidCharacter {
+                name: name.to_string(),
+                character,
+            }),
+            None => Ok(MethodName(name.to_string())),
+        }
+    }
+
+    /// Returns `<init>`, the name of constructors.
+    pub fn constructor() -> MethodName {
+        MethodName("<init>".to_string())
+    }
+
+    /// Returns `<clinit>`, the name of the static initializer.
+    pub fn static_initializer() -> MethodName {
+        MethodName("<clinit>".to_string())
+    }
+
+    pub fn is_constructor(&self) -> bool {
+        self.0 == "<init>"
+    }
+
+    pub fn is_static_initializer(&self) -> bool {
+        self.0 == "<clinit>"
+    }
+}
+
+/// The name of a field, an unqualified name which can't contain `.`, `;`,
+/// `[` or `/`. The method names `<init>` and `<clinit>` aren't allowed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FieldName(String);
+
+name_type!(FieldName);
+
+impl FieldName {
+    pub fn new(name: &str) -> Result<FieldName, NameError> {
+        check_unqualified(name)?;
+        if name == "<init>" || name == "<clinit>" {
+            return Err(NameError::InvalidCharacter {
+                name: name.to_string(),
+                character: '<',
+            });
+        }
+        Ok(FieldName(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid_character(name: &str, character: char) -> NameError {
+        NameError::InvalidCharacter {
+            name: name.to_string(),
+            character,
+        }
+    }
+
+    #[test]
+    fn special_method_names_are_only_method_names() {
+        for name in ["<init>", "<clinit>"] {
+            assert_eq!(MethodName::new(name).unwrap(), name);
+            assert_eq!(FieldName::new(name), Err(invalid_character(name, '<')));
+        }
+        assert!(MethodName::new("<init>").unwrap().is_constructor());
+        assert!(MethodName::static_initializer().is_static_initializer());
+        assert_eq!(
+            MethodName::new("<main>"),
+            Err(invalid_character("<main>", '<'))
+        );
+        assert_eq!(MethodName::new("get>"), Err(invalid_character("get>", '>')));
+        assert_eq!(MethodName::new("a.b"), Err(invalid_character("a.b", '.')));
+        assert_eq!(FieldName::new(""), Err(NameError::Empty));
+        assert_eq!(FieldName::new("a;"), Err(invalid_character("a;", ';')));
+        assert_eq!(FieldName::new("value").unwrap(), "value");
+    }
+
+    #[test]
+    fn class_names_are_internal_names() {
+        let name = ClassName::new("java/lang/String").unwrap();
+        assert_eq!(name.binary_name(), "java.lang.String");
+        assert_eq!(
+            ClassName::new("java.lang.String"),
+            Err(invalid_character("java.lang.String", '.'))
+        );
+        assert_eq!(
+            ClassName::from_binary_name("java.util.Map$Entry").unwrap(),
+            "java/util/Map$Entry"
+        );
+        for name in ["java//Object", "/Object", "java/"] {
+            assert_eq!(
+                ClassName::new(name),
+                Err(NameError::EmptyComponent(name.to_string()))
+            );
+        }
+        assert_eq!(ClassName::new(""), Err(NameError::Empty));
+    }
+
+    #[test]
+    fn array_class_names_are_array_descriptors() {
+        for name in ["[I", "[Ljava/lang/String;", "[[D"] {
+            let class = ClassName::new(name).unwrap();
+            assert!(class.is_array());
+            assert_eq!(class.package(), None);
+            assert_eq!(class.simple_name(), name);
+        }
+        for name in ["[", "[V", "[Ljava/lang/String", "[java/lang/String"] {
+            assert_eq!(
+                ClassName::new(name),
+                Err(NameError::InvalidArray(name.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn packages_are_derived_from_class_names() {
+        let name = ClassName::new("java/util/Map$Entry").unwrap();
+        assert_eq!(name.package(), Some(PackageName::new("java/util").unwrap()));
+        assert_eq!(name.simple_name(), "Map$Entry");
+        let name = ClassName::new("Main").unwrap();
+        assert_eq!(name.package(), None);
+        assert_eq!(name.simple_name(), "Main");
+        assert_eq!(
+            PackageName::new("java//util"),
+            Err(NameError::EmptyComponent("java//util".to_string()))
+        );
+    }
+}
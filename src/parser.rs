@@ -1,8 +1,11 @@
 use crate::{
     bytecode::{
-        instr::Opcode, AttributeInfo, AttributeInfoKind, ClassFile, CpInfo, CpInfoType,
-        ExceptionTable, FieldInfo, LineNumberTable, MethodInfo,
+        instr::Opcode, Annotation, AttributeInfo, AttributeInfoKind, BootstrapMethod, ClassFile,
+        CpInfo, CpInfoType, ElementValue, ElementValueKind, ElementValuePair, ExceptionTable,
+        FieldInfo, InnerClass, LineNumberTable, MethodInfo, ParameterAnnotation, RecordComponent,
+        StackMapFrame, VerificationTypeInfo,
     },
+    flags::Flags,
     reader::Reader,
 };
 
@@ -19,7 +22,10 @@ pub const CONSTANT_NAMEANDTYPE: u8 = 12;
 pub const CONSTANT_UTF8: u8 = 1;
 pub const CONSTANT_METHODHANDLE: u8 = 15;
 pub const CONSTANT_METHODTYPE: u8 = 16;
+pub const CONSTANT_DYNAMIC: u8 = 17;
 pub const CONSTANT_INVOKEDYNAMIC: u8 = 18;
+pub const CONSTANT_MODULE: u8 = 19;
+pub const CONSTANT_PACKAGE: u8 = 20;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Parser {
@@ -38,7 +44,10 @@ impl Parser {
         let magic = self.reader.read_int4();
         let minor_version = self.reader.read_int2();
         let major_version = self.reader.read_int2();
-        let constant_pool_count = self.reader.read_int2();
+        // The count read from the file is one more than the number of slots,
+        // which is what `ClassFile::constant_pool_count` holds. A malformed
+        // count of 0 is read as an empty pool.
+        let constant_pool_count = self.reader.read_int2().saturating_sub(1);
         let mut class_file = ClassFile {
             magic,
             minor_version,
@@ -46,8 +55,15 @@ impl Parser {
             constant_pool_count,
             ..Default::default()
         };
-        for _ in 0..constant_pool_count - 1 {
+        let mut slot = 0;
+        while slot < constant_pool_count {
             let tag = self.reader.read_int1();
+            // `Long` and `Double` entries take two slots.
+            slot += if tag == CONSTANT_LONG || tag == CONSTANT_DOUBLE {
+                2
+            } else {
+                1
+            };
             match tag {
                 CONSTANT_CLASS => class_file.constant_pool.push(CpInfo {
                     tag,
@@ -62,6 +78,13 @@ impl Parser {
                         name_and_type_index: self.reader.read_int2(),
                     },
                 }),
+                CONSTANT_INTERFACEMETHODREF => class_file.constant_pool.push(CpInfo {
+                    tag,
+                    info: CpInfoType::InterfaceMethodref {
+                        class_index: self.reader.read_int2(),
+                        name_and_type_index: self.reader.read_int2(),
+                    },
+                }),
                 CONSTANT_FIELDREF => class_file.constant_pool.push(CpInfo {
                     tag,
                     info: CpInfoType::Fieldref {
@@ -98,19 +121,78 @@ impl Parser {
                         bytes: self.reader.read_int4(),
                     },
                 }),
-                e => todo!("{:?} {}", class_file, e),
+                CONSTANT_FLOAT => class_file.constant_pool.push(CpInfo {
+                    tag,
+                    info: CpInfoType::Float {
+                        bytes: f32::from_bits(self.reader.read_int4()),
+                    },
+                }),
+                CONSTANT_LONG => class_file.constant_pool.push(CpInfo {
+                    tag,
+                    info: CpInfoType::Long {
+                        high_bytes: self.reader.read_int4(),
+                        low_bytes: self.reader.read_int4(),
+                    },
+                }),
+                CONSTANT_DOUBLE => class_file.constant_pool.push(CpInfo {
+                    tag,
+                    info: CpInfoType::Double {
+                        high_bytes: self.reader.read_int4(),
+                        low_bytes: self.reader.read_int4(),
+                    },
+                }),
+                CONSTANT_METHODHANDLE => class_file.constant_pool.push(CpInfo {
+                    tag,
+                    info: CpInfoType::MethodHandle {
+                        reference_kind: self.reader.read_int1(),
+                        reference_index: self.reader.read_int2(),
+                    },
+                }),
+                CONSTANT_METHODTYPE => class_file.constant_pool.push(CpInfo {
+                    tag,
+                    info: CpInfoType::MethodType {
+                        descriptor_index: self.reader.read_int2(),
+                    },
+                }),
+                CONSTANT_INVOKEDYNAMIC => class_file.constant_pool.push(CpInfo {
+                    tag,
+                    info: CpInfoType::InvokeDynamic {
+                        bootstrap_method_attr_index: self.reader.read_int2(),
+                        name_and_type_index: self.reader.read_int2(),
+                    },
+                }),
+                CONSTANT_DYNAMIC => class_file.constant_pool.push(CpInfo {
+                    tag,
+                    info: CpInfoType::Dynamic {
+                        bootstrap_method_attr_index: self.reader.read_int2(),
+                        name_and_type_index: self.reader.read_int2(),
+                    },
+                }),
+                CONSTANT_MODULE => class_file.constant_pool.push(CpInfo {
+                    tag,
+                    info: CpInfoType::Module {
+                        name_index: self.reader.read_int2(),
+                    },
+                }),
+                CONSTANT_PACKAGE => class_file.constant_pool.push(CpInfo {
+                    tag,
+                    info: CpInfoType::Package {
+                        name_index: self.reader.read_int2(),
+                    },
+                }),
+                e => panic!("Invalid constant pool tag {}", e),
             }
         }
-        class_file.access_flags = self.reader.read_int2();
+        class_file.access_flags = Flags::from_bits(self.reader.read_int2());
         class_file.this_class = self.reader.read_int2();
         class_file.super_class = self.reader.read_int2();
         class_file.interfaces_count = self.reader.read_int2();
-        for _ in 1..class_file.interfaces_count {
+        for _ in 0..class_file.interfaces_count {
             class_file.interfaces.push(self.reader.read_int2());
         }
         class_file.fields_count = self.reader.read_int2();
         for _ in 0..class_file.fields_count {
-            let access_flags = self.reader.read_int2();
+            let access_flags = Flags::from_bits(self.reader.read_int2());
             let name_index = self.reader.read_int2();
             let descriptor_index = self.reader.read_int2();
             let attributes_count = self.reader.read_int2();
@@ -125,7 +207,7 @@ impl Parser {
         }
         class_file.method_count = self.reader.read_int2();
         for _ in 0..class_file.method_count {
-            let access_flags = self.reader.read_int2();
+            let access_flags = Flags::from_bits(self.reader.read_int2());
             let name_index = self.reader.read_int2();
             let descriptor_index = self.reader.read_int2();
             let attributes_count = self.reader.read_int2();
@@ -150,12 +232,8 @@ impl Parser {
             let attribute_name_index = self.reader.read_int2();
             let attribute_length = self.reader.read_int4();
             let attribute;
-            if let CpInfoType::Utf8 { bytes, .. } = class.constant_pool
-                [attribute_name_index as usize - 1]
-                .clone()
-                .info
-            {
-                match bytes.as_str() {
+            if let Some(name) = class.get_utf8(attribute_name_index) {
+                match name {
                     "ConstantValue" => {
                         attribute = AttributeInfoKind::ConstantValue {
                             constantvalue_index: self.reader.read_int2(),
@@ -195,9 +273,26 @@ impl Parser {
                         };
                     }
                     "StackMapTable" => {
-                        todo!()
+                        let number_of_entries = self.reader.read_int2();
+                        let mut entries = vec![];
+                        for _ in 0..number_of_entries {
+                            entries.push(self.parse_stack_map_frame());
+                        }
+                        attribute = AttributeInfoKind::StackMapTable {
+                            number_of_entries,
+                            entries,
+                        };
+                    }
+                    "Exceptions" => {
+                        let number_of_exceptions = self.reader.read_int2();
+                        let exception_index_table = (0..number_of_exceptions)
+                            .map(|_| self.reader.read_int2())
+                            .collect();
+                        attribute = AttributeInfoKind::Exceptions {
+                            number_of_exceptions,
+                            exception_index_table,
+                        };
                     }
-                    "Exceptions" => todo!(),
                     "LineNumberTable" => {
                         let line_number_table_length = self.reader.read_int2();
                         let mut line_number_table = vec![];
@@ -217,7 +312,123 @@ impl Parser {
                             sourcefile_index: self.reader.read_int2(),
                         };
                     }
-                    e => todo!("{}", e),
+                    "Signature" => {
+                        attribute = AttributeInfoKind::Signature {
+                            signature_index: self.reader.read_int2(),
+                        };
+                    }
+                    "Deprecated" => {
+                        attribute = AttributeInfoKind::Deprecated;
+                    }
+                    "InnerClasses" => {
+                        let number_of_classes = self.reader.read_int2();
+                        let mut classes = vec![];
+                        for _ in 0..number_of_classes {
+                            classes.push(InnerClass {
+                                inner_class_info_index: self.reader.read_int2(),
+                                outer_class_info_index: self.reader.read_int2(),
+                                inner_name_index: self.reader.read_int2(),
+                                inner_class_access_flags: Flags::from_bits(self.reader.read_int2()),
+                            });
+                        }
+                        attribute = AttributeInfoKind::InnerClasses {
+                            number_of_classes,
+                            classes,
+                        };
+                    }
+                    "BootstrapMethods" => {
+                        let num_bootstrap_methods = self.reader.read_int2();
+                        let mut bootstrap_methods = vec![];
+                        for _ in 0..num_bootstrap_methods {
+                            let bootstrap_method_ref = self.reader.read_int2();
+                            let num_bootstrap_arguments = self.reader.read_int2();
+                            let bootstrap_arguments = (0..num_bootstrap_arguments)
+                                .map(|_| self.reader.read_int2())
+                                .collect();
+                            bootstrap_methods.push(BootstrapMethod {
+                                bootstrap_method_ref,
+                                num_bootstrap_arguments,
+                                bootstrap_arguments,
+                            });
+                        }
+                        attribute = AttributeInfoKind::BootstrapMethods {
+                            num_bootstrap_methods,
+                            bootstrap_methods,
+                        };
+                    }
+                    "Record" => {
+                        let components_count = self.reader.read_int2();
+                        let mut components = vec![];
+                        for _ in 0..components_count {
+                            let name_index = self.reader.read_int2();
+                            let descriptor_index = self.reader.read_int2();
+                            let attributes_count = self.reader.read_int2();
+                            let attributes = self.parse_attribute(class, attributes_count);
+                            components.push(RecordComponent {
+                                name_index,
+                                descriptor_index,
+                                attributes_count,
+                                attributes,
+                            });
+                        }
+                        attribute = AttributeInfoKind::Record {
+                            components_count,
+                            components,
+                        };
+                    }
+                    "RuntimeVisibleAnnotations" | "RuntimeInvisibleAnnotations" => {
+                        let num_annotations = self.reader.read_int2();
+                        let annotations = (0..num_annotations)
+                            .map(|_| self.parse_annotation())
+                            .collect();
+                        attribute = if name == "RuntimeVisibleAnnotations" {
+                            AttributeInfoKind::RuntimeVisibleAnnotations {
+                                num_annotations,
+                                annotations,
+                            }
+                        } else {
+                            AttributeInfoKind::RuntimeInvisibleAnnotations {
+                                num_annotations,
+                                annotations,
+                            }
+                        };
+                    }
+                    "RuntimeVisibleParameterAnnotations"
+                    | "RuntimeInvisibleParameterAnnotations" => {
+                        let num_parameters = self.reader.read_int1();
+                        let mut parameter_annotations = vec![];
+                        for _ in 0..num_parameters {
+                            let num_annotations = self.reader.read_int2();
+                            let annotations = (0..num_annotations)
+                                .map(|_| self.parse_annotation())
+                                .collect();
+                            parameter_annotations.push(ParameterAnnotation {
+                                num_annotations,
+                                annotations,
+                            });
+                        }
+                        attribute = if name == "RuntimeVisibleParameterAnnotations" {
+                            AttributeInfoKind::RuntimeVisibleParameterAnnotations {
+                                num_parameters,
+                                parameter_annotations,
+                            }
+                        } else {
+                            AttributeInfoKind::RuntimeInvisibleParameterAnnotations {
+                                num_parameters,
+                                parameter_annotations,
+                            }
+                        };
+                    }
+                    "AnnotationDefault" => {
+                        attribute = AttributeInfoKind::AnnotationDefault {
+                            default_value: self.parse_element_value(),
+                        };
+                    }
+                    _ => {
+                        attribute = AttributeInfoKind::Custom {
+                            info: self.reader.read_bytes(attribute_length as usize),
+                        };
+                    }
                 }
             } else {
                 panic!("Expected Utf8")
@@ -231,4 +442,253 @@ impl Parser {
 
         attributes
     }
+
+    fn parse_annotation(&mut self) -> Annotation {
+        let type_index = self.reader.read_int2();
+        let num_element_value_pairs = self.reader.read_int2();
+        let element_value_pairs = (0..num_element_value_pairs)
+            .map(|_| ElementValuePair {
+                element_name_index: self.reader.read_int2(),
+                value: self.parse_element_value(),
+            })
+            .collect();
+        Annotation {
+            type_index,
+            num_element_value_pairs,
+            element_value_pairs,
+        }
+    }
+
+    fn parse_element_value(&mut self) -> ElementValue {
+        let tag = self.reader.read_int1();
+        let value = match tag {
+            b'e' => ElementValueKind::EnumConstValue {
+                type_name_index: self.reader.read_int2(),
+                const_name_index: self.reader.read_int2(),
+            },
+            b'c' => ElementValueKind::ClassInfoIndex(self.reader.read_int2()),
+            b'@' => ElementValueKind::AnnotationValue(self.parse_annotation()),
+            b'[' => {
+                let num_values = self.reader.read_int2();
+                let values = (0..num_values)
+                    .map(|_| self.parse_element_value())
+                    .collect();
+                ElementValueKind::ArrayValue { num_values, values }
+            }
+            _ => ElementValueKind::ConstValueIndex(self.reader.read_int2()),
+        };
+        ElementValue { tag, value }
+    }
+
+    fn parse_stack_map_frame(&mut self) -> StackMapFrame {
+        let frame_type = self.reader.read_int1();
+        match frame_type {
+            0..=63 => StackMapFrame::SameFrame { frame_type },
+            64..=127 => StackMapFrame::SameLocals1StackItemFrame {
+                frame_type,
+                stack: self.parse_verification_type(),
+            },
+            247 => StackMapFrame::SameLocals1StackItemFrameExtended {
+                frame_type,
+                offset_delta: self.reader.read_int2(),
+                stack: self.parse_verification_type(),
+            },
+            248..=250 => StackMapFrame::ChopFrame {
+                frame_type,
+                offset_delta: self.reader.read_int2(),
+            },
+            251 => StackMapFrame::SameFrameExtended {
+                frame_type,
+                offset_delta: self.reader.read_int2(),
+            },
+            252..=254 => {
+                let offset_delta = self.reader.read_int2();
+                let locals = (0..frame_type - 251)
+                    .map(|_| self.parse_verification_type())
+                    .collect();
+                StackMapFrame::AppendFrame {
+                    frame_type,
+                    offset_delta,
+                    locals,
+                }
+            }
+            255 => {
+                let offset_delta = self.reader.read_int2();
+                let number_of_locals = self.reader.read_int2();
+                let locals = (0..number_of_locals)
+                    .map(|_| self.parse_verification_type())
+                    .collect();
+                let number_of_stack_items = self.reader.read_int2();
+                let stack = (0..number_of_stack_items)
+                    .map(|_| self.parse_verification_type())
+                    .collect();
+                StackMapFrame::FullFrame {
+                    frame_type,
+                    offset_delta,
+                    locals,
+                    stack,
+                }
+            }
+            e => panic!("Invalid stack map frame type {}", e),
+        }
+    }
+
+    fn parse_verification_type(&mut self) -> VerificationTypeInfo {
+        match self.reader.read_int1() {
+            0 => VerificationTypeInfo::Top,
+            1 => VerificationTypeInfo::Integer,
+            2 => VerificationTypeInfo::Float,
+            3 => VerificationTypeInfo::Double,
+            4 => VerificationTypeInfo::Long,
+            5 => VerificationTypeInfo::Null,
+            6 => VerificationTypeInfo::UninitializedThis,
+            7 => VerificationTypeInfo::Object {
+                cpool_index: self.reader.read_int2(),
+            },
+            8 => VerificationTypeInfo::Uninitialized {
+                offset: self.reader.read_int2(),
+            },
+            e => panic!("Invalid verification type {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::{ToJvmBytecode, JVM_MAGIC, MODULE};
+
+    fn parse(bytes: Vec<u8>) -> ClassFile {
+        Parser {
+            reader: Reader::new(bytes),
+        }
+        .parse()
+    }
+
+    fn utf8(string: &str) -> CpInfo {
+        CpInfo {
+            tag: CONSTANT_UTF8,
+            info: CpInfoType::Utf8 {
+                length: crate::mutf8::encoded_len(string) as u16,
+                bytes: string.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn parses_module_package_and_dynamic_constants() {
+        let constant_pool = vec![
+            utf8("module-info"),
+            CpInfo {
+                tag: CONSTANT_CLASS,
+                info: CpInfoType::Class { name_index: 1 },
+            },
+            utf8("m"),
+            CpInfo {
+                tag: CONSTANT_MODULE,
+                info: CpInfoType::Module { name_index: 3 },
+            },
+            utf8("m/p"),
+            CpInfo {
+                tag: CONSTANT_PACKAGE,
+                info: CpInfoType::Package { name_index: 5 },
+            },
+            utf8("I"),
+            CpInfo {
+                tag: CONSTANT_NAMEANDTYPE,
+                info: CpInfoType::NameAndType {
+                    name_index: 3,
+                    descriptor_index: 7,
+                },
+            },
+            CpInfo {
+                tag: CONSTANT_DYNAMIC,
+                info: CpInfoType::Dynamic {
+                    bootstrap_method_attr_index: 0,
+                    name_and_type_index: 8,
+                },
+            },
+        ];
+        let class_file = ClassFile {
+            magic: JVM_MAGIC,
+            major_version: 61,
+            constant_pool_count: constant_pool.len() as u16,
+            constant_pool,
+            access_flags: MODULE,
+            this_class: 2,
+            ..Default::default()
+        };
+        assert_eq!(parse(class_file.to_jvm_bytecode()), class_file);
+    }
+
+    #[test]
+    fn utf8_constants_use_modified_utf8() {
+        let string = "a\0\u{1f600}";
+        let mut pool = crate::constant_pool::ConstantPool::new();
+        let index = pool.utf8(string).unwrap();
+        let constant_pool = pool.into_entries();
+        assert_eq!(constant_pool[index as usize - 1], utf8(string));
+        let class_file = ClassFile {
+            magic: JVM_MAGIC,
+            major_version: 61,
+            constant_pool_count: constant_pool.len() as u16,
+            constant_pool,
+            ..Default::default()
+        };
+        let bytes = class_file.to_jvm_bytecode();
+        let encoded = [0, 9, b'a', 0xc0, 0x80, 0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80];
+        assert!(bytes.windows(encoded.len()).any(|w| w == encoded));
+        assert_eq!(parse(bytes), class_file);
+    }
+
+    fn utf8_class_file(string: &str, length: u16) -> ClassFile {
+        ClassFile {
+            magic: JVM_MAGIC,
+            major_version: 61,
+            constant_pool_count: 1,
+            constant_pool: vec![CpInfo {
+                tag: CONSTANT_UTF8,
+                info: CpInfoType::Utf8 {
+                    length,
+                    bytes: string.to_string(),
+                },
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn utf8_lengths_are_written_from_the_encoding() {
+        // A length counted in UTF-8 bytes, where `\0` takes one byte.
+        let class_file = utf8_class_file("a\0b", 3);
+        let bytes = class_file.to_bytes().unwrap();
+        let encoded = [0, 4, b'a', 0xc0, 0x80, b'b'];
+        assert!(bytes.windows(encoded.len()).any(|w| w == encoded));
+        assert_eq!(parse(bytes), utf8_class_file("a\0b", 4));
+    }
+
+    #[test]
+    fn utf8_constants_longer_than_their_length_are_errors() {
+        let class_file = utf8_class_file(&"\0".repeat(40000), 0);
+        assert_eq!(
+            class_file.to_bytes(),
+            Err(crate::constant_pool::ConstantPoolError::StringTooLong(
+                80000
+            ))
+        );
+        let path = std::env::temp_dir().join("classer_too_long.class");
+        let error = class_file.write(path.to_str().unwrap()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn zero_constant_pool_count_is_an_empty_pool() {
+        let mut bytes = vec![0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 61];
+        bytes.extend([0, 0]); // constant_pool_count
+        bytes.extend([0; 16]); // flags, classes and empty member tables
+        let class_file = parse(bytes);
+        assert_eq!(class_file.constant_pool_count, 0);
+        assert!(class_file.constant_pool.is_empty());
+    }
 }
@@ -1,3 +1,5 @@
+use crate::mutf8;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Reader {
     pub data: Vec<u8>,
@@ -9,10 +11,11 @@ impl Reader {
         Reader { data, cursor: 0 }
     }
 
+    /// Reads `length` bytes of modified UTF-8, as in a `CONSTANT_Utf8` entry.
     pub fn read_string(&mut self, length: usize) -> String {
         let slice = &self.data[self.cursor..self.cursor + length];
         self.cursor += length;
-        String::from_utf8(slice.to_vec()).unwrap()
+        mutf8::decode(slice)
     }
 
     pub fn read_bytes(&mut self, length: usize) -> Vec<u8> {
@@ -0,0 +1,769 @@
+use std::fmt;
+
+use crate::descriptor::internal_to_binary_name;
+
+/// A type as written in a `Signature` attribute, where classes can have type
+/// arguments and type variables can be used.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeSignature {
+    Byte,
+    Char,
+    Double,
+    Float,
+    Int,
+    Long,
+    Short,
+    Boolean,
+    Class(ClassTypeSignature),
+    /// A type variable such as `T`.
+    TypeVariable(String),
+    Array(Box<TypeSignature>),
+    /// Only valid as the return type of a method.
+    Void,
+}
+
+/// A class type with its type arguments, such as
+/// `java.util.Map<K, V>.Entry<K, V>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassTypeSignature {
+    /// The internal name of the outermost class, such as `java/util/Map`.
+    pub name: String,
+    pub type_arguments: Vec<TypeArgument>,
+    /// The inner classes, from the outermost one, such as `Entry`.
+    pub inner: Vec<InnerClassTypeSignature>,
+}
+
+/// An inner class of a `ClassTypeSignature`, named without its outer class.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InnerClassTypeSignature {
+    pub name: String,
+    pub type_arguments: Vec<TypeArgument>,
+}
+
+/// A type argument of a class type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeArgument {
+    /// `?`
+    Wildcard,
+    /// `? extends T`
+    Extends(TypeSignature),
+    /// `? super T`
+    Super(TypeSignature),
+    Exact(TypeSignature),
+}
+
+/// A type parameter of a generic class or method, such as
+/// `T extends Number & Comparable<T>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeParameter {
+    pub name: String,
+    /// The class or type variable bound. Parameters bounded only by
+    /// interfaces have none.
+    pub class_bound: Option<TypeSignature>,
+    pub interface_bounds: Vec<TypeSignature>,
+}
+
+/// The `Signature` attribute of a class.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub super_class: ClassTypeSignature,
+    pub interfaces: Vec<ClassTypeSignature>,
+}
+
+/// The `Signature` attribute of a method.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub parameters: Vec<TypeSignature>,
+    pub return_type: TypeSignature,
+    /// The thrown classes or type variables. Left empty when the method's
+    /// `Exceptions` attribute has no generic types.
+    pub throws: Vec<TypeSignature>,
+}
+
+/// An error found while parsing a signature.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignatureError {
+    /// The signature ends in the middle of a type.
+    UnexpectedEnd,
+    /// An unexpected character at the given byte offset.
+    InvalidCharacter { character: char, position: usize },
+    /// Characters following a complete signature.
+    TrailingCharacters(String),
+    /// An empty class, package or type variable name at the given byte offset.
+    EmptyIdentifier { position: usize },
+    /// A name at the given byte offset that runs to the end of the signature,
+    /// such as a class type missing its `;`.
+    UnterminatedIdentifier { position: usize },
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::UnexpectedEnd => write!(f, "unexpected end of signature"),
+            SignatureError::InvalidCharacter {
+                character,
+                position,
+            } => write!(
+                f,
+                "invalid character {:?} at offset {}",
+                character, position
+            ),
+            SignatureError::TrailingCharacters(rest) => {
+                write!(f, "unexpected {:?} after signature", rest)
+            }
+            SignatureError::EmptyIdentifier { position } => {
+                write!(f, "empty identifier at offset {}", position)
+            }
+            SignatureError::UnterminatedIdentifier { position } => {
+                write!(f, "unterminated identifier at offset {}", position)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+/// A cursor over the characters of a signature.
+struct SignatureParser<'a> {
+    signature: &'a str,
+    position: usize,
+}
+
+impl<'a> SignatureParser<'a> {
+    fn new(signature: &'a str) -> Self {
+        SignatureParser {
+            signature,
+            position: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.signature[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Result<char, SignatureError> {
+        let c = self.peek().ok_or(SignatureError::UnexpectedEnd)?;
+        self.position += c.len_utf8();
+        Ok(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SignatureError> {
+        let position = self.position;
+        match self.next()? {
+            c if c == expected => Ok(()),
+            character => Err(SignatureError::InvalidCharacter {
+                character,
+                position,
+            }),
+        }
+    }
+
+    /// `extra` holds delimiters allowed in the identifier, such as `/`.
+    fn identifier(&mut self, extra: &[char]) -> Result<String, SignatureError> {
+        let position = self.position;
+        let rest = &self.signature[position..];
+        let end = rest
+            .find(|c: char| {
+                matches!(c, '.' | ';' | '[' | '/' | '<' | '>' | ':') && !extra.contains(&c)
+            })
+            .ok_or(SignatureError::UnterminatedIdentifier { position })?;
+        let identifier = &rest[..end];
+        if identifier.is_empty() || identifier.split('/').any(str::is_empty) {
+            return Err(SignatureError::EmptyIdentifier { position });
+        }
+        self.position += end;
+        Ok(identifier.to_string())
+    }
+
+    fn type_parameters(&mut self) -> Result<Vec<TypeParameter>, SignatureError> {
+        let mut type_parameters = vec![];
+        if self.peek() != Some('<') {
+            return Ok(type_parameters);
+        }
+        self.position += 1;
+        loop {
+            let name = self.identifier(&[])?;
+            self.expect(':')?;
+            let class_bound = match self.peek() {
+                Some(':') => None,
+                _ => Some(self.reference_type()?),
+            };
+            let mut interface_bounds = vec![];
+            while self.peek() == Some(':') {
+                self.position += 1;
+                interface_bounds.push(self.reference_type()?);
+            }
+            type_parameters.push(TypeParameter {
+                name,
+                class_bound,
+                interface_bounds,
+            });
+            if self.peek() == Some('>') {
+                self.position += 1;
+                return Ok(type_parameters);
+            }
+        }
+    }
+
+    fn type_arguments(&mut self) -> Result<Vec<TypeArgument>, SignatureError> {
+        let mut type_arguments = vec![];
+        if self.peek() != Some('<') {
+            return Ok(type_arguments);
+        }
+        self.position += 1;
+        loop {
+            let argument = match self.peek() {
+                Some('*') => {
+                    self.position += 1;
+                    TypeArgument::Wildcard
+                }
+                Some('+') => {
+                    self.position += 1;
+                    TypeArgument::Extends(self.reference_type()?)
+                }
+                Some('-') => {
+                    self.position += 1;
+                    TypeArgument::Super(self.reference_type()?)
+                }
+                _ => TypeArgument::Exact(self.reference_type()?),
+            };
+            type_arguments.push(argument);
+            if self.peek() == Some('>') {
+                self.position += 1;
+                return Ok(type_arguments);
+            }
+        }
+    }
+
+    fn class_type(&mut self) -> Result<ClassTypeSignature, SignatureError> {
+        self.expect('L')?;
+        let name = self.identifier(&['/'])?;
+        let type_arguments = self.type_arguments()?;
+        let mut inner = vec![];
+        while self.peek() == Some('.') {
+            self.position += 1;
+            let name = self.identifier(&[])?;
+            let type_arguments = self.type_arguments()?;
+            inner.push(InnerClassTypeSignature {
+                name,
+                type_arguments,
+            });
+        }
+        self.expect(';')?;
+        Ok(ClassTypeSignature {
+            name,
+            type_arguments,
+            inner,
+        })
+    }
+
+    fn type_variable(&mut self) -> Result<TypeSignature, SignatureError> {
+        self.expect('T')?;
+        let name = self.identifier(&[])?;
+        self.expect(';')?;
+        Ok(TypeSignature::TypeVariable(name))
+    }
+
+    /// Parses a class, type variable or array type.
+    fn reference_type(&mut self) -> Result<TypeSignature, SignatureError> {
+        let position = self.position;
+        match self.peek() {
+            Some('L') => Ok(TypeSignature::Class(self.class_type()?)),
+            Some('T') => self.type_variable(),
+            Some('[') => self.java_type(),
+            Some(character) => Err(SignatureError::InvalidCharacter {
+                character,
+                position,
+            }),
+            None => Err(SignatureError::UnexpectedEnd),
+        }
+    }
+
+    /// Parses any type but `void`.
+    fn java_type(&mut self) -> Result<TypeSignature, SignatureError> {
+        let mut dimensions = 0;
+        while self.peek() == Some('[') {
+            self.position += 1;
+            dimensions += 1;
+        }
+        let position = self.position;
+        let mut signature = match self.peek().ok_or(SignatureError::UnexpectedEnd)? {
+            'L' | 'T' => self.reference_type()?,
+            character => {
+                self.position += 1;
+                match character {
+                    'B' => TypeSignature::Byte,
+                    'C' => TypeSignature::Char,
+                    'D' => TypeSignature::Double,
+                    'F' => TypeSignature::Float,
+                    'I' => TypeSignature::Int,
+                    'J' => TypeSignature::Long,
+                    'S' => TypeSignature::Short,
+                    'Z' => TypeSignature::Boolean,
+                    character => {
+                        return Err(SignatureError::InvalidCharacter {
+                            character,
+                            position,
+                        })
+                    }
+                }
+            }
+        };
+        for _ in 0..dimensions {
+            signature = TypeSignature::Array(Box::new(signature));
+        }
+        Ok(signature)
+    }
+
+    fn finish(&self) -> Result<(), SignatureError> {
+        match &self.signature[self.position..] {
+            "" => Ok(()),
+            rest => Err(SignatureError::TrailingCharacters(rest.to_string())),
+        }
+    }
+}
+
+impl TypeSignature {
+    /// Parses the `Signature` attribute of a field, such as
+    /// `Ljava/util/List<Ljava/lang/String;>;`.
+    pub fn parse(signature: &str) -> Result<TypeSignature, SignatureError> {
+        let mut parser = SignatureParser::new(signature);
+        let field = parser.java_type()?;
+        parser.finish()?;
+        Ok(field)
+    }
+
+    pub fn serialize(&self) -> String {
+        match self {
+            TypeSignature::Byte => "B".to_string(),
+            TypeSignature::Char => "C".to_string(),
+            TypeSignature::Double => "D".to_string(),
+            TypeSignature::Float => "F".to_string(),
+            TypeSignature::Int => "I".to_string(),
+            TypeSignature::Long => "J".to_string(),
+            TypeSignature::Short => "S".to_string(),
+            TypeSignature::Boolean => "Z".to_string(),
+            TypeSignature::Class(class) => class.serialize(),
+            TypeSignature::TypeVariable(name) => format!("T{};", name),
+            TypeSignature::Array(inner) => format!("[{}", inner.serialize()),
+            TypeSignature::Void => "V".to_string(),
+        }
+    }
+}
+
+impl ClassTypeSignature {
+    /// Returns the internal name of the class, such as `java/util/Map$Entry`.
+    pub fn internal_name(&self) -> String {
+        let mut name = self.name.clone();
+        for inner in &self.inner {
+            name.push('$');
+            name.push_str(&inner.name);
+        }
+        name
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut s = format!("L{}", self.name);
+        serialize_type_arguments(&mut s, &self.type_arguments);
+        for inner in &self.inner {
+            s.push('.');
+            s.push_str(&inner.name);
+            serialize_type_arguments(&mut s, &inner.type_arguments);
+        }
+        s.push(';');
+        s
+    }
+}
+
+impl TypeArgument {
+    pub fn serialize(&self) -> String {
+        match self {
+            TypeArgument::Wildcard => "*".to_string(),
+            TypeArgument::Extends(bound) => format!("+{}", bound.serialize()),
+            TypeArgument::Super(bound) => format!("-{}", bound.serialize()),
+            TypeArgument::Exact(argument) => argument.serialize(),
+        }
+    }
+}
+
+impl TypeParameter {
+    pub fn serialize(&self) -> String {
+        let mut s = format!("{}:", self.name);
+        if let Some(bound) = &self.class_bound {
+            s.push_str(&bound.serialize());
+        }
+        for bound in &self.interface_bounds {
+            s.push(':');
+            s.push_str(&bound.serialize());
+        }
+        s
+    }
+}
+
+impl ClassSignature {
+    /// Parses the `Signature` attribute of a class, such as
+    /// `<T:Ljava/lang/Object;>Ljava/lang/Object;Ljava/lang/Comparable<TT;>;`.
+    pub fn parse(signature: &str) -> Result<ClassSignature, SignatureError> {
+        let mut parser = SignatureParser::new(signature);
+        let type_parameters = parser.type_parameters()?;
+        let super_class = parser.class_type()?;
+        let mut interfaces = vec![];
+        while parser.peek().is_some() {
+            interfaces.push(parser.class_type()?);
+        }
+        Ok(ClassSignature {
+            type_parameters,
+            super_class,
+            interfaces,
+        })
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut s = String::new();
+        serialize_type_parameters(&mut s, &self.type_parameters);
+        s.push_str(&self.super_class.serialize());
+        for interface in &self.interfaces {
+            s.push_str(&interface.serialize());
+        }
+        s
+    }
+}
+
+impl MethodSignature {
+    /// Parses the `Signature` attribute of a method, such as
+    /// `<T:Ljava/lang/Object;>(Ljava/util/List<+TT;>;)TT;^Ljava/io/IOException;`.
+    pub fn parse(signature: &str) -> Result<MethodSignature, SignatureError> {
+        let mut parser = SignatureParser::new(signature);
+        let type_parameters = parser.type_parameters()?;
+        parser.expect('(')?;
+        let mut parameters = vec![];
+        while parser.peek() != Some(')') {
+            parameters.push(parser.java_type()?);
+        }
+        parser.position += 1;
+        let return_type = match parser.peek() {
+            Some('V') => {
+                parser.position += 1;
+                TypeSignature::Void
+            }
+            _ => parser.java_type()?,
+        };
+        let mut throws = vec![];
+        while parser.peek().is_some() {
+            parser.expect('^')?;
+            throws.push(match parser.peek() {
+                Some('T') => parser.type_variable()?,
+                _ => TypeSignature::Class(parser.class_type()?),
+            });
+        }
+        Ok(MethodSignature {
+            type_parameters,
+            parameters,
+            return_type,
+            throws,
+        })
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut s = String::new();
+        serialize_type_parameters(&mut s, &self.type_parameters);
+        s.push('(');
+        for parameter in &self.parameters {
+            s.push_str(&parameter.serialize());
+        }
+        s.push(')');
+        s.push_str(&self.return_type.serialize());
+        for thrown in &self.throws {
+            s.push('^');
+            s.push_str(&thrown.serialize());
+        }
+        s
+    }
+}
+
+fn serialize_type_arguments(s: &mut String, type_arguments: &[TypeArgument]) {
+    if type_arguments.is_empty() {
+        return;
+    }
+    s.push('<');
+    for argument in type_arguments {
+        s.push_str(&argument.serialize());
+    }
+    s.push('>');
+}
+
+fn serialize_type_parameters(s: &mut String, type_parameters: &[TypeParameter]) {
+    if type_parameters.is_empty() {
+        return;
+    }
+    s.push('<');
+    for parameter in type_parameters {
+        s.push_str(&parameter.serialize());
+    }
+    s.push('>');
+}
+
+/// Writes `items` separated by `separator`.
+fn write_list<T: fmt::Display>(
+    f: &mut fmt::Formatter<'_>,
+    items: &[T],
+    separator: &str,
+) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            f.write_str(separator)?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+fn write_type_arguments(
+    f: &mut fmt::Formatter<'_>,
+    type_arguments: &[TypeArgument],
+) -> fmt::Result {
+    if type_arguments.is_empty() {
+        return Ok(());
+    }
+    f.write_str("<")?;
+    write_list(f, type_arguments, ", ")?;
+    f.write_str(">")
+}
+
+fn write_type_parameters(
+    f: &mut fmt::Formatter<'_>,
+    type_parameters: &[TypeParameter],
+) -> fmt::Result {
+    if type_parameters.is_empty() {
+        return Ok(());
+    }
+    f.write_str("<")?;
+    write_list(f, type_parameters, ", ")?;
+    f.write_str(">")
+}
+
+/// Displays the type as in Java source, such as
+/// `java.util.Map<java.lang.String, java.util.List<? extends T>>[]`.
+impl fmt::Display for TypeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeSignature::Byte => f.write_str("byte"),
+            TypeSignature::Char => f.write_str("char"),
+            TypeSignature::Double => f.write_str("double"),
+            TypeSignature::Float => f.write_str("float"),
+            TypeSignature::Int => f.write_str("int"),
+            TypeSignature::Long => f.write_str("long"),
+            TypeSignature::Short => f.write_str("short"),
+            TypeSignature::Boolean => f.write_str("boolean"),
+            TypeSignature::Class(class) => write!(f, "{}", class),
+            TypeSignature::TypeVariable(name) => f.write_str(name),
+            TypeSignature::Array(inner) => write!(f, "{}[]", inner),
+            TypeSignature::Void => f.write_str("void"),
+        }
+    }
+}
+
+impl fmt::Display for ClassTypeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&internal_to_binary_name(&self.name))?;
+        write_type_arguments(f, &self.type_arguments)?;
+        for inner in &self.inner {
+            write!(f, ".{}", inner.name)?;
+            write_type_arguments(f, &inner.type_arguments)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TypeArgument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeArgument::Wildcard => f.write_str("?"),
+            TypeArgument::Extends(bound) => write!(f, "? extends {}", bound),
+            TypeArgument::Super(bound) => write!(f, "? super {}", bound),
+            TypeArgument::Exact(argument) => write!(f, "{}", argument),
+        }
+    }
+}
+
+/// Displays the parameter as in Java source. A lone `java.lang.Object` bound
+/// is left out, as javac writes it for unbounded parameters.
+impl fmt::Display for TypeParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        let bounds: Vec<&TypeSignature> = self
+            .class_bound
+            .iter()
+            .chain(&self.interface_bounds)
+            .collect();
+        let object = matches!(
+            bounds.as_slice(),
+            [TypeSignature::Class(class)] if class.name == "java/lang/Object" && class.inner.is_empty()
+        );
+        if bounds.is_empty() || object {
+            return Ok(());
+        }
+        f.write_str(" extends ")?;
+        write_list(f, &bounds, " & ")
+    }
+}
+
+/// Displays the class header as in Java source, without the class name, such
+/// as `<T> extends java.lang.Object implements java.lang.Comparable<T>`.
+impl fmt::Display for ClassSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.type_parameters.is_empty() {
+            write_type_parameters(f, &self.type_parameters)?;
+            f.write_str(" ")?;
+        }
+        write!(f, "extends {}", self.super_class)?;
+        if !self.interfaces.is_empty() {
+            f.write_str(" implements ")?;
+            write_list(f, &self.interfaces, ", ")?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays the method as in Java source, without the method name, such as
+/// `<T> T(java.util.List<? extends T>) throws java.io.IOException`.
+impl fmt::Display for MethodSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.type_parameters.is_empty() {
+            write_type_parameters(f, &self.type_parameters)?;
+            f.write_str(" ")?;
+        }
+        write!(f, "{}(", self.return_type)?;
+        write_list(f, &self.parameters, ", ")?;
+        f.write_str(")")?;
+        if !self.throws.is_empty() {
+            f.write_str(" throws ")?;
+            write_list(f, &self.throws, ", ")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn type_signatures_round_trip() {
+        for signature in [
+            "I",
+            "TT;",
+            "[TT;",
+            "Ljava/util/List<Ljava/lang/String;>;",
+            "Ljava/util/Map<TK;+Ljava/util/List<-TV;>;>;",
+            "Ljava/util/Map<TK;TV;>.Entry<TK;TV;>;",
+            "Ljava/lang/Class<*>;",
+        ] {
+            let parsed = TypeSignature::parse(signature).unwrap();
+            assert_eq!(parsed.serialize(), signature);
+        }
+    }
+
+    #[test]
+    fn class_signatures_round_trip() {
+        for signature in [
+            "<T:Ljava/lang/Object;>Ljava/lang/Object;Ljava/lang/Comparable<TT;>;",
+            "<E:Ljava/lang/Enum<TE;>;>Ljava/lang/Object;",
+            "<T::Ljava/lang/Runnable;:Ljava/io/Serializable;>Ljava/lang/Object;",
+        ] {
+            let parsed = ClassSignature::parse(signature).unwrap();
+            assert_eq!(parsed.serialize(), signature);
+        }
+    }
+
+    #[test]
+    fn method_signatures_round_trip() {
+        for signature in [
+            "<T:Ljava/lang/Object;>(Ljava/util/List<+TT;>;)TT;",
+            "(I[Ljava/lang/String;)V",
+            "<X:Ljava/lang/Exception;>()V^TX;^Ljava/io/IOException;",
+        ] {
+            let parsed = MethodSignature::parse(signature).unwrap();
+            assert_eq!(parsed.serialize(), signature);
+        }
+    }
+
+    #[test]
+    fn interface_bounds_have_no_class_bound() {
+        let parsed = ClassSignature::parse("<T::Ljava/lang/Runnable;>Ljava/lang/Object;").unwrap();
+        let parameter = &parsed.type_parameters[0];
+        assert_eq!(parameter.class_bound, None);
+        assert_eq!(parameter.interface_bounds.len(), 1);
+    }
+
+    #[test]
+    fn displays_as_java_source() {
+        let signature = "<T:Ljava/lang/Object;>(Ljava/util/List<+TT;>;)TT;^Ljava/io/IOException;";
+        assert_eq!(
+            MethodSignature::parse(signature).unwrap().to_string(),
+            "<T> T(java.util.List<? extends T>) throws java.io.IOException"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_signatures() {
+        assert_eq!(
+            TypeSignature::parse("Ljava/util/List<"),
+            Err(SignatureError::UnexpectedEnd)
+        );
+        assert_eq!(
+            TypeSignature::parse("TT;I"),
+            Err(SignatureError::TrailingCharacters("I".to_string()))
+        );
+        assert_eq!(
+            TypeSignature::parse("T;"),
+            Err(SignatureError::EmptyIdentifier { position: 1 })
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_invalid_part() {
+        assert_eq!(
+            TypeSignature::parse("Ljava/lang/String"),
+            Err(SignatureError::UnterminatedIdentifier { position: 1 })
+        );
+        assert_eq!(
+            TypeSignature::parse("Ljava/util/List<TT>;"),
+            Err(SignatureError::InvalidCharacter {
+                character: '>',
+                position: 18
+            })
+        );
+        assert_eq!(
+            TypeSignature::parse("Ljava/util/List<>;"),
+            Err(SignatureError::InvalidCharacter {
+                character: '>',
+                position: 16
+            })
+        );
+        assert_eq!(
+            ClassSignature::parse("<>Ljava/lang/Object;"),
+            Err(SignatureError::EmptyIdentifier { position: 1 })
+        );
+        assert_eq!(
+            TypeSignature::parse("Ljava/util/List<I>;"),
+            Err(SignatureError::InvalidCharacter {
+                character: 'I',
+                position: 16
+            })
+        );
+        assert_eq!(
+            MethodSignature::parse("()VLjava/io/IOException;"),
+            Err(SignatureError::InvalidCharacter {
+                character: 'L',
+                position: 3
+            })
+        );
+        assert_eq!(
+            SignatureError::UnterminatedIdentifier { position: 1 }.to_string(),
+            "unterminated identifier at offset 1"
+        );
+    }
+}
@@ -0,0 +1,435 @@
+use crate::{
+    bytecode::{
+        instr::Opcode, AttributeInfo, AttributeInfoKind, ClassFile, ExceptionTable, StackMapFrame,
+        VerificationTypeInfo,
+    },
+    constant_pool::ConstantPool,
+    frame::{analyze_with_pool, Frame, FrameError, VerificationType, THROWABLE_CLASS},
+    hierarchy::ClassHierarchy,
+};
+
+/// Computes the `StackMapTable` of every method with a `Code` attribute and
+/// updates its `max_stack` and `max_locals`.
+///
+/// Frames are recorded at every branch target and exception handler entry.
+/// Unreachable code is replaced by `nop ... athrow`, as the verifier requires a
+/// frame for it that can't be inferred.
+pub fn compute_stack_map_tables(
+    class_file: &mut ClassFile,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<(), FrameError> {
+    let methods: Vec<usize> = (0..class_file.method_info.len()).collect();
+    compute_method_stack_map_tables(class_file, &methods, hierarchy)
+}
+
+/// Like `compute_stack_map_tables`, for the methods at `methods` only.
+pub(crate) fn compute_method_stack_map_tables(
+    class_file: &mut ClassFile,
+    methods: &[usize],
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<(), FrameError> {
+    let mut constant_pool = ConstantPool::from_entries(class_file.constant_pool.clone())?;
+    for &index in methods {
+        let has_code = class_file.method_info[index]
+            .attributes
+            .iter()
+            .any(|a| matches!(a.info, AttributeInfoKind::Code { .. }));
+        if has_code {
+            compute_stack_map_table(class_file, &mut constant_pool, index, hierarchy)?;
+        }
+    }
+    class_file.constant_pool_count = constant_pool.count();
+    class_file.constant_pool = constant_pool.into_entries();
+    Ok(())
+}
+
+fn compute_stack_map_table(
+    class_file: &mut ClassFile,
+    constant_pool: &mut ConstantPool,
+    method_index: usize,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<(), FrameError> {
+    let method = &class_file.method_info[method_index];
+    let analysis = analyze_with_pool(class_file, constant_pool, method, hierarchy)?;
+    let (old_code, old_exception_table) = match code_attribute(class_file, method_index) {
+        AttributeInfoKind::Code {
+            code,
+            exception_table,
+            ..
+        } => (code.clone(), exception_table.clone()),
+        _ => unreachable!(),
+    };
+
+    let mut code = vec![];
+    let mut dead_ranges: Vec<(u16, u16)> = vec![];
+    let mut frames: Vec<(u16, Frame)> = vec![];
+    for (i, op) in old_code.iter().enumerate() {
+        let pc = analysis.pcs[i];
+        let end = pc + op.size(pc as usize) as u16;
+        if analysis.frames[i].is_some() {
+            code.push(op.clone());
+            continue;
+        }
+        match dead_ranges.last_mut() {
+            Some((_, dead_end)) if *dead_end == pc => {
+                *code.last_mut().unwrap() = Opcode::Nop;
+                *dead_end = end;
+            }
+            _ => {
+                dead_ranges.push((pc, end));
+                frames.push((
+                    pc,
+                    Frame {
+                        locals: vec![],
+                        stack: vec![VerificationType::Object(THROWABLE_CLASS.to_string())],
+                    },
+                ));
+            }
+        }
+        code.extend(std::iter::repeat_n(Opcode::Nop, (end - pc) as usize));
+        *code.last_mut().unwrap() = Opcode::Athrow;
+    }
+
+    let exception_table: Vec<ExceptionTable> = old_exception_table
+        .iter()
+        .flat_map(|e| remove_ranges(e, &dead_ranges))
+        .collect();
+
+    let mut targets = vec![];
+    for (i, op) in old_code.iter().enumerate() {
+        if analysis.frames[i].is_some() {
+            targets.extend(op.branch_targets(analysis.pcs[i]));
+        }
+    }
+    targets.extend(exception_table.iter().map(|e| e.handler_pc as i32));
+    for (i, frame) in analysis.frames.iter().enumerate() {
+        if let Some(frame) = frame {
+            if targets.contains(&(analysis.pcs[i] as i32)) {
+                frames.push((analysis.pcs[i], frame.clone()));
+            }
+        }
+    }
+    frames.sort_by_key(|(pc, _)| *pc);
+
+    let mut entries = vec![];
+    let mut previous_locals =
+        verification_infos(constant_pool, &compress(&analysis.initial.locals))?;
+    let mut previous_pc: Option<u16> = None;
+    for (pc, frame) in &frames {
+        let offset_delta = match previous_pc {
+            Some(previous) => pc - previous - 1,
+            None => *pc,
+        };
+        previous_pc = Some(*pc);
+        let locals = verification_infos(constant_pool, &compress(&frame.locals))?;
+        let stack = verification_infos(constant_pool, &frame.stack)?;
+        entries.push(encode_frame(offset_delta, &previous_locals, &locals, stack));
+        previous_locals = locals;
+    }
+
+    let mut stack_map_table = None;
+    if !entries.is_empty() {
+        stack_map_table = Some(AttributeInfo {
+            attribute_name_index: constant_pool.utf8("StackMapTable")?,
+            attribute_length: 0,
+            info: AttributeInfoKind::StackMapTable {
+                number_of_entries: entries.len() as u16,
+                entries,
+            },
+        });
+    }
+    let dead_stack = if dead_ranges.is_empty() { 0 } else { 1 };
+    if let AttributeInfoKind::Code {
+        max_stack,
+        max_locals,
+        code: old_code,
+        code_length,
+        exception_table_length,
+        exception_table: old_exception_table,
+        attributes_count,
+        attributes,
+    } = code_attribute(class_file, method_index)
+    {
+        *max_stack = analysis.max_stack.max(dead_stack);
+        *max_locals = analysis.max_locals;
+        *code_length = code
+            .iter()
+            .fold(0, |pc, op| pc + op.size(pc as usize) as u32);
+        *old_code = code;
+        *exception_table_length = exception_table.len() as u16;
+        *old_exception_table = exception_table;
+        attributes.retain(|a| !matches!(a.info, AttributeInfoKind::StackMapTable { .. }));
+        attributes.extend(stack_map_table);
+        *attributes_count = attributes.len() as u16;
+    }
+    Ok(())
+}
+
+fn code_attribute(class_file: &mut ClassFile, method_index: usize) -> &mut AttributeInfoKind {
+    class_file.method_info[method_index]
+        .attributes
+        .iter_mut()
+        .map(|a| &mut a.info)
+        .find(|a| matches!(a, AttributeInfoKind::Code { .. }))
+        .unwrap()
+}
+
+/// Splits an exception table entry so it no longer covers any of `ranges`.
+fn remove_ranges(entry: &ExceptionTable, ranges: &[(u16, u16)]) -> Vec<ExceptionTable> {
+    let mut pieces = vec![(entry.start_pc, entry.end_pc)];
+    for (dead_start, dead_end) in ranges {
+        pieces = pieces
+            .into_iter()
+            .flat_map(|(start, end)| [(start, end.min(*dead_start)), (start.max(*dead_end), end)])
+            .filter(|(start, end)| start < end)
+            .collect();
+    }
+    pieces
+        .into_iter()
+        .map(|(start_pc, end_pc)| ExceptionTable {
+            start_pc,
+            end_pc,
+            ..entry.clone()
+        })
+        .collect()
+}
+
+/// Converts locals to the form used by stack map frames, where `Long` and
+/// `Double` imply the following `Top`, and drops trailing `Top` entries.
+fn compress(locals: &[VerificationType]) -> Vec<VerificationType> {
+    let mut compressed = vec![];
+    let mut i = 0;
+    while i < locals.len() {
+        compressed.push(locals[i].clone());
+        i += if locals[i].is_wide() { 2 } else { 1 };
+    }
+    while compressed.last() == Some(&VerificationType::Top) {
+        compressed.pop();
+    }
+    compressed
+}
+
+fn verification_infos(
+    constant_pool: &mut ConstantPool,
+    types: &[VerificationType],
+) -> Result<Vec<VerificationTypeInfo>, FrameError> {
+    types
+        .iter()
+        .map(|t| {
+            Ok(match t {
+                VerificationType::Top => VerificationTypeInfo::Top,
+                VerificationType::Integer => VerificationTypeInfo::Integer,
+                VerificationType::Float => VerificationTypeInfo::Float,
+                VerificationType::Long => VerificationTypeInfo::Long,
+                VerificationType::Double => VerificationTypeInfo::Double,
+                VerificationType::Null => VerificationTypeInfo::Null,
+                VerificationType::UninitializedThis => VerificationTypeInfo::UninitializedThis,
+                VerificationType::Object(name) => VerificationTypeInfo::Object {
+                    cpool_index: constant_pool.class(name)?,
+                },
+                VerificationType::Uninitialized(offset) => {
+                    VerificationTypeInfo::Uninitialized { offset: *offset }
+                }
+            })
+        })
+        .collect()
+}
+
+/// Picks the most compact frame kind describing `locals` and `stack` relative
+/// to the locals of the previous frame.
+fn encode_frame(
+    offset_delta: u16,
+    previous: &[VerificationTypeInfo],
+    locals: &[VerificationTypeInfo],
+    mut stack: Vec<VerificationTypeInfo>,
+) -> StackMapFrame {
+    if locals == previous && stack.is_empty() {
+        if offset_delta < 64 {
+            return StackMapFrame::SameFrame {
+                frame_type: offset_delta as u8,
+            };
+        }
+        return StackMapFrame::SameFrameExtended {
+            frame_type: 251,
+            offset_delta,
+        };
+    }
+    if locals == previous && stack.len() == 1 {
+        let stack = stack.remove(0);
+        if offset_delta < 64 {
+            return StackMapFrame::SameLocals1StackItemFrame {
+                frame_type: 64 + offset_delta as u8,
+                stack,
+            };
+        }
+        return StackMapFrame::SameLocals1StackItemFrameExtended {
+            frame_type: 247,
+            offset_delta,
+            stack,
+        };
+    }
+    if stack.is_empty() && locals.len() < previous.len() && previous.starts_with(locals) {
+        let chopped = previous.len() - locals.len();
+        if chopped <= 3 {
+            return StackMapFrame::ChopFrame {
+                frame_type: 251 - chopped as u8,
+                offset_delta,
+            };
+        }
+    }
+    if stack.is_empty() && locals.len() > previous.len() && locals.starts_with(previous) {
+        let appended = locals.len() - previous.len();
+        if appended <= 3 {
+            return StackMapFrame::AppendFrame {
+                frame_type: 251 + appended as u8,
+                offset_delta,
+                locals: locals[previous.len()..].to_vec(),
+            };
+        }
+    }
+    StackMapFrame::FullFrame {
+        frame_type: 255,
+        offset_delta,
+        locals: locals.to_vec(),
+        stack,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bytecode::ToJvmBytecode, hierarchy::SimpleHierarchy, parser::Parser, reader::Reader,
+    };
+
+    /// `testdata/Frames.java` compiled by javac 17 with `-g:none`.
+    const FRAMES_CLASS: &[u8] = include_bytes!("../testdata/Frames.class");
+
+    fn parse(bytes: &[u8]) -> ClassFile {
+        Parser {
+            reader: Reader::new(bytes.to_vec()),
+        }
+        .parse()
+    }
+
+    fn remove_stack_map_tables(class_file: &mut ClassFile) {
+        for method in &mut class_file.method_info {
+            for attribute in &mut method.attributes {
+                if let AttributeInfoKind::Code {
+                    attributes,
+                    attributes_count,
+                    ..
+                } = &mut attribute.info
+                {
+                    attributes
+                        .retain(|a| !matches!(a.info, AttributeInfoKind::StackMapTable { .. }));
+                    *attributes_count = attributes.len() as u16;
+                }
+            }
+        }
+    }
+
+    fn stack_map_table<'a>(class_file: &'a ClassFile, name: &str) -> &'a [StackMapFrame] {
+        let method = class_file
+            .method_info
+            .iter()
+            .find(|m| class_file.get_utf8(m.name_index) == Some(name))
+            .unwrap();
+        method
+            .attributes
+            .iter()
+            .find_map(|a| match &a.info {
+                AttributeInfoKind::Code { attributes, .. } => {
+                    attributes.iter().find_map(|a| match &a.info {
+                        AttributeInfoKind::StackMapTable { entries, .. } => Some(&entries[..]),
+                        _ => None,
+                    })
+                }
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn recomputed_tables_match_javac() {
+        let mut class_file = parse(FRAMES_CLASS);
+        remove_stack_map_tables(&mut class_file);
+        compute_stack_map_tables(&mut class_file, &SimpleHierarchy::new()).unwrap();
+        assert_eq!(class_file.to_jvm_bytecode(), FRAMES_CLASS);
+    }
+
+    #[test]
+    fn frames_match_javac() {
+        let mut class_file = parse(FRAMES_CLASS);
+        remove_stack_map_tables(&mut class_file);
+        compute_stack_map_tables(&mut class_file, &SimpleHierarchy::new()).unwrap();
+        let object = |name: &str| {
+            let cpool_index = (1..class_file.constant_pool_count)
+                .find(|i| class_file.get_class_name(*i) == Some(name))
+                .unwrap();
+            VerificationTypeInfo::Object { cpool_index }
+        };
+        // `a > b ? a : b` joins with an int on the stack.
+        assert_eq!(
+            stack_map_table(&class_file, "max"),
+            [
+                StackMapFrame::SameFrame { frame_type: 9 },
+                StackMapFrame::SameLocals1StackItemFrame {
+                    frame_type: 64,
+                    stack: VerificationTypeInfo::Integer,
+                },
+            ]
+        );
+        // The loop head declares `steps`, which stays live after the loop.
+        assert_eq!(
+            stack_map_table(&class_file, "countDown"),
+            [
+                StackMapFrame::AppendFrame {
+                    frame_type: 252,
+                    offset_delta: 2,
+                    locals: vec![VerificationTypeInfo::Integer],
+                },
+                StackMapFrame::SameFrame { frame_type: 12 },
+            ]
+        );
+        // `result` is assigned a `String` in both branches of the `if`.
+        assert_eq!(
+            stack_map_table(&class_file, "describe"),
+            [
+                StackMapFrame::SameFrame { frame_type: 10 },
+                StackMapFrame::AppendFrame {
+                    frame_type: 252,
+                    offset_delta: 4,
+                    locals: vec![object("java/lang/String")],
+                },
+            ]
+        );
+        // The handler starts with the caught exception on the stack.
+        assert_eq!(
+            stack_map_table(&class_file, "parse"),
+            [StackMapFrame::SameLocals1StackItemFrame {
+                frame_type: 69,
+                stack: object("java/lang/NumberFormatException"),
+            }]
+        );
+        // Every switch target gets a frame, the first one after the padding.
+        assert_eq!(
+            stack_map_table(&class_file, "dense"),
+            [
+                StackMapFrame::SameFrame { frame_type: 28 },
+                StackMapFrame::SameFrame { frame_type: 2 },
+                StackMapFrame::SameFrame { frame_type: 2 },
+                StackMapFrame::SameFrame { frame_type: 2 },
+            ]
+        );
+        // `double` locals take two slots but are appended as one type.
+        assert_eq!(
+            stack_map_table(&class_file, "scale"),
+            [StackMapFrame::AppendFrame {
+                frame_type: 252,
+                offset_delta: 15,
+                locals: vec![VerificationTypeInfo::Double],
+            }]
+        );
+    }
+}
@@ -0,0 +1,57 @@
+public class Frames {
+    static int max(int a, int b) {
+        return a > b ? a : b;
+    }
+
+    static int countDown(int n) {
+        int steps = 0;
+        while (n > 0) {
+            n--;
+            steps++;
+        }
+        return steps;
+    }
+
+    static String describe(Object value) {
+        String result;
+        if (value == null) {
+            result = "null";
+        } else {
+            result = value.toString();
+        }
+        return result;
+    }
+
+    static int parse(String text) {
+        try {
+            return Integer.parseInt(text);
+        } catch (NumberFormatException e) {
+            return -1;
+        }
+    }
+
+    static String dense(int day) {
+        switch (day) {
+            case 1: return "one";
+            case 2: return "two";
+            case 3: return "three";
+            default: return "many";
+        }
+    }
+
+    static int sparse(int code) {
+        switch (code) {
+            case 10: return 1;
+            case 1000: return 2;
+            default: return 0;
+        }
+    }
+
+    double scale(double factor, boolean twice) {
+        double result = factor;
+        if (twice) {
+            result *= 2;
+        }
+        return result;
+    }
+}